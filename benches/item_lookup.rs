@@ -0,0 +1,152 @@
+//! Scan to line latency with a large catalogue
+//! Run with `cargo bench --bench item_lookup [item count]`, 100,000 items by default
+//! The app is only a binary, so the modules it needs are pulled in by path
+
+#![allow(dead_code)]
+
+#[path = "../src/audit_log.rs"]
+mod audit_log;
+#[path = "../src/data_error.rs"]
+mod data_error;
+#[path = "../src/item.rs"]
+mod item;
+#[path = "../src/item_db.rs"]
+mod item_db;
+#[path = "../src/sales_journal.rs"]
+mod sales_journal;
+#[path = "../src/transaction.rs"]
+mod transaction;
+#[path = "../src/utils.rs"]
+mod utils;
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use audit_log::AuditLog;
+use item::Item;
+use item_db::{ItemDB, MovementReason};
+use transaction::Transaction;
+
+/// How many lookups each measurement is made from
+const SAMPLES: usize = 10_000;
+/// Lines in a basket before it's started over, so adding a line stays realistic
+const BASKET_SIZE: usize = 50;
+/// Made up words for item names, so name searches match a realistic share of items
+const WORDS: [&str; 12] = [
+    "Organic", "Milk", "Bread", "Apple", "Cheddar", "Large", "Free", "Range", "Eggs", "Juice",
+    "Sliced", "Pack",
+];
+
+fn main() {
+    let count = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(100_000);
+
+    let started = Instant::now();
+    let mut item_db = ItemDB::new(catalogue(count));
+    println!("Indexed {} items in {:?}", count, started.elapsed());
+
+    // spread the scanned barcodes over the whole catalogue
+    let barcodes = (0..SAMPLES)
+        .map(|sample| barcode(sample * 7919 % count))
+        .collect::<Vec<_>>();
+
+    let mut transaction = Transaction::default();
+    report(
+        "scan to line",
+        time_each(&barcodes, |barcode| {
+            if transaction.items.len() == BASKET_SIZE {
+                transaction = Transaction::default();
+            }
+            let item = item_db.get_item(barcode).expect("Item is in the catalogue");
+            transaction.add_item(item);
+        }),
+    );
+
+    report(
+        "scan to line, linear scan",
+        time_each(&barcodes[..SAMPLES / 10], |barcode| {
+            black_box(item_db.items().iter().find(|item| item.barcode == barcode));
+        }),
+    );
+
+    // stock changes are written to the audit log, like in the app
+    let audit_path = std::env::temp_dir().join("item_lookup_audit_log.jsonl");
+    let audit_path = audit_path.to_string_lossy();
+    let _ = std::fs::remove_file(&*audit_path);
+    item_db.audit_log = AuditLog::new(&audit_path);
+    report(
+        "stock update",
+        time_each(&barcodes, |barcode| {
+            item_db
+                .move_stock(barcode, -1, MovementReason::Sale)
+                .expect("Item is in the catalogue");
+        }),
+    );
+
+    let _ = std::fs::remove_file(&*audit_path);
+
+    let queries = ["milk", "free range eggs", "chedd", "organic apple juice"]
+        .into_iter()
+        .cycle()
+        .take(SAMPLES / 10)
+        .collect::<Vec<_>>();
+    report(
+        "name search",
+        time_each(&queries, |query| {
+            black_box(item_db.search(query));
+        }),
+    );
+}
+
+/// Items with unique barcodes and names made from `WORDS`
+fn catalogue(count: usize) -> Vec<Item> {
+    (0..count)
+        .map(|index| Item {
+            barcode: barcode(index),
+            name: format!(
+                "{} {} {}",
+                WORDS[index % WORDS.len()],
+                WORDS[index / WORDS.len() % WORDS.len()],
+                index
+            ),
+            price: 100 + (index % 1000) as u32,
+            amount_in_stock: 1_000_000,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// EAN-13 sized barcode for the item at an index
+fn barcode(index: usize) -> u64 {
+    5_000_000_000_000 + index as u64
+}
+
+/// Runs the function once for each input, timing each run on its own
+fn time_each<T: Copy>(inputs: &[T], mut function: impl FnMut(T)) -> Vec<Duration> {
+    let mut times = inputs
+        .iter()
+        .map(|&input| {
+            let started = Instant::now();
+            function(input);
+            started.elapsed()
+        })
+        .collect::<Vec<_>>();
+    times.sort();
+    times
+}
+
+/// Prints the median and 99th percentile of sorted times
+fn report(name: &str, times: Vec<Duration>) {
+    let percentile = |percent: usize| times[(times.len() - 1) * percent / 100];
+    println!(
+        "{:<28} median {:>10?}  p99 {:>10?}  ({} runs)",
+        name,
+        percentile(50),
+        percentile(99),
+        times.len()
+    );
+}
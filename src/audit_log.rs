@@ -0,0 +1,215 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, fs::OpenOptions, io::Write};
+
+use crate::{
+    data_error::DataError,
+    item::Item,
+    storage::load_jsonl,
+    utils::{format_price, format_weight},
+};
+
+/// What happened to an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditKind {
+    Created,
+    Edited,
+    Deleted,
+    StockAdjusted,
+}
+
+impl Display for AuditKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AuditKind::Created => "Created",
+            AuditKind::Edited => "Edited",
+            AuditKind::Deleted => "Deleted",
+            AuditKind::StockAdjusted => "Stock adjusted",
+        })
+    }
+}
+
+/// One change to one field of an item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Local>,
+    /// Who was logged in when it changed
+    pub user: String,
+    pub kind: AuditKind,
+    pub barcode: u64,
+    pub item_name: String,
+    /// Only for edits and stock adjustments
+    pub field: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// Append-only log of every change made to the item database
+/// Stored on disk as one JSON entry per line
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AuditLog {
+    /// Empty to only keep the entries in memory
+    path: String,
+    pub entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Loads the log given the path, starting empty if the file doesn't exist yet
+    pub fn load(path: &str) -> Result<Self, DataError> {
+        Ok(Self {
+            path: path.to_owned(),
+            entries: load_jsonl(path)?,
+        })
+    }
+
+    /// An empty log that appends to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records a new item
+    pub fn created(&mut self, user: &str, item: &Item) -> Result<(), DataError> {
+        self.append(vec![entry(user, AuditKind::Created, item, None)])
+    }
+
+    /// Records a deleted item
+    pub fn deleted(&mut self, user: &str, item: &Item) -> Result<(), DataError> {
+        self.append(vec![entry(user, AuditKind::Deleted, item, None)])
+    }
+
+    /// Records every field that's different between the old and new item
+    pub fn edited(&mut self, user: &str, old: &Item, new: &Item) -> Result<(), DataError> {
+        let entries = fields(old)
+            .into_iter()
+            .zip(fields(new))
+            .filter(|((_, old_value), (_, new_value))| old_value != new_value)
+            .map(|((field, old_value), (_, new_value))| {
+                entry(
+                    user,
+                    AuditKind::Edited,
+                    new,
+                    Some((field, old_value, new_value)),
+                )
+            })
+            .collect();
+        self.append(entries)
+    }
+
+    /// Records a change in stock, given the item as it was before
+    pub fn stock_adjusted(
+        &mut self,
+        user: &str,
+        item: &Item,
+        new_stock: u32,
+    ) -> Result<(), DataError> {
+        let format_stock = |stock| {
+            if item.sold_by_weight {
+                format_weight(stock)
+            } else {
+                stock.to_string()
+            }
+        };
+        self.append(vec![entry(
+            user,
+            AuditKind::StockAdjusted,
+            item,
+            Some((
+                "amount_in_stock",
+                format_stock(item.amount_in_stock),
+                format_stock(new_stock),
+            )),
+        )])
+    }
+
+    /// Writes entries to the end of the file and keeps them in memory
+    /// They're kept even if they couldn't be written, so the audit view still shows them
+    fn append(&mut self, entries: Vec<AuditEntry>) -> Result<(), DataError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in &entries {
+            lines += &serde_json::to_string(entry)
+                .map_err(|error| DataError::Serialize(error.to_string()))?;
+            lines.push('\n');
+        }
+        self.entries.extend(entries);
+        if self.path.is_empty() {
+            return Ok(());
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|error| DataError::io(&self.path, error))
+    }
+}
+
+/// Utility to make an entry for an item at the current time
+fn entry(
+    user: &str,
+    kind: AuditKind,
+    item: &Item,
+    change: Option<(&str, String, String)>,
+) -> AuditEntry {
+    let (field, old_value, new_value) = match change {
+        Some((field, old_value, new_value)) => {
+            (Some(field.to_owned()), Some(old_value), Some(new_value))
+        }
+        None => (None, None, None),
+    };
+
+    AuditEntry {
+        time: Local::now(),
+        user: user.to_owned(),
+        kind,
+        barcode: item.barcode,
+        item_name: item.name.clone(),
+        field,
+        old_value,
+        new_value,
+    }
+}
+
+/// Every field of an item with a readable value, in the same order for every item
+fn fields(item: &Item) -> Vec<(&'static str, String)> {
+    vec![
+        ("barcode", item.barcode.to_string()),
+        ("name", item.name.clone()),
+        ("price", format_price(item.price)),
+        ("image_path", item.image_path.clone().unwrap_or_default()),
+        ("amount_in_stock", item.amount_in_stock.to_string()),
+        ("tax_class", item.tax_class.to_string()),
+        ("sold_by_weight", item.sold_by_weight.to_string()),
+        ("age_restricted", item.age_restricted.to_string()),
+        (
+            "reorder_point",
+            item.reorder_point
+                .map_or(String::new(), |point| point.to_string()),
+        ),
+        ("reorder_quantity", item.reorder_quantity.to_string()),
+        ("cost_price", format_price(item.cost_price)),
+        ("category", item.category.clone()),
+        (
+            "suppliers",
+            item.suppliers
+                .iter()
+                .map(|link| {
+                    format!(
+                        "#{} {} {}",
+                        link.supplier,
+                        link.product_code,
+                        format_price(link.cost_price)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    ]
+}
@@ -0,0 +1,120 @@
+use chrono::NaiveDate;
+use iced::{
+    widget::{column, row, scrollable, text, text_input, Column},
+    Length,
+};
+
+use crate::{audit_log::AuditEntry, item_db::ItemDB, Element, Message};
+
+/// Most entries shown at once, newest first
+const MAX_ROWS: usize = 300;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AuditView {
+    input_item: String,
+    input_from: String,
+    input_to: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditMessage {
+    ItemChanged(String),
+    FromChanged(String),
+    ToChanged(String),
+}
+
+fn map(message: AuditMessage) -> Message {
+    Message::Audit(message)
+}
+
+impl AuditView {
+    pub fn view(&self, item_db: &ItemDB) -> Element {
+        let from = parse_date(&self.input_from);
+        let to = parse_date(&self.input_to);
+
+        let rows = Column::with_children(
+            item_db
+                .audit_log
+                .entries
+                .iter()
+                .rev()
+                .filter(|entry| self.matches(entry, from, to))
+                .take(MAX_ROWS)
+                .map(render_entry)
+                .collect(),
+        )
+        .spacing(5);
+
+        let date_hint = |input: &str| match parse_date(input) {
+            None if !input.is_empty() => text("Use YYYY-MM-DD"),
+            _ => text(""),
+        };
+
+        column![
+            row![
+                text_input("Item name or barcode", &self.input_item, |string| {
+                    map(AuditMessage::ItemChanged(string))
+                }),
+                text_input("From (YYYY-MM-DD)", &self.input_from, |string| {
+                    map(AuditMessage::FromChanged(string))
+                })
+                .width(Length::Units(200)),
+                text_input("To (YYYY-MM-DD)", &self.input_to, |string| {
+                    map(AuditMessage::ToChanged(string))
+                })
+                .width(Length::Units(200)),
+            ]
+            .spacing(10),
+            row![date_hint(&self.input_from), date_hint(&self.input_to)].spacing(10),
+            scrollable(rows).height(Length::Fill),
+        ]
+        .padding(20)
+        .spacing(10)
+        .into()
+    }
+
+    /// Whether an entry passes the item and date filters, both dates are inclusive
+    fn matches(&self, entry: &AuditEntry, from: Option<NaiveDate>, to: Option<NaiveDate>) -> bool {
+        let search = self.input_item.trim().to_lowercase();
+        let date = entry.time.naive_local().date();
+
+        (search.is_empty()
+            || entry.item_name.to_lowercase().contains(&search)
+            || entry.barcode.to_string().contains(&search))
+            && from.map_or(true, |from| date >= from)
+            && to.map_or(true, |to| date <= to)
+    }
+
+    pub fn update(&mut self, message: AuditMessage) {
+        match message {
+            AuditMessage::ItemChanged(v) => self.input_item = v,
+            AuditMessage::FromChanged(v) => self.input_from = v,
+            AuditMessage::ToChanged(v) => self.input_to = v,
+        }
+    }
+}
+
+/// Utility to render one line of the log
+fn render_entry<'a>(entry: &AuditEntry) -> Element<'a> {
+    let change = match (&entry.field, &entry.old_value, &entry.new_value) {
+        (Some(field), Some(old_value), Some(new_value)) => {
+            format!("{}: {} -> {}", field, old_value, new_value)
+        }
+        _ => String::new(),
+    };
+
+    row![
+        text(entry.time.format("%Y-%m-%d %H:%M:%S")).width(Length::Units(180)),
+        text(&entry.user).width(Length::Units(120)),
+        text(format!("{} ({})", entry.item_name, entry.barcode)).width(Length::FillPortion(2)),
+        text(entry.kind).width(Length::Units(130)),
+        text(change).width(Length::FillPortion(3)),
+    ]
+    .spacing(10)
+    .into()
+}
+
+/// Parses a date typed as YYYY-MM-DD
+fn parse_date(input: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").ok()
+}
@@ -0,0 +1,91 @@
+/// What is embedded in a variable measure barcode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmbeddedValue {
+    /// Price in pence
+    Price(u32),
+    /// Weight in grams
+    Weight(u32),
+}
+
+/// A variable measure EAN-13 barcode, printed in store for goods that are weighed or priced
+/// individually
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VariableMeasureBarcode {
+    /// The code of the item in the database
+    pub plu: u64,
+    pub value: EmbeddedValue,
+}
+
+/// Parses a variable measure EAN-13 barcode
+/// Layout: 2 digit prefix, 5 digit PLU, 5 digit value, check digit
+/// Prefixes 02 and 20-24 carry a price in pence, 25-29 carry a weight in grams
+pub fn parse_variable_measure(code: &str) -> Option<VariableMeasureBarcode> {
+    if code.len() != 13 || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let digits = code.bytes().map(|b| (b - b'0') as u32).collect::<Vec<_>>();
+
+    // EAN-13 check digit, weighting digits 1 and 3 alternately
+    let sum: u32 = digits[..12]
+        .iter()
+        .enumerate()
+        .map(|(index, digit)| if index % 2 == 0 { *digit } else { digit * 3 })
+        .sum();
+    if (10 - sum % 10) % 10 != digits[12] {
+        return None;
+    }
+
+    let plu = code[2..7].parse().ok()?;
+    let value = code[7..12].parse().ok()?;
+
+    let value = match &code[..2] {
+        "02" | "20" | "21" | "22" | "23" | "24" => EmbeddedValue::Price(value),
+        "25" | "26" | "27" | "28" | "29" => EmbeddedValue::Weight(value),
+        _ => return None,
+    };
+
+    Some(VariableMeasureBarcode { plu, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weight_barcode() {
+        assert_eq!(
+            parse_variable_measure("2512345012504"),
+            Some(VariableMeasureBarcode {
+                plu: 12345,
+                value: EmbeddedValue::Weight(1250),
+            })
+        );
+    }
+
+    #[test]
+    fn price_barcode() {
+        for code in ["2012345003996", "0212345003992"] {
+            assert_eq!(
+                parse_variable_measure(code),
+                Some(VariableMeasureBarcode {
+                    plu: 12345,
+                    value: EmbeddedValue::Price(399),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_check_digit() {
+        assert_eq!(parse_variable_measure("2512345012505"), None);
+    }
+
+    #[test]
+    fn not_variable_measure() {
+        // a valid EAN-13 with a prefix that doesn't carry a value
+        assert_eq!(parse_variable_measure("3012345003995"), None);
+        assert_eq!(parse_variable_measure("251234501250"), None);
+        assert_eq!(parse_variable_measure("25123450125a4"), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! A pretend card terminal for testing card payments without hardware
+//! Run with `cargo run --bin terminal_simulator [address]`, then answer each payment or reversal
+//! request with y to approve, n to decline or t to ignore it so the till times out
+
+use std::{
+    io::{stdin, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_owned());
+    let listener = TcpListener::bind(&address).expect("Couldn't listen on address");
+    println!("Terminal simulator listening on {}", address);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => handle_payment(stream),
+            Err(error) => println!("Connection failed: {}", error),
+        }
+    }
+}
+
+/// Reads one payment or reversal request and answers it however the operator says
+fn handle_payment(mut stream: TcpStream) {
+    let mut request = String::new();
+    let mut reader = BufReader::new(stream.try_clone().expect("Couldn't clone stream"));
+    if reader.read_line(&mut request).is_err() {
+        return;
+    }
+
+    let mut words = request.split_whitespace();
+    let (kind, amount, original) = match (
+        words.next(),
+        words.next().map(str::parse::<u32>),
+        words.next(),
+    ) {
+        (Some("PAY"), Some(Ok(amount)), None) => ("Payment", amount, None),
+        (Some("REVERSE"), Some(Ok(amount)), Some(original)) => ("Reversal", amount, Some(original)),
+        _ => {
+            println!("Unknown request: {}", request.trim());
+            return;
+        }
+    };
+
+    println!(
+        "{} of £{}.{:02}{} requested. Approve, decline or time out? [y/n/t]",
+        kind,
+        amount / 100,
+        amount % 100,
+        original.map_or(String::new(), |original| format!(" (auth {})", original))
+    );
+
+    let mut answer = String::new();
+    stdin().lock().read_line(&mut answer).unwrap();
+    let response = match answer.trim() {
+        "y" => format!("APPROVED {}\n", auth_code()),
+        "t" => {
+            // say nothing and wait for the till to give up
+            let _ = reader.read_to_end(&mut Vec::new());
+            println!("Till gave up");
+            return;
+        }
+        _ => "DECLINED\n".to_owned(),
+    };
+
+    if stream.write_all(response.as_bytes()).is_err() {
+        println!("Till is gone, too late to answer");
+    } else {
+        print!("Sent {}", response);
+    }
+}
+
+/// Makes up a 6 digit auth code
+fn auth_code() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos();
+    format!("{:06}", nanos % 1_000_000)
+}
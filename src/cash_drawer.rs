@@ -0,0 +1,287 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use crate::{
+    data_error::DataError,
+    sales_journal::{PaymentMethod, SaleKind, SaleRecord, SalesJournal},
+    utils::{format_signed_price, write_atomic},
+};
+
+/// Notes and coins in the drawer, in pence
+pub const DENOMINATIONS: [u32; 12] = [5000, 2000, 1000, 500, 200, 100, 50, 20, 10, 5, 2, 1];
+
+/// Cash put into or taken out of the drawer outside of a sale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashMovement {
+    /// Positive for cash in, negative for cash out
+    pub amount: i32,
+    pub reason: String,
+    pub time: DateTime<Local>,
+}
+
+/// A shift at the till, from the opening float until the Z report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shift {
+    pub started: DateTime<Local>,
+    pub opening_float: u32,
+    /// Number of the first journal record that belongs to the shift
+    pub first_record: u64,
+    pub movements: Vec<CashMovement>,
+}
+
+/// The current shift, saved to disk on every change so a restart doesn't lose it
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashDrawer {
+    #[serde(skip)]
+    path: String,
+    pub shift: Option<Shift>,
+}
+
+impl CashDrawer {
+    /// Loads the drawer from a YAML file given the path, with no shift open if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let mut drawer = match fs::read_to_string(path) {
+            Ok(string) => serde_yaml::from_str::<Self>(&string)
+                .map_err(|error| DataError::parse(path, error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(DataError::io(path, error)),
+        };
+        drawer.path = path.to_owned();
+        Ok(drawer)
+    }
+
+    /// No shift open, saved to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            shift: None,
+        }
+    }
+
+    /// Opens a shift with the float in the drawer, covering every sale recorded from now on
+    pub fn start_shift(
+        &mut self,
+        opening_float: u32,
+        sales_journal: &SalesJournal,
+    ) -> Result<(), DataError> {
+        self.shift = Some(Shift {
+            started: Local::now(),
+            opening_float,
+            first_record: sales_journal.next_number(),
+            movements: Vec::new(),
+        });
+        self.save_yaml()
+    }
+
+    /// Records cash put in (positive) or taken out (negative) of the drawer
+    pub fn move_cash(&mut self, amount: i32, reason: String) -> Result<(), DataError> {
+        if let Some(shift) = &mut self.shift {
+            shift.movements.push(CashMovement {
+                amount,
+                reason,
+                time: Local::now(),
+            });
+            self.save_yaml()?;
+        }
+        Ok(())
+    }
+
+    /// Builds a report for the open shift from the journal and the counted cash
+    pub fn report(
+        &self,
+        kind: ReportKind,
+        sales_journal: &SalesJournal,
+        counted_cash: u32,
+    ) -> Option<ShiftReport> {
+        let shift = self.shift.as_ref()?;
+        let records = sales_journal
+            .records
+            .iter()
+            .filter(|record| record.number >= shift.first_record)
+            .collect::<Vec<_>>();
+
+        let sales = records
+            .iter()
+            .filter(|record| record.kind == SaleKind::Sale)
+            .collect::<Vec<_>>();
+        let refunds = records
+            .iter()
+            .filter(|record| record.kind != SaleKind::Sale)
+            .collect::<Vec<_>>();
+        let tendered = |records: &[&&SaleRecord], method| -> i32 {
+            records.iter().map(|record| record.tendered(method)).sum()
+        };
+
+        let cash_tendered = tendered(&sales, PaymentMethod::Cash);
+        let change_given: i32 = sales.iter().map(|record| record.change as i32).sum();
+        // refund tenders are stored negative
+        let cash_refunded = -tendered(&refunds, PaymentMethod::Cash);
+        let cash_in: i32 = shift
+            .movements
+            .iter()
+            .filter(|movement| movement.amount > 0)
+            .map(|movement| movement.amount)
+            .sum();
+        let cash_out: i32 = -shift
+            .movements
+            .iter()
+            .filter(|movement| movement.amount < 0)
+            .map(|movement| movement.amount)
+            .sum::<i32>();
+
+        Some(ShiftReport {
+            kind,
+            shift_started: shift.started,
+            generated: Local::now(),
+            opening_float: shift.opening_float as i32,
+            cash_tendered,
+            change_given,
+            cash_refunded,
+            cash_in,
+            cash_out,
+            expected_cash: shift.opening_float as i32 + cash_tendered
+                - change_given
+                - cash_refunded
+                + cash_in
+                - cash_out,
+            counted_cash: counted_cash as i32,
+            card_sales: tendered(&sales, PaymentMethod::Card),
+            card_refunded: -tendered(&refunds, PaymentMethod::Card),
+            transactions: sales.len(),
+            refunds: refunds.len(),
+            refund_total: -refunds.iter().map(|record| record.total).sum::<i32>(),
+        })
+    }
+
+    /// Closes the shift, appending the Z report to a JSON lines file so it's never lost
+    /// The shift stays open if the report couldn't be written
+    pub fn end_shift(&mut self, report: &ShiftReport, reports_path: &str) -> Result<(), DataError> {
+        let line = serde_json::to_string(report)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(reports_path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|error| DataError::io(reports_path, error))?;
+
+        self.shift = None;
+        self.save_yaml()
+    }
+
+    /// Saves itself to the YAML file it was loaded from
+    fn save_yaml(&self) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(&self.path, &string).map_err(|error| DataError::io(&self.path, error))
+    }
+}
+
+/// X reports can be run any time, Z reports close the shift
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReportKind {
+    X,
+    Z,
+}
+
+impl Display for ReportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ReportKind::X => "X",
+            ReportKind::Z => "Z",
+        })
+    }
+}
+
+/// Totals for a shift, all amounts in pence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShiftReport {
+    pub kind: ReportKind,
+    pub shift_started: DateTime<Local>,
+    pub generated: DateTime<Local>,
+    pub opening_float: i32,
+    pub cash_tendered: i32,
+    pub change_given: i32,
+    pub cash_refunded: i32,
+    pub cash_in: i32,
+    pub cash_out: i32,
+    pub expected_cash: i32,
+    pub counted_cash: i32,
+    pub card_sales: i32,
+    pub card_refunded: i32,
+    /// Number of sales, not counting refunds
+    pub transactions: usize,
+    pub refunds: usize,
+    pub refund_total: i32,
+}
+
+impl ShiftReport {
+    /// Counted minus expected cash, negative when the drawer is short
+    pub fn difference(&self) -> i32 {
+        self.counted_cash - self.expected_cash
+    }
+
+    /// Labels and values of everything on the report, in the order they're shown
+    pub fn lines(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("OPENING FLOAT:", format_signed_price(self.opening_float)),
+            ("CASH TENDERED:", format_signed_price(self.cash_tendered)),
+            ("CHANGE GIVEN:", format_signed_price(-self.change_given)),
+            ("CASH REFUNDED:", format_signed_price(-self.cash_refunded)),
+            ("CASH IN:", format_signed_price(self.cash_in)),
+            ("CASH OUT:", format_signed_price(-self.cash_out)),
+            ("EXPECTED CASH:", format_signed_price(self.expected_cash)),
+            ("COUNTED CASH:", format_signed_price(self.counted_cash)),
+            ("DIFFERENCE:", format_signed_price(self.difference())),
+            ("CARD SALES:", format_signed_price(self.card_sales)),
+            ("CARD REFUNDED:", format_signed_price(-self.card_refunded)),
+            ("TRANSACTIONS:", self.transactions.to_string()),
+            ("REFUNDS:", self.refunds.to_string()),
+            ("REFUNDED TOTAL:", format_signed_price(-self.refund_total)),
+        ]
+    }
+
+    /// Create a string with the report in plain text, laid out like a receipt
+    pub fn generate_report(&self) -> String {
+        let mut string = String::from("\n\n\n\n");
+        string.push_str(&format!(
+            "Swansea Food Centre     {}\n",
+            self.generated.format("%d. %m. %H:%M:%S")
+        ));
+        string.push_str(&format!(
+            "{} report for shift from {}\n",
+            self.kind,
+            self.shift_started.format("%d. %m. %H:%M")
+        ));
+        string.push_str("========================================\n");
+
+        for (label, value) in self.lines() {
+            string.push_str(&format!("{0: <30}{1: >10}\n", label, value));
+        }
+
+        string
+    }
+}
+
+/// Adds up counted notes and coins, given how many there are of each of the denominations
+pub fn count_cash(counts: &[u32]) -> u32 {
+    DENOMINATIONS
+        .iter()
+        .zip(counts)
+        .map(|(denomination, count)| denomination * count)
+        .sum()
+}
+
+/// How a denomination is written, e.g. £50 or 20p
+pub fn denomination_label(denomination: u32) -> String {
+    if denomination >= 100 {
+        format!("£{}", denomination / 100)
+    } else {
+        format!("{}p", denomination)
+    }
+}
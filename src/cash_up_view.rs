@@ -0,0 +1,263 @@
+use chrono::Local;
+use iced::{
+    widget::{button, column, row, scrollable, text, text_input, Column},
+    Alignment, Length,
+};
+use std::fs;
+
+use crate::{
+    cash_drawer::{
+        count_cash, denomination_label, CashDrawer, ReportKind, ShiftReport, DENOMINATIONS,
+    },
+    data_error::DataError,
+    pdf::write_text_pdf,
+    sales_journal::SalesJournal,
+    theme::ButtonStyle,
+    toast,
+    users::Action,
+    utils::{format_price, format_signed_price, parse_price},
+    Element, Message,
+};
+
+const REPORT_DIRECTORY: &str = "./reports";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CashUpView {
+    input_float: String,
+    input_movement: String,
+    input_movement_reason: String,
+    /// How many of each denomination were counted
+    input_counts: [String; DENOMINATIONS.len()],
+    /// The last report run, shown until the next one
+    report: Option<ShiftReport>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CashUpMessage {
+    FloatChanged(String),
+    StartShift,
+    MovementChanged(String),
+    MovementReasonChanged(String),
+    CashIn,
+    CashOut,
+    CountChanged(usize, String),
+    RunReport(ReportKind),
+}
+
+fn map(message: CashUpMessage) -> Message {
+    Message::CashUp(message)
+}
+
+impl CashUpView {
+    pub fn view(&self, cash_drawer: &CashDrawer) -> Element {
+        let shift = match &cash_drawer.shift {
+            Some(shift) => shift,
+            None => {
+                return column![
+                    text("No shift open").size(30),
+                    row![
+                        text_input("Opening float", &self.input_float, |string| {
+                            map(CashUpMessage::FloatChanged(string))
+                        })
+                        .on_submit(map(CashUpMessage::StartShift)),
+                        button("Start Shift")
+                            .style(ButtonStyle::Important)
+                            .on_press(map(CashUpMessage::StartShift)),
+                    ]
+                    .spacing(10),
+                    self.render_report(),
+                ]
+                .padding(20)
+                .spacing(10)
+                .into()
+            }
+        };
+
+        let movements = row![
+            text_input("Amount", &self.input_movement, |string| {
+                map(CashUpMessage::MovementChanged(string))
+            })
+            .width(Length::Units(150)),
+            text_input("Reason", &self.input_movement_reason, |string| {
+                map(CashUpMessage::MovementReasonChanged(string))
+            }),
+            button("Cash In").on_press(map(CashUpMessage::CashIn)),
+            button("Cash Out").on_press(map(CashUpMessage::CashOut)),
+        ]
+        .spacing(10);
+
+        let counts = column(
+            DENOMINATIONS
+                .iter()
+                .enumerate()
+                .map(|(index, denomination)| {
+                    let count = self.input_counts[index].parse::<u32>().unwrap_or(0);
+                    row![
+                        text(denomination_label(*denomination)).width(Length::Units(60)),
+                        text_input("0", &self.input_counts[index], move |string| {
+                            map(CashUpMessage::CountChanged(index, string))
+                        })
+                        .width(Length::Units(100)),
+                        text(format_price(denomination * count)),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        column![
+            text(format!(
+                "Shift started {} with a float of {}",
+                shift.started.format("%d. %m. %H:%M"),
+                format_price(shift.opening_float)
+            ))
+            .size(30),
+            movements,
+            row![
+                scrollable(counts).height(Length::Fill),
+                column![
+                    text(format!("Counted: {}", format_price(self.counted_cash()))).size(30),
+                    row![
+                        button("X Report").on_press(map(CashUpMessage::RunReport(ReportKind::X))),
+                        // closing the shift can't be undone
+                        button("Z Report").style(ButtonStyle::Important).on_press(
+                            Message::RequirePermission(
+                                Action::CloseShift,
+                                Box::new(map(CashUpMessage::RunReport(ReportKind::Z))),
+                            )
+                        ),
+                    ]
+                    .spacing(10),
+                    self.render_report(),
+                ]
+                .spacing(10)
+                .width(Length::Fill),
+            ]
+            .spacing(20),
+        ]
+        .padding(20)
+        .spacing(10)
+        .into()
+    }
+
+    /// Renders the last report run, if any
+    fn render_report(&self) -> Element {
+        let report = match &self.report {
+            Some(report) => report,
+            None => return column![].into(),
+        };
+
+        let lines = Column::with_children(
+            report
+                .lines()
+                .into_iter()
+                .map(|(label, value)| {
+                    row![text(label).width(Length::Fill), text(value)]
+                        .spacing(10)
+                        .into()
+                })
+                .collect(),
+        );
+
+        column![
+            text(format!(
+                "{} report at {}",
+                report.kind,
+                report.generated.format("%H:%M:%S")
+            ))
+            .size(25),
+            lines,
+        ]
+        .spacing(10)
+        .width(Length::Units(400))
+        .into()
+    }
+
+    /// Total of the notes and coins counted so far
+    fn counted_cash(&self) -> u32 {
+        count_cash(
+            &self
+                .input_counts
+                .iter()
+                .map(|count| count.parse::<u32>().unwrap_or(0))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        message: CashUpMessage,
+        cash_drawer: &mut CashDrawer,
+        sales_journal: &SalesJournal,
+    ) -> Result<(), DataError> {
+        match message {
+            CashUpMessage::FloatChanged(v) => self.input_float = v,
+            CashUpMessage::StartShift => match parse_price(&self.input_float) {
+                Ok(float) => {
+                    cash_drawer.start_shift(float, sales_journal)?;
+                    self.input_float.clear();
+                    self.report = None;
+                }
+                Err(_) => {
+                    toast::warning("Invalid float", "Enter the cash in the drawer, e.g. 100.00")
+                }
+            },
+            CashUpMessage::MovementChanged(v) => self.input_movement = v,
+            CashUpMessage::MovementReasonChanged(v) => self.input_movement_reason = v,
+            CashUpMessage::CashIn | CashUpMessage::CashOut => {
+                match parse_price(&self.input_movement) {
+                    Ok(amount) if amount > 0 && !self.input_movement_reason.trim().is_empty() => {
+                        let amount = if message == CashUpMessage::CashIn {
+                            amount as i32
+                        } else {
+                            -(amount as i32)
+                        };
+                        // recorded even if it couldn't be saved, so it isn't entered twice
+                        let result = cash_drawer
+                            .move_cash(amount, std::mem::take(&mut self.input_movement_reason));
+                        self.input_movement.clear();
+                        toast::success(
+                            "Cash recorded",
+                            &format!("{} recorded", format_signed_price(amount)),
+                        );
+                        result?;
+                    }
+                    _ => toast::warning("Invalid cash movement", "Enter an amount and a reason"),
+                }
+            }
+            CashUpMessage::CountChanged(index, v) => {
+                if v.is_empty() || v.parse::<u32>().is_ok() {
+                    self.input_counts[index] = v;
+                }
+            }
+            CashUpMessage::RunReport(kind) => {
+                let report = match cash_drawer.report(kind, sales_journal, self.counted_cash()) {
+                    Some(report) => report,
+                    None => return Ok(()),
+                };
+                let document = report.generate_report();
+
+                if kind == ReportKind::Z {
+                    cash_drawer.end_shift(&report, "./z_reports.jsonl")?;
+                    self.input_counts = Default::default();
+                }
+                self.report = Some(report);
+
+                fs::create_dir_all(REPORT_DIRECTORY)
+                    .map_err(|error| DataError::io(REPORT_DIRECTORY, error))?;
+                let path = format!(
+                    "{}/{}-report-{}.pdf",
+                    REPORT_DIRECTORY,
+                    kind.to_string().to_lowercase(),
+                    Local::now().format("%Y-%m-%d-%H%M")
+                );
+                write_text_pdf(&path, &document).map_err(|error| DataError::io(&path, error))?;
+                toast::success(&format!("{} report ready to print", kind), &path);
+            }
+        }
+        Ok(())
+    }
+}
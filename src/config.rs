@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind};
+
+use crate::{
+    data_error::DataError, payment_terminal::TerminalConfig, storage::StorageBackend,
+    utils::write_atomic,
+};
+
+/// Settings that persist between runs, stored as YAML
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub ui_scale: f64,
+    /// Discounts taking off more than this percentage need someone allowed to apply large discounts
+    pub discount_password_threshold: u32,
+    pub payment_terminal: TerminalConfig,
+    /// Seconds to wait for the customer at the card terminal
+    pub terminal_timeout: u64,
+    /// Wrong PINs in a row before the login is locked
+    pub max_failed_attempts: u32,
+    /// How long the first lockout lasts, each one after doubles it
+    pub lockout_seconds: u64,
+    /// Minutes without any input before the till locks itself, 0 to never lock
+    pub idle_lock_minutes: u32,
+    /// Old versions of the item database to keep, one from the start of each session
+    pub item_db_backups: usize,
+    /// Also show notifications as desktop popups, on top of the ones in the window
+    pub os_notifications: bool,
+    /// Where items, sales and users are kept, takes effect the next time the app starts
+    pub storage: StorageBackend,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ui_scale: 1.0,
+            discount_password_threshold: 10,
+            payment_terminal: TerminalConfig::default(),
+            terminal_timeout: 60,
+            max_failed_attempts: 5,
+            lockout_seconds: 30,
+            idle_lock_minutes: 5,
+            item_db_backups: 5,
+            os_notifications: false,
+            storage: StorageBackend::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from a YAML file given the path, using defaults if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        match fs::read_to_string(path) {
+            Ok(string) => {
+                serde_yaml::from_str::<Self>(&string).map_err(|error| DataError::parse(path, error))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(DataError::io(path, error)),
+        }
+    }
+
+    /// Saves itself to a YAML file
+    pub fn save_yaml(&self, path: &str) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(path, &string).map_err(|error| DataError::io(path, error))
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt::Display;
+
+/// Something that went wrong loading, saving or changing stored data
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataError {
+    /// The file couldn't be read or written
+    Io { path: String, message: String },
+    /// The file isn't valid YAML for what it should hold
+    Parse {
+        path: String,
+        message: String,
+        /// 1-based, if the parser knows where it went wrong
+        line: Option<usize>,
+    },
+    /// The SQLite database couldn't be opened, read or written
+    Database { path: String, message: String },
+    /// The data couldn't be turned into YAML or JSON
+    Serialize(String),
+    /// There's no item with the barcode, e.g. it was deleted during a sale
+    ItemNotFound(u64),
+    /// More than one item has, or would have, the barcode
+    DuplicateBarcode(u64),
+}
+
+impl DataError {
+    pub fn io(path: &str, error: std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_owned(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn database(path: &str, error: rusqlite::Error) -> Self {
+        Self::Database {
+            path: path.to_owned(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn parse(path: &str, error: serde_yaml::Error) -> Self {
+        Self::Parse {
+            path: path.to_owned(),
+            line: error.location().map(|location| location.line()),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataError::Io { path, message } => write!(f, "Couldn't access {}: {}", path, message),
+            DataError::Parse {
+                path,
+                message,
+                line: Some(line),
+            } => write!(f, "{} is invalid at line {}: {}", path, line, message),
+            DataError::Parse { path, message, .. } => write!(f, "{} is invalid: {}", path, message),
+            DataError::Database { path, message } => {
+                write!(f, "Database error in {}: {}", path, message)
+            }
+            DataError::Serialize(message) => write!(f, "Couldn't serialize data: {}", message),
+            DataError::ItemNotFound(barcode) => write!(f, "No item with barcode {}", barcode),
+            DataError::DuplicateBarcode(barcode) => write!(
+                f,
+                "More than one item has barcode {}, each item needs its own",
+                barcode
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
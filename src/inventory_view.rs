@@ -1,14 +1,50 @@
+use chrono::Local;
 use iced::{
     widget::{button, column, container, image, row, scrollable, text, text_input, Column, Space},
-    Length,
+    Alignment, Length,
 };
+use std::fs;
 
-use crate::{item::Item, item_db::ItemDB, theme::ButtonStyle, utils::get_handle, ViewIndex};
+use crate::{
+    data_error::DataError,
+    item::Item,
+    item_db::ItemDB,
+    pdf::write_text_pdf,
+    stock_take::{StockTake, Variance},
+    theme::{ButtonStyle, ContainerStyle},
+    toast,
+    users::Action,
+    utils::{
+        format_large_price, format_price, format_signed_price, format_stock, get_handle,
+        parse_weight,
+    },
+    valuation::{CategoryValue, Valuation},
+    ViewIndex,
+};
+
+/// Where valuation and low stock reports are exported to
+const REPORT_DIRECTORY: &str = "./reports";
 use crate::{Element, Message};
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct InventoryView {
     pub input_search: String,
+    stock_take: StockTake,
+    /// Whether the stock take is shown instead of the items, a count carries on while it's hidden
+    show_stock_take: bool,
+    input_count_barcode: String,
+    input_count_quantity: String,
+    /// What's waiting for the user to confirm it
+    confirming: Option<StockTakeEnd>,
+    /// Whether the valuation report is shown instead of the items
+    show_valuation: bool,
+}
+
+/// The ways a stock take can end, both need confirming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockTakeEnd {
+    Apply,
+    Discard,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -16,10 +52,66 @@ pub enum InventoryMessage {
     SearchChanged(String),
     DeleteItem(Item),
     PrintItemsLowInStock,
+    ToggleStockTake,
+    StartStockTake,
+    CountBarcodeChanged(String),
+    CountQuantityChanged(String),
+    /// Adds the scanned item to the count
+    Count,
+    RemoveCount(u64),
+    /// Asks to confirm ending the stock take
+    EndStockTake(StockTakeEnd),
+    CancelEnd,
+    ConfirmEnd(StockTakeEnd),
+    ToggleValuation,
+    ExportValuation,
+}
+
+fn map(message: InventoryMessage) -> Message {
+    Message::Inventory(message)
 }
 
 impl InventoryView {
+    /// Picks up where a count saved before a restart left off
+    pub fn new(stock_take: StockTake) -> Self {
+        Self {
+            show_stock_take: stock_take.is_active(),
+            stock_take,
+            ..Default::default()
+        }
+    }
+
     pub fn view(&self, item_db: &ItemDB) -> Element {
+        let stock_take_label = if self.show_stock_take {
+            "Back to Items"
+        } else if self.stock_take.is_active() {
+            "Stock Take (in progress)"
+        } else {
+            "Stock Take"
+        };
+        let stock_take_button =
+            button(text(stock_take_label)).on_press(map(InventoryMessage::ToggleStockTake));
+
+        let valuation_button = button(text(if self.show_valuation {
+            "Back to Items"
+        } else {
+            "Valuation"
+        }))
+        .on_press(map(InventoryMessage::ToggleValuation));
+
+        if self.show_stock_take {
+            return column![stock_take_button, self.render_stock_take(item_db)]
+                .spacing(20)
+                .padding(20)
+                .into();
+        }
+        if self.show_valuation {
+            return column![valuation_button, render_valuation(&Valuation::new(item_db))]
+                .spacing(20)
+                .padding(20)
+                .into();
+        }
+
         const COL_COUNT: usize = 4;
         const COL_HEIGHT: u16 = 140;
 
@@ -31,8 +123,21 @@ impl InventoryView {
                         .height(Length::Units(COL_HEIGHT)),
                     column![
                         text(&item.name),
-                        button("Delete").on_press(Message::Inventory(
-                            InventoryMessage::DeleteItem(item.clone())
+                        text(match (item.margin(), item.markup()) {
+                            (Some(margin), Some(markup)) => format!(
+                                "Cost {}, margin {:.1}%, markup {:.1}%",
+                                format_price(item.cost_price),
+                                margin,
+                                markup
+                            ),
+                            _ => "No cost price".to_owned(),
+                        })
+                        .size(16),
+                        button("Delete").on_press(Message::RequirePermission(
+                            Action::DeleteItem,
+                            Box::new(Message::Inventory(InventoryMessage::DeleteItem(
+                                item.clone()
+                            )))
                         ))
                     ]
                 ]
@@ -71,13 +176,8 @@ impl InventoryView {
 
         // setup grid
         let i = item_db
-            .items
-            .iter()
-            .filter(|item| {
-                item.name
-                    .to_lowercase()
-                    .contains(&self.input_search.to_lowercase())
-            })
+            .search(&self.input_search)
+            .into_iter()
             .cloned()
             .collect::<Vec<_>>();
 
@@ -103,7 +203,9 @@ impl InventoryView {
                         .on_press(Message::SetActiveView(ViewIndex::ItemCreation)),
                     button(text("Print low in stock list"))
                         .style(ButtonStyle::Important)
-                        .on_press(Message::Inventory(InventoryMessage::PrintItemsLowInStock))
+                        .on_press(Message::Inventory(InventoryMessage::PrintItemsLowInStock)),
+                    stock_take_button,
+                    valuation_button,
                 ]
                 .spacing(20),
             )
@@ -120,20 +222,309 @@ impl InventoryView {
             .into()
     }
 
-    pub fn update(&mut self, message: InventoryMessage, item_db: &mut ItemDB) {
+    fn render_stock_take(&self, item_db: &ItemDB) -> Element {
+        let started = match self.stock_take.started {
+            Some(started) => started,
+            None => {
+                return column![
+                    text("Count some or all of the stock, only the items counted are adjusted"),
+                    button("Start Stock Take")
+                        .style(ButtonStyle::Important)
+                        .on_press(map(InventoryMessage::StartStockTake)),
+                ]
+                .spacing(10)
+                .into()
+            }
+        };
+
+        let variances = self.stock_take.variances(item_db);
+        let changed = variances.iter().filter(|v| v.difference != 0).count();
+        let value: i32 = variances.iter().map(|variance| variance.value).sum();
+
+        let header = row![
+            text("Item").width(Length::FillPortion(3)),
+            text("Expected").width(Length::FillPortion(1)),
+            text("Counted").width(Length::FillPortion(1)),
+            text("Variance").width(Length::FillPortion(1)),
+            text("Value").width(Length::FillPortion(1)),
+            Space::new(Length::Units(30), Length::Shrink),
+        ]
+        .spacing(10);
+        let rows =
+            Column::with_children(variances.iter().rev().map(render_variance).collect()).spacing(5);
+
+        let actions: Element = match self.confirming {
+            Some(end) => container(
+                row![
+                    text(match end {
+                        StockTakeEnd::Apply => format!(
+                            "Adjust the stock of {} items by {} in total?",
+                            changed,
+                            format_signed_price(value)
+                        ),
+                        StockTakeEnd::Discard =>
+                            "Throw away the count without changing any stock?".to_owned(),
+                    })
+                    .width(Length::Fill),
+                    button("Cancel").on_press(map(InventoryMessage::CancelEnd)),
+                    button("Confirm")
+                        .style(ButtonStyle::Important)
+                        .on_press(match end {
+                            // applying is a stock adjustment, discarding changes nothing
+                            StockTakeEnd::Apply => Message::RequirePermission(
+                                Action::AdjustStock,
+                                Box::new(map(InventoryMessage::ConfirmEnd(end))),
+                            ),
+                            StockTakeEnd::Discard => map(InventoryMessage::ConfirmEnd(end)),
+                        }),
+                ]
+                .spacing(10)
+                .align_items(Alignment::Center),
+            )
+            .style(ContainerStyle::Warning)
+            .padding(10)
+            .into(),
+            None => row![
+                button("Apply Variances")
+                    .style(ButtonStyle::Important)
+                    .on_press(map(InventoryMessage::EndStockTake(StockTakeEnd::Apply))),
+                button("Discard Count")
+                    .on_press(map(InventoryMessage::EndStockTake(StockTakeEnd::Discard))),
+            ]
+            .spacing(10)
+            .into(),
+        };
+
+        column![
+            text(format!(
+                "Stock take started {}, {} items counted, {} with a variance of {} in total",
+                started.format("%d. %m. %H:%M"),
+                variances.len(),
+                changed,
+                format_signed_price(value)
+            ))
+            .size(25),
+            row![
+                text_input("Scan or type a barcode", &self.input_count_barcode, |v| {
+                    map(InventoryMessage::CountBarcodeChanged(v))
+                })
+                .on_submit(map(InventoryMessage::Count)),
+                text_input("Quantity (1)", &self.input_count_quantity, |v| {
+                    map(InventoryMessage::CountQuantityChanged(v))
+                })
+                .on_submit(map(InventoryMessage::Count))
+                .width(Length::Units(200)),
+                button("Count").on_press(map(InventoryMessage::Count)),
+            ]
+            .spacing(10),
+            header,
+            scrollable(rows).height(Length::Fill),
+            actions,
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    /// Counts the item typed in, an empty quantity is one
+    /// Quantities of items sold by weight are in kg
+    fn count(&mut self, item_db: &ItemDB) -> Result<(), DataError> {
+        let item = self
+            .input_count_barcode
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|barcode| item_db.get_item(barcode));
+        let item = match item {
+            Some(item) => item,
+            None => {
+                toast::warning("Invalid item", "Item with barcode couldn't be found");
+                return Ok(());
+            }
+        };
+
+        let input = self.input_count_quantity.trim();
+        let quantity = match (input.is_empty(), item.sold_by_weight) {
+            (true, _) => Ok(1),
+            (false, true) => parse_weight(input),
+            (false, false) => input.parse().map_err(|_| ()),
+        };
+        match quantity {
+            Ok(quantity) => {
+                self.input_count_barcode.clear();
+                self.input_count_quantity.clear();
+                self.stock_take
+                    .count(item.barcode, quantity, item.amount_in_stock)
+            }
+            Err(_) => {
+                toast::warning(
+                    "Invalid quantity",
+                    "Enter a whole number, or kg when sold by weight",
+                );
+                Ok(())
+            }
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        message: InventoryMessage,
+        item_db: &mut ItemDB,
+    ) -> Result<(), DataError> {
         match message {
             InventoryMessage::SearchChanged(value) => self.input_search = value,
-            InventoryMessage::DeleteItem(item) => item_db.delete_item(item),
+            InventoryMessage::DeleteItem(item) => item_db.delete_item(item)?,
             InventoryMessage::PrintItemsLowInStock => {
-                println!("Items low in stock:");
-                for item in item_db
-                    .items
-                    .iter()
-                    .filter(|item| item.amount_in_stock < 20)
-                {
-                    println!("Item: {}, in stock: {}", &item.name, item.amount_in_stock);
+                fs::create_dir_all(REPORT_DIRECTORY)
+                    .map_err(|error| DataError::io(REPORT_DIRECTORY, error))?;
+                let path = format!(
+                    "{}/low-stock-{}.pdf",
+                    REPORT_DIRECTORY,
+                    Local::now().format("%Y-%m-%d-%H%M")
+                );
+                write_text_pdf(&path, &low_stock_document(item_db))
+                    .map_err(|error| DataError::io(&path, error))?;
+                toast::success("Low stock report ready to print", &path);
+            }
+            InventoryMessage::ToggleStockTake => {
+                self.show_stock_take = !self.show_stock_take;
+                self.show_valuation = false;
+                self.confirming = None;
+            }
+            InventoryMessage::ToggleValuation => {
+                self.show_valuation = !self.show_valuation;
+                self.show_stock_take = false;
+            }
+            InventoryMessage::ExportValuation => {
+                let path = Valuation::new(item_db).export(REPORT_DIRECTORY)?;
+                toast::success("Valuation exported", &path);
+            }
+            InventoryMessage::StartStockTake => self.stock_take.start()?,
+            InventoryMessage::CountBarcodeChanged(v) => self.input_count_barcode = v,
+            InventoryMessage::CountQuantityChanged(v) => self.input_count_quantity = v,
+            InventoryMessage::Count => self.count(item_db)?,
+            InventoryMessage::RemoveCount(barcode) => self.stock_take.remove(barcode)?,
+            InventoryMessage::EndStockTake(end) => self.confirming = Some(end),
+            InventoryMessage::CancelEnd => self.confirming = None,
+            InventoryMessage::ConfirmEnd(end) => {
+                self.confirming = None;
+                match end {
+                    StockTakeEnd::Apply => {
+                        self.stock_take.apply(item_db)?;
+                        toast::success("Stock take applied", "");
+                    }
+                    StockTakeEnd::Discard => self.stock_take.discard()?,
                 }
             }
         }
+        Ok(())
+    }
+}
+
+/// Utility to render one counted item
+fn render_variance<'a>(variance: &Variance) -> Element<'a> {
+    let difference = format!(
+        "{}{}",
+        if variance.difference < 0 { "-" } else { "+" },
+        format_stock(variance.difference.unsigned_abs(), variance.sold_by_weight)
+    );
+
+    row![
+        text(&variance.name).width(Length::FillPortion(3)),
+        text(format_stock(variance.expected, variance.sold_by_weight))
+            .width(Length::FillPortion(1)),
+        text(format_stock(variance.counted, variance.sold_by_weight)).width(Length::FillPortion(1)),
+        text(difference).width(Length::FillPortion(1)),
+        text(format_signed_price(variance.value)).width(Length::FillPortion(1)),
+        button("x")
+            .on_press(map(InventoryMessage::RemoveCount(variance.barcode)))
+            .width(Length::Units(30)),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+/// Utility to render the value of the stock by category
+fn render_valuation<'a>(valuation: &Valuation) -> Element<'a> {
+    let header = row![
+        text("Category").width(Length::FillPortion(3)),
+        text("Items").width(Length::FillPortion(1)),
+        text("At cost").width(Length::FillPortion(1)),
+        text("At retail").width(Length::FillPortion(1)),
+    ]
+    .spacing(10);
+    let value_row = |category: &CategoryValue| -> Element<'a> {
+        row![
+            text(&category.category).width(Length::FillPortion(3)),
+            text(category.items).width(Length::FillPortion(1)),
+            text(format_large_price(category.at_cost)).width(Length::FillPortion(1)),
+            text(format_large_price(category.at_retail)).width(Length::FillPortion(1)),
+        ]
+        .spacing(10)
+        .into()
+    };
+    let total = CategoryValue {
+        category: "Total".to_owned(),
+        items: valuation
+            .categories
+            .iter()
+            .map(|category| category.items)
+            .sum(),
+        at_cost: valuation.total_at_cost(),
+        at_retail: valuation.total_at_retail(),
+    };
+
+    let mut summary = column![text(format!(
+        "Stock value at {}",
+        valuation.time.format("%d. %m. %Y %H:%M")
+    ))
+    .size(25)]
+    .spacing(5);
+    if valuation.missing_cost > 0 {
+        summary = summary.push(text(format!(
+            "{} items in stock have no cost price and count as nothing at cost",
+            valuation.missing_cost
+        )));
+    }
+
+    column![
+        summary,
+        header,
+        scrollable(
+            Column::with_children(valuation.categories.iter().map(value_row).collect()).spacing(5)
+        )
+        .height(Length::Fill),
+        value_row(&total),
+        button("Export CSV")
+            .style(ButtonStyle::Important)
+            .on_press(map(InventoryMessage::ExportValuation)),
+    ]
+    .spacing(10)
+    .into()
+}
+
+/// The items that need reordering in plain text, laid out like a receipt
+fn low_stock_document(item_db: &ItemDB) -> String {
+    let mut string = format!(
+        "Swansea Food Centre     {}\n",
+        Local::now().format("%d. %m. %Y")
+    );
+    string.push_str("Items low in stock\n");
+    string.push_str("========================================\n");
+    let items = item_db
+        .items()
+        .iter()
+        .filter(|item| item.needs_reorder())
+        .collect::<Vec<_>>();
+    for item in items.iter() {
+        string.push_str(&format!("{}\n", item.name));
+        string.push_str(&format!(
+            "    in stock {}, reorder at {}\n",
+            format_stock(item.amount_in_stock, item.sold_by_weight),
+            format_stock(item.reorder_point.unwrap_or(0), item.sold_by_weight)
+        ));
     }
+    string.push_str("========================================\n");
+    string.push_str(&format!("{} items\n", items.len()));
+    string
 }
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::transaction::TaxSummary;
 
 /// Represents an item
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
@@ -8,4 +11,125 @@ pub struct Item {
     pub price: u32,
     pub image_path: Option<String>,
     pub amount_in_stock: u32,
+    #[serde(default)]
+    pub tax_class: TaxClass,
+    /// If true, `price` is per kg and `amount_in_stock` is in grams
+    #[serde(default)]
+    pub sold_by_weight: bool,
+    /// If true, the customer's age has to be checked before it can be sold
+    #[serde(default)]
+    pub age_restricted: bool,
+    /// Reorder once stock is down to this, None if it isn't reordered
+    #[serde(default)]
+    pub reorder_point: Option<u32>,
+    /// How much to order at a time, in grams for items sold by weight
+    #[serde(default)]
+    pub reorder_quantity: u32,
+    /// Who it's ordered from, the preferred supplier first
+    #[serde(default)]
+    pub suppliers: Vec<ItemSupplier>,
+    /// What it costs on average in pence, per kg for items sold by weight, 0 if unknown
+    /// Deliveries booked in with a cost move it to the weighted average of the stock
+    #[serde(default)]
+    pub cost_price: u32,
+    /// What it's grouped under in reports, empty if it isn't in one
+    #[serde(default)]
+    pub category: String,
+    /// The name of its supplier in databases saved before there was a supplier registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supplier: Option<String>,
+}
+
+/// How an item is bought from one supplier
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemSupplier {
+    /// The id of the supplier
+    pub supplier: u64,
+    /// What the supplier calls it
+    #[serde(default)]
+    pub product_code: String,
+    /// What they charge for it in pence, per kg for items sold by weight
+    #[serde(default)]
+    pub cost_price: u32,
+}
+
+impl Item {
+    /// Whether stock is low enough that it should be ordered
+    pub fn needs_reorder(&self) -> bool {
+        self.reorder_point
+            .map_or(false, |point| self.amount_in_stock <= point)
+    }
+
+    /// The price without VAT, which is what the shop gets to keep
+    pub fn net_price(&self) -> u32 {
+        TaxSummary::from_gross(self.tax_class, self.price as i32).net as u32
+    }
+
+    /// Profit as a percentage of the price without VAT, None if the cost price is unknown
+    pub fn margin(&self) -> Option<f64> {
+        let net_price = self.net_price() as f64;
+        (self.cost_price > 0 && net_price > 0.0)
+            .then(|| (net_price - self.cost_price as f64) / net_price * 100.0)
+    }
+
+    /// Profit as a percentage of the cost price, None if the cost price is unknown
+    pub fn markup(&self) -> Option<f64> {
+        let cost_price = self.cost_price as f64;
+        (self.cost_price > 0).then(|| (self.net_price() as f64 - cost_price) / cost_price * 100.0)
+    }
+
+    /// What the stock is worth at a price per item, or per kg for items sold by weight
+    pub fn stock_value(&self, price: u32) -> u64 {
+        if self.sold_by_weight {
+            // stock is in grams
+            (self.amount_in_stock as u64 * price as u64 + 500) / 1000
+        } else {
+            self.amount_in_stock as u64 * price as u64
+        }
+    }
+
+    /// The category it's reported under
+    pub fn category_name(&self) -> &str {
+        match self.category.trim() {
+            "" => "Uncategorised",
+            category => category,
+        }
+    }
+
+    /// The supplier it's ordered from first
+    pub fn preferred_supplier(&self) -> Option<&ItemSupplier> {
+        self.suppliers.first()
+    }
+}
+
+/// The VAT rate an item is sold at
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TaxClass {
+    #[default]
+    Standard,
+    Reduced,
+    Zero,
+}
+
+impl TaxClass {
+    pub const ALL: [TaxClass; 3] = [TaxClass::Standard, TaxClass::Reduced, TaxClass::Zero];
+
+    /// The VAT rate as a percentage
+    pub fn rate(&self) -> u32 {
+        match self {
+            TaxClass::Standard => 20,
+            TaxClass::Reduced => 5,
+            TaxClass::Zero => 0,
+        }
+    }
+}
+
+impl Display for TaxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaxClass::Standard => write!(f, "Standard ({}%)", self.rate()),
+            TaxClass::Reduced => write!(f, "Reduced ({}%)", self.rate()),
+            TaxClass::Zero => write!(f, "Zero ({}%)", self.rate()),
+        }
+    }
 }
@@ -1,12 +1,16 @@
 use iced::{
-    widget::{button, column, image, row, text, text_input},
-    Length,
+    widget::{button, column, image, row, text, text_input, Column},
+    Alignment, Length,
 };
 
 use crate::{
-    item::Item,
+    data_error::DataError,
+    item::{Item, ItemSupplier, TaxClass},
     item_db::ItemDB,
-    utils::{get_handle, notify, parse_price},
+    suppliers::Suppliers,
+    theme::ButtonStyle,
+    toast,
+    utils::{format_price, format_stock, get_handle, parse_price, parse_weight},
 };
 use crate::{Element, Message};
 
@@ -17,6 +21,18 @@ pub struct ItemCreationView {
     input_name: String,
     input_price: String,
     input_barcode: String,
+    input_tax_class: TaxClass,
+    input_sold_by_weight: bool,
+    input_age_restricted: bool,
+    input_cost_price: String,
+    input_category: String,
+    input_reorder_point: String,
+    input_reorder_quantity: String,
+    /// The suppliers it's bought from, the preferred one first
+    supplier_links: Vec<ItemSupplier>,
+    input_link_supplier: String,
+    input_link_product_code: String,
+    input_link_cost_price: String,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -26,6 +42,21 @@ pub enum ItemCreationMessage {
     NameChanged(String),
     PriceChanged(String),
     BarcodeChanged(String),
+    TaxClassChanged(TaxClass),
+    SoldByWeightToggled,
+    AgeRestrictedToggled,
+    CostPriceChanged(String),
+    CategoryChanged(String),
+    ReorderPointChanged(String),
+    ReorderQuantityChanged(String),
+    LinkSupplierChanged(String),
+    LinkProductCodeChanged(String),
+    LinkCostPriceChanged(String),
+    /// Links the supplier named in the inputs, or updates the link if there is one
+    AddSupplierLink,
+    RemoveSupplierLink(usize),
+    /// Moves a link to the front, so the item is ordered from that supplier
+    PreferSupplierLink(usize),
     SaveItem,
 }
 
@@ -36,15 +67,74 @@ impl ItemCreationView {
             self.input_barcode = item.barcode.to_string();
             self.input_name = item.name.clone();
             self.input_image_path = item.image_path.unwrap_or("".to_owned());
-            self.input_price = (item.price as f32 / 100.0).to_string();
+            self.input_price = format!("{}.{:02}", item.price / 100, item.price % 100);
+            self.input_tax_class = item.tax_class;
+            self.input_sold_by_weight = item.sold_by_weight;
+            self.input_age_restricted = item.age_restricted;
+            self.input_cost_price = if item.cost_price > 0 {
+                format!("{}.{:02}", item.cost_price / 100, item.cost_price % 100)
+            } else {
+                String::new()
+            };
+            self.input_category = item.category.clone();
+            let format_quantity = |quantity| format_stock(quantity, item.sold_by_weight);
+            self.input_reorder_point = item.reorder_point.map_or(String::new(), format_quantity);
+            self.input_reorder_quantity = match item.reorder_point {
+                Some(_) => format_quantity(item.reorder_quantity),
+                None => String::new(),
+            };
+            self.supplier_links = item.suppliers;
         } else {
             self.input_barcode = "".to_owned();
             self.input_name = "".to_owned();
             self.input_image_path = "".to_owned();
             self.input_price = "".to_owned();
+            self.input_tax_class = TaxClass::default();
+            self.input_sold_by_weight = false;
+            self.input_age_restricted = false;
+            self.input_cost_price = "".to_owned();
+            self.input_category = "".to_owned();
+            self.input_reorder_point = "".to_owned();
+            self.input_reorder_quantity = "".to_owned();
+            self.supplier_links.clear();
         }
+        self.input_link_supplier.clear();
+        self.input_link_product_code.clear();
+        self.input_link_cost_price.clear();
     }
-    pub fn view(&self) -> Element {
+    pub fn view<'a>(&'a self, suppliers: &'a Suppliers) -> Element<'a> {
+        let supplier_links = Column::with_children(
+            self.supplier_links
+                .iter()
+                .enumerate()
+                .map(|(index, link)| {
+                    let preferred: Element = if index == 0 {
+                        text("Preferred").width(Length::Units(130)).into()
+                    } else {
+                        button(text("Make preferred"))
+                            .on_press(Message::ItemCreation(
+                                ItemCreationMessage::PreferSupplierLink(index),
+                            ))
+                            .width(Length::Units(130))
+                            .into()
+                    };
+                    row![
+                        text(suppliers.name(link.supplier)).width(Length::FillPortion(2)),
+                        text(&link.product_code).width(Length::FillPortion(1)),
+                        text(format_price(link.cost_price)).width(Length::FillPortion(1)),
+                        preferred,
+                        button(text("Remove")).on_press(Message::ItemCreation(
+                            ItemCreationMessage::RemoveSupplierLink(index)
+                        )),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
         row![
             // left side
             column![
@@ -65,12 +155,107 @@ impl ItemCreationView {
                 text_input("Item Name", &self.input_name, |input| {
                     Message::ItemCreation(ItemCreationMessage::NameChanged(input))
                 }),
-                text_input("Item Price", &self.input_price, |input| {
-                    Message::ItemCreation(ItemCreationMessage::PriceChanged(input))
-                }),
+                row![
+                    text_input(
+                        if self.input_sold_by_weight {
+                            "Item Price per kg"
+                        } else {
+                            "Item Price"
+                        },
+                        &self.input_price,
+                        |input| Message::ItemCreation(ItemCreationMessage::PriceChanged(input))
+                    ),
+                    button(text("Sold by weight"))
+                        .style(if self.input_sold_by_weight {
+                            ButtonStyle::ItemSelected
+                        } else {
+                            ButtonStyle::Item
+                        })
+                        .on_press(Message::ItemCreation(
+                            ItemCreationMessage::SoldByWeightToggled
+                        ))
+                ]
+                .spacing(10),
+                // cost price, left empty if it isn't known yet
+                row![
+                    text_input(
+                        if self.input_sold_by_weight {
+                            "Cost Price per kg"
+                        } else {
+                            "Cost Price"
+                        },
+                        &self.input_cost_price,
+                        |input| Message::ItemCreation(ItemCreationMessage::CostPriceChanged(input))
+                    ),
+                    text_input("Category", &self.input_category, |input| {
+                        Message::ItemCreation(ItemCreationMessage::CategoryChanged(input))
+                    }),
+                ]
+                .spacing(10),
+                text(self.describe_margin()),
                 text_input("Item Barcode", &self.input_barcode, |input| {
                     Message::ItemCreation(ItemCreationMessage::BarcodeChanged(input))
                 }),
+                // VAT rate
+                row(TaxClass::ALL
+                    .iter()
+                    .map(|class| {
+                        button(text(class))
+                            .style(if &self.input_tax_class == class {
+                                ButtonStyle::ItemSelected
+                            } else {
+                                ButtonStyle::Item
+                            })
+                            .on_press(Message::ItemCreation(ItemCreationMessage::TaxClassChanged(
+                                *class,
+                            )))
+                            .into()
+                    })
+                    .collect())
+                .spacing(10),
+                button(text("Age restricted (18+)"))
+                    .style(if self.input_age_restricted {
+                        ButtonStyle::ItemSelected
+                    } else {
+                        ButtonStyle::Item
+                    })
+                    .on_press(Message::ItemCreation(
+                        ItemCreationMessage::AgeRestrictedToggled
+                    )),
+                // reordering, left empty if the item isn't reordered
+                row![
+                    text_input("Reorder at (stock)", &self.input_reorder_point, |input| {
+                        Message::ItemCreation(ItemCreationMessage::ReorderPointChanged(input))
+                    }),
+                    text_input("Reorder quantity", &self.input_reorder_quantity, |input| {
+                        Message::ItemCreation(ItemCreationMessage::ReorderQuantityChanged(input))
+                    }),
+                ]
+                .spacing(10),
+                // suppliers, picked by name from the ones on the suppliers tab
+                supplier_links,
+                row![
+                    text_input("Supplier", &self.input_link_supplier, |input| {
+                        Message::ItemCreation(ItemCreationMessage::LinkSupplierChanged(input))
+                    }),
+                    text_input("Product code", &self.input_link_product_code, |input| {
+                        Message::ItemCreation(ItemCreationMessage::LinkProductCodeChanged(input))
+                    }),
+                    text_input(
+                        if self.input_sold_by_weight {
+                            "Cost price per kg"
+                        } else {
+                            "Cost price"
+                        },
+                        &self.input_link_cost_price,
+                        |input| Message::ItemCreation(ItemCreationMessage::LinkCostPriceChanged(
+                            input
+                        ))
+                    ),
+                    button(text("Add Supplier"))
+                        .on_press(Message::ItemCreation(ItemCreationMessage::AddSupplierLink)),
+                ]
+                .spacing(10),
                 button(text("Save Item"))
                     .on_press(Message::ItemCreation(ItemCreationMessage::SaveItem)),
             ]
@@ -81,37 +266,192 @@ impl ItemCreationView {
         .into()
     }
 
+    /// The margin and markup at the price and cost price typed in
+    fn describe_margin(&self) -> String {
+        let item = match (
+            parse_price(&self.input_price),
+            parse_price(&self.input_cost_price),
+        ) {
+            (Ok(price), Ok(cost_price)) => Item {
+                price,
+                cost_price,
+                tax_class: self.input_tax_class,
+                ..Default::default()
+            },
+            _ => return "Enter a price and cost price to see the margin".to_owned(),
+        };
+        match (item.margin(), item.markup()) {
+            (Some(margin), Some(markup)) => format!(
+                "Margin {:.1}%, markup {:.1}% (on the price without VAT)",
+                margin, markup
+            ),
+            _ => "Enter a price and cost price to see the margin".to_owned(),
+        }
+    }
+
+    /// Try parse an item from the inputs
+    /// The reorder point and quantity are in kg for items sold by weight
+    fn parse_item(&self) -> Result<Item, ()> {
+        let barcode = self.input_barcode.parse().map_err(|_| ())?;
+        let price = parse_price(&self.input_price)?;
+        let cost_price = match self.input_cost_price.trim() {
+            "" => 0,
+            input => parse_price(input)?,
+        };
+
+        let image_path = if self.input_image_path.is_empty() {
+            None
+        } else {
+            Some(self.input_image_path.clone())
+        };
+
+        let parse_quantity = |input: &str| {
+            if self.input_sold_by_weight {
+                parse_weight(input)
+            } else {
+                input.trim().parse().map_err(|_| ())
+            }
+        };
+        let (reorder_point, reorder_quantity) = if self.input_reorder_point.trim().is_empty() {
+            (None, 0)
+        } else {
+            let quantity = parse_quantity(&self.input_reorder_quantity)?;
+            // something has to be ordered when stock gets low
+            if quantity == 0 {
+                return Err(());
+            }
+            (Some(parse_quantity(&self.input_reorder_point)?), quantity)
+        };
+
+        Ok(Item {
+            barcode,
+            name: self.input_name.clone(),
+            price,
+            image_path,
+            // stock only changes through goods in and adjustments, edits keep the stock they had
+            amount_in_stock: 0,
+            tax_class: self.input_tax_class,
+            sold_by_weight: self.input_sold_by_weight,
+            age_restricted: self.input_age_restricted,
+            reorder_point,
+            reorder_quantity,
+            suppliers: self.supplier_links.clone(),
+            cost_price,
+            category: self.input_category.trim().to_owned(),
+            supplier: None,
+        })
+    }
+
     pub fn update(
         &mut self,
         message: ItemCreationMessage,
         item_db: &mut ItemDB,
+        suppliers: &Suppliers,
     ) -> Option<Message> {
         match message {
             ItemCreationMessage::ImagePathChanged(value) => self.input_image_path = value,
             ItemCreationMessage::NameChanged(value) => self.input_name = value,
             ItemCreationMessage::PriceChanged(value) => self.input_price = value,
             ItemCreationMessage::BarcodeChanged(value) => self.input_barcode = value,
+            ItemCreationMessage::TaxClassChanged(value) => self.input_tax_class = value,
+            ItemCreationMessage::CostPriceChanged(value) => self.input_cost_price = value,
+            ItemCreationMessage::CategoryChanged(value) => self.input_category = value,
+            ItemCreationMessage::SoldByWeightToggled => {
+                self.input_sold_by_weight = !self.input_sold_by_weight
+            }
+            ItemCreationMessage::AgeRestrictedToggled => {
+                self.input_age_restricted = !self.input_age_restricted
+            }
+            ItemCreationMessage::ReorderPointChanged(value) => self.input_reorder_point = value,
+            ItemCreationMessage::ReorderQuantityChanged(value) => {
+                self.input_reorder_quantity = value
+            }
+            ItemCreationMessage::LinkSupplierChanged(value) => self.input_link_supplier = value,
+            ItemCreationMessage::LinkProductCodeChanged(value) => {
+                self.input_link_product_code = value
+            }
+            ItemCreationMessage::LinkCostPriceChanged(value) => self.input_link_cost_price = value,
+            ItemCreationMessage::AddSupplierLink => {
+                let name = self.input_link_supplier.trim();
+                let supplier = match suppliers
+                    .suppliers
+                    .iter()
+                    .find(|supplier| supplier.name.eq_ignore_ascii_case(name))
+                {
+                    Some(supplier) => supplier.id,
+                    None => {
+                        toast::warning("Unknown supplier", "Add them on the suppliers tab first");
+                        return None;
+                    }
+                };
+                let cost_price = match self.input_link_cost_price.trim() {
+                    "" => Ok(0),
+                    input => parse_price(input),
+                };
+                let cost_price = match cost_price {
+                    Ok(cost_price) => cost_price,
+                    Err(_) => {
+                        toast::warning("Invalid cost price", &self.input_link_cost_price);
+                        return None;
+                    }
+                };
+
+                let link = ItemSupplier {
+                    supplier,
+                    product_code: self.input_link_product_code.trim().to_owned(),
+                    cost_price,
+                };
+                match self
+                    .supplier_links
+                    .iter_mut()
+                    .find(|existing| existing.supplier == supplier)
+                {
+                    Some(existing) => *existing = link,
+                    None => self.supplier_links.push(link),
+                }
+                self.input_link_supplier.clear();
+                self.input_link_product_code.clear();
+                self.input_link_cost_price.clear();
+            }
+            ItemCreationMessage::RemoveSupplierLink(index) => {
+                if index < self.supplier_links.len() {
+                    self.supplier_links.remove(index);
+                }
+            }
+            ItemCreationMessage::PreferSupplierLink(index) => {
+                if index < self.supplier_links.len() {
+                    let link = self.supplier_links.remove(index);
+                    self.supplier_links.insert(0, link);
+                }
+            }
 
             ItemCreationMessage::SaveItem => {
                 // try parse the item data
-                match parse_item(
-                    self.input_name.clone(),
-                    self.input_barcode.clone(),
-                    self.input_price.clone(),
-                    self.input_image_path.clone(),
-                ) {
+                match self.parse_item() {
                     Ok(item) => {
-                        if self.editing_item.is_some() {
+                        let result = if self.editing_item.is_some() {
                             let e = self.editing_item.clone().unwrap();
-                            let index = item_db.items.iter().position(|i| &e == i).unwrap();
-                            item_db.items[index] = item;
+                            item_db.replace_item(&e, item)
                         } else {
-                            item_db.items.push(item);
+                            item_db.add_item(item)
+                        };
+                        match result {
+                            // the item may have been deleted while it was being edited
+                            Err(error @ DataError::ItemNotFound(_)) => {
+                                return Some(Message::DataError(error))
+                            }
+                            // nothing was saved, the barcode needs changing first
+                            Err(error @ DataError::DuplicateBarcode(_)) => {
+                                toast::error("Failed saving item", &error.to_string());
+                                return None;
+                            }
+                            // saved, only the audit log is missing it
+                            Err(error) => toast::error("Saved Item", &error.to_string()),
+                            Ok(()) => toast::success("Saved Item", &self.input_name),
                         }
-                        notify("Saved Item", &self.input_name);
                         return Some(Message::SetActiveView(crate::ViewIndex::Inventory));
                     }
-                    Err(_) => notify("Failed saving item", ""),
+                    Err(_) => toast::error("Failed saving item", ""),
                 };
             }
 
@@ -133,28 +473,3 @@ impl ItemCreationView {
         None
     }
 }
-
-/// Try parse an item from strings
-fn parse_item(
-    name: String,
-    barcode: String,
-    price: String,
-    image_path: String,
-) -> Result<Item, ()> {
-    let barcode = barcode.parse().map_err(|_| ())?;
-    let price = parse_price(&price)?;
-
-    let image_path = if image_path.is_empty() {
-        None
-    } else {
-        Some(image_path)
-    };
-
-    Ok(Item {
-        barcode,
-        name,
-        price,
-        image_path,
-        amount_in_stock: 5,
-    })
-}
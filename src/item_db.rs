@@ -1,60 +1,675 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    fs,
+};
 
-use crate::{item::Item, transaction::Transaction};
-
-// TODO: maybe load into a hashmap for better lookup performance
+use crate::{
+    audit_log::AuditLog, data_error::DataError, item::Item, transaction::Transaction,
+    utils::write_atomic,
+};
 
 /// A database of all `Item`s
+/// Only changed through its methods, so the lookup indexes always match the items
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
 pub struct ItemDB {
-    // pub items: HashMap<u64, Item>,
-    pub items: Vec<Item>,
+    items: Vec<Item>,
+    /// Position in `items` of each barcode
+    #[serde(skip)]
+    by_barcode: HashMap<u64, usize>,
+    /// Positions in `items` of the items with each lowercase word in their name
+    #[serde(skip)]
+    by_word: BTreeMap<String, BTreeSet<usize>>,
+    /// Every change made through the methods below is recorded here
+    #[serde(skip)]
+    pub audit_log: AuditLog,
+    /// Who is making changes, for the audit log
+    #[serde(skip)]
+    pub user: String,
+    /// Barcodes of items added or edited since the last save
+    #[serde(skip)]
+    changed: BTreeSet<u64>,
+    /// Barcodes of items deleted since the last save
+    #[serde(skip)]
+    removed: BTreeSet<u64>,
+    /// Every change in stock, oldest first, so the stock of an item is the sum of its movements
+    #[serde(skip)]
+    pub movements: Vec<StockMovement>,
+    /// How many of the movements have been saved
+    #[serde(skip)]
+    saved_movements: usize,
+}
+
+/// Why the stock of an item changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementReason {
+    /// Stock that was there before movements were recorded
+    Opening,
+    Sale,
+    Refund,
+    GoodsIn,
+    Breakage,
+    Theft,
+    /// Counted stock didn't match the recorded stock
+    StockTake,
+    /// Stock moved over when the barcode of an item was changed
+    Relabelled,
+    /// Stock written off when the item was deleted, so its movements add up to nothing
+    Deleted,
+    /// Recorded before movements had a reason, so it isn't known why
+    Migrated,
+    /// More was sold or written off than was in stock, this much never was
+    Shortfall,
+}
+
+impl MovementReason {
+    /// The reasons stock can be adjusted by hand for
+    pub const ADJUSTMENTS: [MovementReason; 3] = [
+        MovementReason::Breakage,
+        MovementReason::Theft,
+        MovementReason::StockTake,
+    ];
+
+    /// Whether the stock change is written to the audit log,
+    /// sales and refunds are already in the sales journal
+    pub fn is_audited(&self) -> bool {
+        Self::ADJUSTMENTS.contains(self) || *self == MovementReason::GoodsIn
+    }
+}
+
+impl Display for MovementReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MovementReason::Opening => "Opening stock",
+            MovementReason::Sale => "Sale",
+            MovementReason::Refund => "Refund",
+            MovementReason::GoodsIn => "Goods in",
+            MovementReason::Breakage => "Breakage",
+            MovementReason::Theft => "Theft",
+            MovementReason::StockTake => "Stock-take correction",
+            MovementReason::Relabelled => "Barcode changed",
+            MovementReason::Deleted => "Item deleted",
+            MovementReason::Migrated => "Unknown (before reasons were recorded)",
+            MovementReason::Shortfall => "Shortfall (more than was in stock)",
+        })
+    }
+}
+
+/// A change in stock of one item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockMovement {
+    pub time: DateTime<Local>,
+    pub barcode: u64,
+    /// In grams for items sold by weight, negative when stock goes down
+    pub change: i32,
+    pub reason: MovementReason,
+    pub user: String,
+    /// Who delivered it, for goods in
+    #[serde(default)]
+    pub supplier: Option<String>,
+    /// The delivery note number, for goods in
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// What each was bought for in pence, per kg for items sold by weight, for goods in
+    #[serde(default)]
+    pub unit_cost: Option<u32>,
 }
 
 impl ItemDB {
+    pub fn new(items: Vec<Item>) -> Self {
+        let mut item_db = Self {
+            items,
+            ..Default::default()
+        };
+        item_db.reindex();
+        item_db
+    }
+
     /// Loads databse from YAML file given the path
-    pub fn load_yaml(path: &str) -> Self {
-        let string = fs::read_to_string(path).expect("Couldn't read file");
-        serde_yaml::from_str::<Self>(&string).expect("Couldn't deserialize file")
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let string = fs::read_to_string(path).map_err(|error| DataError::io(path, error))?;
+        let mut item_db =
+            serde_yaml::from_str::<Self>(&string).map_err(|error| DataError::parse(path, error))?;
+        item_db.reindex();
+        Ok(item_db)
+    }
+
+    /// Every item, in the order they were added
+    pub fn items(&self) -> &[Item] {
+        &self.items
     }
 
     /// Gets an item from the databse given a code
     pub fn get_item(&self, code: u64) -> Option<&Item> {
-        self.items.iter().find(|item| item.barcode == code)
-        // self.items.get(&barcode)
+        self.by_barcode
+            .get(&code)
+            .map(|&position| &self.items[position])
+    }
+
+    /// Items with a word in their name starting with each word of the query, in catalogue order
+    /// An empty query matches every item
+    pub fn search(&self, query: &str) -> Vec<&Item> {
+        let mut found: Option<BTreeSet<usize>> = None;
+        for word in words(query) {
+            let matches = self
+                .by_word
+                .range(word.clone()..)
+                .take_while(|(key, _)| key.starts_with(&word))
+                .flat_map(|(_, positions)| positions.iter().copied())
+                .collect::<BTreeSet<_>>();
+            found = Some(match found {
+                Some(found) => found.intersection(&matches).copied().collect(),
+                None => matches,
+            });
+        }
+
+        match found {
+            Some(positions) => positions
+                .into_iter()
+                .map(|position| &self.items[position])
+                .collect(),
+            None => self.items.iter().collect(),
+        }
+    }
+
+    /// Position of an item, if it's still exactly as given
+    fn position(&self, item: &Item) -> Option<usize> {
+        self.by_barcode
+            .get(&item.barcode)
+            .copied()
+            .filter(|&position| &self.items[position] == item)
+    }
+
+    /// Adds the item at a position to the indexes
+    fn index(&mut self, position: usize) {
+        let item = &self.items[position];
+        // with duplicate barcodes the first one is found, like the linear scan did
+        self.by_barcode.entry(item.barcode).or_insert(position);
+        for word in words(&item.name) {
+            self.by_word.entry(word).or_default().insert(position);
+        }
+    }
+
+    /// Removes the item at a position from the indexes
+    fn unindex(&mut self, position: usize) {
+        let barcode = self.items[position].barcode;
+        if self.by_barcode.get(&barcode) == Some(&position) {
+            self.by_barcode.remove(&barcode);
+        }
+        for word in words(&self.items[position].name) {
+            if let Some(positions) = self.by_word.get_mut(&word) {
+                positions.remove(&position);
+                if positions.is_empty() {
+                    self.by_word.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Finds the first item with a barcode again if it's no longer indexed,
+    /// another item may have it while there are duplicate barcodes from an old database
+    fn index_barcode(&mut self, barcode: u64) {
+        if !self.by_barcode.contains_key(&barcode) {
+            if let Some(position) = self.items.iter().position(|item| item.barcode == barcode) {
+                self.by_barcode.insert(barcode, position);
+            }
+        }
+    }
+
+    /// Moves every indexed position after a removed item up a place
+    fn shift_positions(&mut self, removed: usize) {
+        for position in self.by_barcode.values_mut() {
+            if *position > removed {
+                *position -= 1;
+            }
+        }
+        for positions in self.by_word.values_mut() {
+            let after = positions.split_off(&removed);
+            positions.extend(after.into_iter().map(|position| position - 1));
+        }
+    }
+
+    /// Builds the indexes from scratch, after loading
+    fn reindex(&mut self) {
+        self.by_barcode.clear();
+        self.by_word.clear();
+        for position in 0..self.items.len() {
+            self.index(position);
+        }
     }
 
     /// Updates quantities of items in the database from a transaction
-    pub fn update_quantities_from_transaction(&mut self, transaction: &Transaction) {
+    /// Items that can't be found are skipped so the rest still get updated
+    pub fn update_quantities_from_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<(), DataError> {
+        let mut result = Ok(());
         for transaction_item in transaction.items.iter() {
-            self.modify_quantity(&transaction_item.item, -(transaction_item.quantity as i32));
+            if let Err(error) = self.move_stock(
+                transaction_item.item.barcode,
+                -(transaction_item.stock_quantity() as i32),
+                MovementReason::Sale,
+            ) {
+                result = Err(error);
+            }
         }
+        result
     }
 
-    /// Modifies quantity of an item
-    pub fn modify_quantity(&mut self, item: &Item, count: i32) {
-        let found = self
+    /// Changes the stock of an item, recording why
+    pub fn move_stock(
+        &mut self,
+        barcode: u64,
+        change: i32,
+        reason: MovementReason,
+    ) -> Result<(), DataError> {
+        self.apply_movement(barcode, change, reason, None, None, None)
+    }
+
+    /// Books in a delivery of an item
+    /// With a unit cost, the cost price becomes the weighted average of the stock and the delivery
+    pub fn receive_goods(
+        &mut self,
+        barcode: u64,
+        quantity: u32,
+        supplier: Option<String>,
+        reference: Option<String>,
+        unit_cost: Option<u32>,
+    ) -> Result<(), DataError> {
+        let position = *self
+            .by_barcode
+            .get(&barcode)
+            .ok_or(DataError::ItemNotFound(barcode))?;
+        let mut audited = Ok(());
+        if let Some(unit_cost) = unit_cost {
+            let old = self.items[position].clone();
+            let mut new = old.clone();
+            new.cost_price = weighted_average_cost(&old, quantity, unit_cost);
+            if new.cost_price != old.cost_price {
+                audited = self.audit_log.edited(&self.user, &old, &new);
+                self.items[position] = new;
+            }
+        }
+
+        self.apply_movement(
+            barcode,
+            quantity as i32,
+            MovementReason::GoodsIn,
+            supplier,
+            reference,
+            unit_cost,
+        )?;
+        audited
+    }
+
+    /// Changes the stock and records the movement, stock can't go below zero
+    /// The stock still changes if the audit log can't be written, that error is returned after
+    fn apply_movement(
+        &mut self,
+        barcode: u64,
+        change: i32,
+        reason: MovementReason,
+        supplier: Option<String>,
+        reference: Option<String>,
+        unit_cost: Option<u32>,
+    ) -> Result<(), DataError> {
+        let position = *self
+            .by_barcode
+            .get(&barcode)
+            .ok_or(DataError::ItemNotFound(barcode))?;
+        let found = &mut self.items[position];
+
+        let new_stock = found.amount_in_stock.saturating_add_signed(change);
+        let audited = if reason.is_audited() {
+            self.audit_log.stock_adjusted(&self.user, found, new_stock)
+        } else {
+            Ok(())
+        };
+        let shortfall = new_stock as i64 - found.amount_in_stock as i64 - change as i64;
+        self.movements.push(StockMovement {
+            time: Local::now(),
+            barcode,
+            change,
+            reason,
+            user: self.user.clone(),
+            supplier,
+            reference,
+            unit_cost,
+        });
+        found.amount_in_stock = new_stock;
+        self.changed.insert(barcode);
+        // stock can't go below nothing, so the movements only add up to it with the difference
+        self.record_movement(barcode, shortfall as i32, MovementReason::Shortfall);
+        audited
+    }
+
+    /// Records a movement that the stock of an item already includes
+    fn record_movement(&mut self, barcode: u64, change: i32, reason: MovementReason) {
+        if change != 0 {
+            self.movements.push(StockMovement {
+                time: Local::now(),
+                barcode,
+                change,
+                reason,
+                user: self.user.clone(),
+                supplier: None,
+                reference: None,
+                unit_cost: None,
+            });
+        }
+    }
+
+    /// The movements of one item, newest first
+    pub fn movements_of(&self, barcode: u64) -> impl Iterator<Item = &StockMovement> {
+        self.movements
+            .iter()
+            .rev()
+            .filter(move |movement| movement.barcode == barcode)
+    }
+
+    /// Records the stock of every item as opening stock when the ledger is first used,
+    /// once it has movements everything after goes through them
+    pub fn record_opening_stock(&mut self) {
+        if !self.movements.is_empty() {
+            return;
+        }
+
+        let stock = self
             .items
-            .iter_mut()
-            .find(|i| i.barcode == item.barcode)
-            .unwrap();
+            .iter()
+            .map(|item| (item.barcode, item.amount_in_stock as i32))
+            .collect::<Vec<_>>();
+        for (barcode, stock) in stock {
+            self.record_movement(barcode, stock, MovementReason::Opening);
+        }
+    }
 
-        found.amount_in_stock = found.amount_in_stock.saturating_add_signed(count);
+    /// Adds a new item to the database, any stock it starts with is counted as opening stock
+    pub fn add_item(&mut self, item: Item) -> Result<(), DataError> {
+        if self.by_barcode.contains_key(&item.barcode) {
+            return Err(DataError::DuplicateBarcode(item.barcode));
+        }
+        let audited = self.audit_log.created(&self.user, &item);
+        self.removed.remove(&item.barcode);
+        self.changed.insert(item.barcode);
+        self.record_movement(
+            item.barcode,
+            item.amount_in_stock as i32,
+            MovementReason::Opening,
+        );
+        self.items.push(item);
+        self.index(self.items.len() - 1);
+        audited
+    }
+
+    /// Replaces an item with an edited version of it
+    /// Stock only changes through movements, so the edited item keeps the stock it has
+    pub fn replace_item(&mut self, old: &Item, mut new: Item) -> Result<(), DataError> {
+        let index = self
+            .position(old)
+            .ok_or(DataError::ItemNotFound(old.barcode))?;
+        if old.barcode != new.barcode && self.by_barcode.contains_key(&new.barcode) {
+            return Err(DataError::DuplicateBarcode(new.barcode));
+        }
+        new.amount_in_stock = old.amount_in_stock;
+        let audited = self.audit_log.edited(&self.user, old, &new);
+        self.removed.remove(&new.barcode);
+        self.changed.insert(new.barcode);
+
+        let relabelled = old.barcode != new.barcode;
+        if relabelled {
+            self.changed.remove(&old.barcode);
+            self.removed.insert(old.barcode);
+            let stock = old.amount_in_stock as i32;
+            self.record_movement(old.barcode, -stock, MovementReason::Relabelled);
+            self.record_movement(new.barcode, stock, MovementReason::Relabelled);
+        }
+
+        self.unindex(index);
+        self.items[index] = new;
+        self.index(index);
+        if relabelled {
+            self.index_barcode(old.barcode);
+        }
+        audited
     }
 
     /// Removes an item from the database
-    pub fn delete_item(&mut self, item: Item) {
+    pub fn delete_item(&mut self, item: Item) -> Result<(), DataError> {
+        let index = self
+            .position(&item)
+            .ok_or(DataError::ItemNotFound(item.barcode))?;
+        // close the ledger with what was still on hand
+        let stock = self.items[index].amount_in_stock as i32;
+        self.record_movement(item.barcode, -stock, MovementReason::Deleted);
+        // removing keeps the catalogue order, but shifts the positions of every item after it
+        self.unindex(index);
+        self.items.remove(index);
+        self.shift_positions(index);
+        self.index_barcode(item.barcode);
+        let audited = self.audit_log.deleted(&self.user, &item);
+        self.changed.remove(&item.barcode);
+        self.removed.insert(item.barcode);
+        audited
+    }
+
+    /// Whether anything changed since the last save
+    pub fn is_dirty(&self) -> bool {
+        !self.changed.is_empty()
+            || !self.removed.is_empty()
+            || self.saved_movements < self.movements.len()
+    }
+
+    /// Items added or edited since the last save, with their position in the catalogue
+    pub fn changed_items(&self) -> impl Iterator<Item = (usize, &Item)> {
         self.items
-            .remove(self.items.iter().position(|i| i == &item).unwrap());
+            .iter()
+            .enumerate()
+            .filter(|(_position, item)| self.changed.contains(&item.barcode))
+    }
+
+    /// A barcode more than one item has, which only the first of them is found by
+    pub fn duplicate_barcode(&self) -> Option<u64> {
+        self.items
+            .iter()
+            .enumerate()
+            .find(|(position, item)| self.by_barcode.get(&item.barcode) != Some(position))
+            .map(|(_position, item)| item.barcode)
+    }
+
+    /// Barcodes of items deleted since the last save
+    pub fn removed_barcodes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.removed.iter().copied()
+    }
+
+    /// Movements recorded since the last save
+    pub fn unsaved_movements(&self) -> &[StockMovement] {
+        &self.movements[self.saved_movements..]
+    }
+
+    /// Sets the movements loaded from storage, which count as saved
+    pub fn set_movements(&mut self, movements: Vec<StockMovement>) {
+        self.saved_movements = movements.len();
+        self.movements = movements;
+    }
+
+    /// Counts every item and movement as changed, so the next save writes all of them
+    pub fn mark_all_changed(&mut self) {
+        self.changed = self.items.iter().map(|item| item.barcode).collect();
+        self.saved_movements = 0;
+    }
+
+    /// Forgets the changes once they've been saved
+    pub fn mark_saved(&mut self) {
+        self.changed.clear();
+        self.removed.clear();
+        self.saved_movements = self.movements.len();
     }
 
     /// Saves itself to a YAML file
-    pub fn save_yaml(&self, path: &str) {
-        fs::write(
-            path,
-            serde_yaml::to_string::<Self>(self).expect("Couldn't serialize Item Database"),
-        )
-        .unwrap();
+    pub fn save_yaml(&self, path: &str) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(path, &string).map_err(|error| DataError::io(path, error))
+    }
+}
+
+/// The lowercase words in a name or search query
+fn words(string: &str) -> impl Iterator<Item = String> + '_ {
+    string
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+/// The cost price of an item once a delivery at a unit cost is added to its stock
+/// Without a known cost price or any stock, the delivery sets it
+fn weighted_average_cost(item: &Item, quantity: u32, unit_cost: u32) -> u32 {
+    let stock = item.amount_in_stock as u64;
+    let total = stock + quantity as u64;
+    if item.cost_price == 0 || stock == 0 || total == 0 {
+        return unit_cost;
+    }
+    let value = stock * item.cost_price as u64 + quantity as u64 * unit_cost as u64;
+    // rounded to the nearest penny
+    ((value + total / 2) / total) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(amount_in_stock: u32, cost_price: u32) -> Item {
+        Item {
+            barcode: 1,
+            amount_in_stock,
+            cost_price,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn weighted_average() {
+        assert_eq!(weighted_average_cost(&item(10, 100), 10, 200), 150);
+        // 302p over 3 is 100.67p
+        assert_eq!(weighted_average_cost(&item(1, 100), 2, 101), 101);
+        assert_eq!(weighted_average_cost(&item(3, 100), 1, 101), 100);
+    }
+
+    #[test]
+    fn delivery_sets_unknown_cost() {
+        assert_eq!(weighted_average_cost(&item(10, 0), 5, 80), 80);
+        assert_eq!(weighted_average_cost(&item(0, 100), 5, 80), 80);
+    }
+
+    #[test]
+    fn receiving_goods_moves_cost() {
+        let directory = std::env::temp_dir().join("item_db_receiving_goods");
+        fs::create_dir_all(&directory).unwrap();
+        let mut item_db = ItemDB::new(vec![item(4, 100)]);
+        item_db.audit_log =
+            AuditLog::load(directory.join("audit_log.jsonl").to_str().unwrap()).unwrap();
+        item_db
+            .receive_goods(1, 12, None, Some("DN-1".to_owned()), Some(120))
+            .unwrap();
+        let received = item_db.get_item(1).unwrap();
+        assert_eq!(received.amount_in_stock, 16);
+        assert_eq!(received.cost_price, 115);
+
+        let movement = item_db.movements.last().unwrap();
+        assert_eq!(movement.reason, MovementReason::GoodsIn);
+        assert_eq!((movement.change, movement.unit_cost), (12, Some(120)));
+
+        // no cost on the delivery note leaves it as it was
+        item_db.receive_goods(1, 4, None, None, None).unwrap();
+        assert_eq!(item_db.get_item(1).unwrap().cost_price, 115);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn named(barcode: u64, name: &str) -> Item {
+        Item {
+            barcode,
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn barcodes(items: Vec<&Item>) -> Vec<u64> {
+        items.iter().map(|item| item.barcode).collect()
+    }
+
+    #[test]
+    fn indexes_follow_edits_and_deletes() {
+        let mut item_db = ItemDB::new(vec![
+            named(1, "Kofola 2l"),
+            named(2, "Kofola 500ml"),
+            named(3, "Tatranka"),
+        ]);
+
+        item_db.delete_item(named(1, "Kofola 2l")).unwrap();
+        assert!(item_db.get_item(1).is_none());
+        assert_eq!(item_db.get_item(3).unwrap().name, "Tatranka");
+        assert_eq!(barcodes(item_db.search("kofola")), vec![2]);
+
+        item_db
+            .replace_item(&named(2, "Kofola 500ml"), named(4, "Kofola Zero 500ml"))
+            .unwrap();
+        assert!(item_db.get_item(2).is_none());
+        assert_eq!(item_db.get_item(4).unwrap().name, "Kofola Zero 500ml");
+        assert_eq!(barcodes(item_db.search("zero")), vec![4]);
+        assert_eq!(barcodes(item_db.search("tat")), vec![3]);
+    }
+
+    #[test]
+    fn duplicate_barcode_found_again() {
+        // only old databases can have these
+        let mut item_db = ItemDB::new(vec![named(1, "Old"), named(1, "New")]);
+        item_db.delete_item(named(1, "Old")).unwrap();
+        assert_eq!(item_db.get_item(1).unwrap().name, "New");
+    }
+
+    #[test]
+    fn only_manual_stock_changes_audited() {
+        let mut item_db = ItemDB::new(vec![item(10, 100)]);
+        item_db.move_stock(1, -2, MovementReason::Sale).unwrap();
+        item_db.move_stock(1, 1, MovementReason::Refund).unwrap();
+        assert!(item_db.audit_log.entries.is_empty());
+
+        item_db.move_stock(1, -1, MovementReason::Breakage).unwrap();
+        assert_eq!(item_db.audit_log.entries.len(), 1);
+        assert_eq!(item_db.movements.len(), 3);
+    }
+
+    #[test]
+    fn selling_more_than_in_stock_records_shortfall() {
+        let mut item_db = ItemDB::new(vec![item(2, 100)]);
+        item_db.move_stock(1, -5, MovementReason::Sale).unwrap();
+        assert_eq!(item_db.get_item(1).unwrap().amount_in_stock, 0);
+
+        let changes = item_db
+            .movements
+            .iter()
+            .map(|movement| (movement.reason, movement.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![(MovementReason::Sale, -5), (MovementReason::Shortfall, 3)]
+        );
+    }
+
+    #[test]
+    fn opening_stock_only_for_a_new_ledger() {
+        let mut item_db = ItemDB::new(vec![item(4, 100)]);
+        item_db.record_opening_stock();
+        assert_eq!(item_db.movements.len(), 1);
+        assert_eq!(item_db.movements[0].change, 4);
+
+        item_db.items[0].amount_in_stock = 6;
+        item_db.record_opening_stock();
+        assert_eq!(item_db.movements.len(), 1);
     }
 }
@@ -3,36 +3,90 @@
 #![feature(iter_array_chunks)]
 #![feature(array_chunks)]
 
+mod audit_log;
+mod audit_view;
+mod barcode;
+mod cash_drawer;
+mod cash_up_view;
+mod config;
+mod data_error;
 mod inventory_view;
 mod item;
 mod item_creation_view;
 mod item_db;
+mod parked_transactions;
+mod payment_terminal;
+mod pdf;
+mod promotions;
+mod purchase_orders;
+mod sales_journal;
+mod security;
 mod settings_view;
+mod sqlite_storage;
+mod stock_take;
+mod stock_view;
+mod storage;
+mod suppliers;
+mod suppliers_view;
 mod theme;
+mod toast;
 mod transaction;
 mod transactions_view;
+mod users;
 mod utils;
+mod valuation;
 
+use audit_log::AuditLog;
+use audit_view::{AuditMessage, AuditView};
+use cash_drawer::CashDrawer;
+use cash_up_view::{CashUpMessage, CashUpView};
+use config::Config;
+use data_error::DataError;
 use inventory_view::{InventoryMessage, InventoryView};
 use item::Item;
 use item_creation_view::{ItemCreationMessage, ItemCreationView};
+use parked_transactions::ParkedTransactions;
+use promotions::Promotions;
+use purchase_orders::PurchaseOrders;
+use sales_journal::SalesJournal;
+use security::{Lockout, SecurityLog};
+use serde::{Deserialize, Serialize};
 use settings_view::{SettingsMessage, SettingsView};
-use theme::MyTheme;
+use sqlite_storage::SqliteStorage;
+use stock_take::StockTake;
+use stock_view::{StockMessage, StockView};
+use storage::{Storage, StorageBackend, YamlStorage};
+use suppliers::Suppliers;
+use suppliers_view::{SuppliersMessage, SuppliersView};
+use theme::{ButtonStyle, ContainerStyle, MyTheme};
+use toast::{ToastMessage, Toasts};
+
+use std::{
+    fmt::Display,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
 
 use iced::{
     alignment::Horizontal,
     executor,
     subscription::events,
+    time,
     widget::text_input::{focus, Id},
-    widget::{button, column, text, text_input},
+    widget::{button, column, container, row, text, text_input, Column, Space},
     window, Alignment, Application, Color, Command, Event, Length, Renderer, Settings,
     Subscription,
 };
 
-use iced_aw::{Modal, TabLabel, Tabs};
+use iced_aw::{
+    floating_element::{Anchor, Offset},
+    FloatingElement, Modal, TabLabel, Tabs,
+};
 
 use transactions_view::{TransactionsMessage, TransactionsView};
-use utils::{encrypt, get_encrypted_password, notify};
+use users::{valid_pin, Action, Role, User, Users};
+use utils::notify;
 
 use crate::item_db::ItemDB;
 
@@ -56,33 +110,172 @@ pub type Element<'a> = iced::Element<'a, Message, Renderer<MyTheme>>;
 
 /// The state model of the application
 pub struct App {
+    config: Config,
     item_db: ItemDB,
+    sales_journal: SalesJournal,
+    cash_drawer: CashDrawer,
+    users: Users,
+    suppliers: Suppliers,
+    lockout: Lockout,
+    security_log: SecurityLog,
+    /// Where the items, sales, users and suppliers are saved
+    storage: Box<dyn Storage>,
+    /// Why the item database couldn't be loaded, the recovery screen is shown until it's fixed
+    item_db_error: Option<DataError>,
+    /// Why the storage couldn't be opened or the sales, users or suppliers loaded
+    /// Nothing can be done about it in the app, so it's shown until the app is closed
+    load_error: Option<DataError>,
+    /// Shown as banners above the tabs until dismissed
+    errors: Vec<DataError>,
+    toasts: Toasts,
 
     transactions_view: TransactionsView,
     inventory_view: InventoryView,
     item_creation_view: ItemCreationView,
+    stock_view: StockView,
+    purchase_orders: PurchaseOrders,
+    suppliers_view: SuppliersView,
+    cash_up_view: CashUpView,
+    audit_view: AuditView,
     settings_view: SettingsView,
 
     active_view: ViewIndex,
-    desired_view: Option<ViewIndex>,
+    /// Nobody can use the till until someone logs in
+    current_user: Option<User>,
+    /// Someone with a higher role who unlocked the view that's open
+    override_user: Option<User>,
+    /// When the last key or mouse input came in, to lock the till once it's left alone
+    last_activity: Instant,
+    pending_action: Option<PendingAction>,
+    username_input: String,
+    username_input_id: Id,
     password_input: String,
     password_input_id: Id,
+    /// Only used when making the first admin
+    confirm_password_input: String,
     should_exit: bool,
 }
 
 impl Default for App {
     fn default() -> Self {
+        // broken files that aren't the main data are only reported, the app starts without them
+        let mut errors = Vec::new();
+        let config = Config::load_yaml("./config.yaml").unwrap_or_else(|error| {
+            errors.push(error);
+            Config::default()
+        });
+
+        let mut load_error = None;
+        let mut storage = match open_storage(&config) {
+            Ok(storage) => storage,
+            Err(error) => {
+                load_error = Some(error);
+                // nothing is saved while there's a load error, so this is only read from
+                Box::new(yaml_storage(&config))
+            }
+        };
+
+        let (mut item_db, item_db_error) = match storage.load_items() {
+            Ok(mut item_db) => {
+                // stock from before movements were recorded
+                item_db.record_opening_stock();
+                (item_db, None)
+            }
+            Err(error) => (ItemDB::default(), Some(error)),
+        };
+        item_db.audit_log = AuditLog::load("./audit_log.jsonl").unwrap_or_else(|error| {
+            errors.push(error);
+            AuditLog::new("./audit_log.jsonl")
+        });
+        let sales_journal = match storage.load_sales() {
+            Ok(records) => SalesJournal::new(records),
+            Err(error) => {
+                load_error.get_or_insert(error);
+                SalesJournal::default()
+            }
+        };
+        let mut users = match storage.load_users() {
+            Ok(users) => users,
+            Err(error) => {
+                load_error.get_or_insert(error);
+                Users::default()
+            }
+        };
+        users.import_legacy_password();
+        let mut suppliers = match storage.load_suppliers() {
+            Ok(suppliers) => suppliers,
+            Err(error) => {
+                load_error.get_or_insert(error);
+                Suppliers::default()
+            }
+        };
+        if item_db_error.is_none() && load_error.is_none() {
+            if let Err(error) = suppliers.import_legacy_names(&mut item_db) {
+                load_error = Some(error);
+            }
+        }
+        // a broken count is only reported, the next count starts over
+        let stock_take = StockTake::load_yaml("./stock_take.yaml").unwrap_or_else(|error| {
+            errors.push(error);
+            StockTake::new("./stock_take.yaml")
+        });
+        let purchase_orders =
+            PurchaseOrders::load_yaml("./purchase_orders.yaml").unwrap_or_else(|error| {
+                errors.push(error);
+                PurchaseOrders::new("./purchase_orders.yaml")
+            });
+        let cash_drawer = CashDrawer::load_yaml("./shift.yaml").unwrap_or_else(|error| {
+            errors.push(error);
+            CashDrawer::new("./shift.yaml")
+        });
+        let lockout = Lockout::load_yaml("./lockout.yaml").unwrap_or_else(|error| {
+            errors.push(error);
+            Lockout::new("./lockout.yaml")
+        });
+        let parked =
+            ParkedTransactions::load_yaml("./parked_transactions.yaml").unwrap_or_else(|error| {
+                errors.push(error);
+                ParkedTransactions::new("./parked_transactions.yaml")
+            });
+        let promotions = Promotions::load_yaml("./promotions.yaml").unwrap_or_else(|error| {
+            errors.push(error);
+            Promotions::default()
+        });
+
         Self {
-            item_db: ItemDB::load_yaml("./item_db.yaml"),
+            config,
+            storage,
+            item_db,
+            item_db_error,
+            load_error,
+            errors,
+            toasts: Toasts::default(),
+            sales_journal,
+            cash_drawer,
+            users,
+            suppliers,
+            lockout,
+            security_log: SecurityLog::new("./security.log"),
             should_exit: false,
-            transactions_view: TransactionsView::default(),
-            inventory_view: InventoryView::default(),
+            transactions_view: TransactionsView::new(parked, promotions),
+            inventory_view: InventoryView::new(stock_take),
             item_creation_view: ItemCreationView::default(),
+            stock_view: StockView::default(),
+            purchase_orders,
+            suppliers_view: SuppliersView::default(),
+            cash_up_view: CashUpView::default(),
+            audit_view: AuditView::default(),
             settings_view: SettingsView::default(),
             active_view: ViewIndex::Transactions,
+            current_user: None,
+            override_user: None,
+            last_activity: Instant::now(),
+            username_input: Default::default(),
+            username_input_id: Id::new("username"),
             password_input: Default::default(),
             password_input_id: Id::new("password"),
-            desired_view: None,
+            confirm_password_input: Default::default(),
+            pending_action: None,
         }
     }
 }
@@ -91,34 +284,89 @@ impl Default for App {
 #[derive(Debug, Clone)]
 pub enum Message {
     EventOccured(Event),
+    /// Sent every second to check if the till has been left alone
+    Tick,
 
     Transactions(TransactionsMessage),
     Inventory(InventoryMessage),
     ItemCreation(ItemCreationMessage),
     EditItem(Item),
+    Stock(StockMessage),
+    Suppliers(SuppliersMessage),
+    CashUp(CashUpMessage),
+    Audit(AuditMessage),
     Settings(SettingsMessage),
+    Toast(ToastMessage),
 
     ClosePasswordModal,
+    UsernameChanged(String),
     PasswordChanged(String),
+    ConfirmPasswordChanged(String),
+    /// Makes the first admin when there are no users yet
+    CreateFirstAdmin,
     SetActiveView(ViewIndex),
+    /// Runs the inner message only if the user is allowed to do the action,
+    /// otherwise asks for someone who is
+    RequirePermission(Action, Box<Message>),
+    Logout,
+
+    /// Shows an error banner
+    DataError(DataError),
+    DismissError(usize),
+    /// Starts over with no items when the item database couldn't be loaded
+    CreateEmptyItemDB,
+    /// Replaces the item database with one of its numbered backups
+    RestoreItemDBBackup(usize),
+    Quit,
+}
+
+/// Shows an error banner from a view's update
+pub fn show_error(error: DataError) -> Command<Message> {
+    Command::perform(async move { error }, Message::DataError)
+}
+
+/// Something waiting for someone allowed to do it to log in
+#[derive(Debug, Clone)]
+enum PendingAction {
+    OpenView(ViewIndex),
+    Message(Action, Box<Message>),
 }
 
 /// Utility enum with predefined indexes
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub enum ViewIndex {
     Transactions = 0,
     Inventory = 1,
     ItemCreation = 2,
-    Settings = 3,
+    Stock = 3,
+    Suppliers = 4,
+    CashUp = 5,
+    Audit = 6,
+    Settings = 7,
 }
 
 impl ViewIndex {
+    pub const ALL: [ViewIndex; 8] = [
+        ViewIndex::Transactions,
+        ViewIndex::Inventory,
+        ViewIndex::ItemCreation,
+        ViewIndex::Stock,
+        ViewIndex::Suppliers,
+        ViewIndex::CashUp,
+        ViewIndex::Audit,
+        ViewIndex::Settings,
+    ];
+
     pub fn to_usize(&self) -> usize {
         match self {
             ViewIndex::Transactions => 0,
             ViewIndex::Inventory => 1,
             ViewIndex::ItemCreation => 2,
-            ViewIndex::Settings => 3,
+            ViewIndex::Stock => 3,
+            ViewIndex::Suppliers => 4,
+            ViewIndex::CashUp => 5,
+            ViewIndex::Audit => 6,
+            ViewIndex::Settings => 7,
         }
     }
     pub fn from_usize(usize: usize) -> Self {
@@ -126,12 +374,31 @@ impl ViewIndex {
             0 => Self::Transactions,
             1 => Self::Inventory,
             2 => Self::ItemCreation,
-            3 => Self::Settings,
+            3 => Self::Stock,
+            4 => Self::Suppliers,
+            5 => Self::CashUp,
+            6 => Self::Audit,
+            7 => Self::Settings,
             _ => panic!("oh no"),
         }
     }
 }
 
+impl Display for ViewIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ViewIndex::Transactions => "Transactions",
+            ViewIndex::Inventory => "Inventory",
+            ViewIndex::ItemCreation => "Item Creation",
+            ViewIndex::Stock => "Stock",
+            ViewIndex::Suppliers => "Suppliers",
+            ViewIndex::CashUp => "Cash Up",
+            ViewIndex::Audit => "Audit",
+            ViewIndex::Settings => "Settings",
+        })
+    }
+}
+
 impl Application for App {
     type Executor = executor::Default;
     type Message = Message;
@@ -139,11 +406,16 @@ impl Application for App {
     type Flags = ();
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
-        (Self::default(), Command::none())
+        let app = Self::default();
+        let command = focus(app.username_input_id.clone());
+        (app, command)
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        events().map(Message::EventOccured)
+        Subscription::batch([
+            events().map(Message::EventOccured),
+            time::every(Duration::from_secs(1)).map(|_| Message::Tick),
+        ])
     }
 
     fn theme(&self) -> Self::Theme {
@@ -151,53 +423,123 @@ impl Application for App {
     }
 
     fn title(&self) -> String {
-        String::from("Swansea Food Centre POS Software")
+        match &self.current_user {
+            Some(user) => format!(
+                "Swansea Food Centre POS Software - {} ({})",
+                user.name, user.role
+            ),
+            None => String::from("Swansea Food Centre POS Software"),
+        }
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
         let mut command = Command::none();
+        // whoever approved an action is on the hook for it too
+        self.item_db.user = self.acting_user();
+        // including any raised by the last message if it returned early
+        self.show_toasts();
         match message {
             Message::EventOccured(event) => {
-                // if transactions view is open, send the event to it
-                if self.active_view == ViewIndex::Transactions {
-                    self.transactions_view.update(
+                if matches!(
+                    event,
+                    Event::Keyboard(_) | Event::Mouse(_) | Event::Touch(_)
+                ) {
+                    self.last_activity = Instant::now();
+                }
+
+                // if transactions view is open, send the event to it, unless someone is typing a PIN
+                if self.active_view == ViewIndex::Transactions
+                    && self.current_user.is_some()
+                    && self.pending_action.is_none()
+                {
+                    command = self.transactions_view.update(
                         TransactionsMessage::EventOccured(event.clone()),
                         &mut self.item_db,
+                        &mut self.sales_journal,
+                        &self.config,
+                        &self.security_log,
                     )
                 }
                 // quit and save upon receiving signal to close
                 if let Event::Window(window::Event::CloseRequested) = event {
-                    self.item_db.save_yaml("./item_db.yaml");
-                    self.should_exit = true;
-                    println!("Closing");
+                    command = self.update(Message::Quit);
                 }
             }
 
             Message::Transactions(message) => {
-                self.transactions_view.update(message, &mut self.item_db)
+                command = self.transactions_view.update(
+                    message,
+                    &mut self.item_db,
+                    &mut self.sales_journal,
+                    &self.config,
+                    &self.security_log,
+                )
+            }
+            Message::Inventory(message) => {
+                if let Err(error) = self.inventory_view.update(message, &mut self.item_db) {
+                    self.errors.push(error);
+                }
             }
-            Message::Inventory(message) => self.inventory_view.update(message, &mut self.item_db),
             Message::ItemCreation(message) => {
-                if let Some(message) = self.item_creation_view.update(message, &mut self.item_db) {
-                    self.update(message);
+                if let Some(message) =
+                    self.item_creation_view
+                        .update(message, &mut self.item_db, &self.suppliers)
+                {
+                    command = self.update(message);
                 }
             }
-            Message::Settings(message) => self.settings_view.update(message),
-            Message::SetActiveView(new_index) => {
-                const PASSWORD_PROTECTED_VIEWS: &'static [ViewIndex] = &[
-                    ViewIndex::Inventory,
-                    ViewIndex::ItemCreation,
-                    ViewIndex::Settings,
-                ];
-
-                // request password only if not already on a password protected view
-                if PASSWORD_PROTECTED_VIEWS.contains(&new_index)
-                    && !PASSWORD_PROTECTED_VIEWS.contains(&self.active_view)
+            Message::Stock(message) => {
+                if let Err(error) = self.stock_view.update(
+                    message,
+                    &mut self.item_db,
+                    &mut self.purchase_orders,
+                    &self.suppliers,
+                ) {
+                    self.errors.push(error);
+                }
+            }
+            Message::Suppliers(message) => {
+                self.suppliers_view
+                    .update(message, &mut self.suppliers, &self.item_db)
+            }
+            Message::CashUp(message) => {
+                if let Err(error) =
+                    self.cash_up_view
+                        .update(message, &mut self.cash_drawer, &self.sales_journal)
                 {
-                    self.desired_view = Some(new_index);
-                    command = focus(self.password_input_id.clone());
+                    self.errors.push(error);
+                }
+            }
+            Message::Audit(message) => self.audit_view.update(message),
+            Message::Settings(message) => {
+                if self.permitted(self.users.permissions.action_role(Action::ChangeSettings)) {
+                    let save = message == SettingsMessage::SaveSettings;
+                    self.settings_view
+                        .update(message, &mut self.config, &mut self.users);
+                    if save && self.save_config() {
+                        toast::success("Settings saved", "");
+                    }
                 } else {
+                    toast::error("Access denied", "You can't change settings");
+                }
+            }
+            Message::SetActiveView(new_index) => {
+                if self.active_view == ViewIndex::Settings && new_index != ViewIndex::Settings {
+                    self.save_config();
+                }
+                let required = self.users.permissions.view_role(new_index);
+                let own_role = self.current_user.as_ref().map(|user| user.role);
+
+                if own_role.map_or(false, |role| role >= required) {
+                    // back to what the user can do by themselves
+                    self.override_user = None;
+                    self.active_view = new_index;
+                } else if self.permitted(required) {
+                    // still unlocked by whoever opened the last view
                     self.active_view = new_index;
+                } else {
+                    self.pending_action = Some(PendingAction::OpenView(new_index));
+                    command = focus(self.username_input_id.clone());
                 }
 
                 // make sure we're not accidentally editing the last item
@@ -205,33 +547,207 @@ impl Application for App {
                     self.item_creation_view.set_item(None);
                 }
             }
+            Message::Tick => {
+                self.toasts.expire();
+
+                // saving at most once a second keeps a busy sale from writing on every scan
+                if self.item_db.is_dirty() && self.can_save() {
+                    let result = self.storage.save_items(&mut self.item_db);
+                    self.report(result);
+                }
+
+                let idle_limit = Duration::from_secs(self.config.idle_lock_minutes as u64 * 60);
+                if self.config.idle_lock_minutes > 0
+                    && self.current_user.is_some()
+                    && self.last_activity.elapsed() >= idle_limit
+                {
+                    self.log_security(format!(
+                        "Locked after {} minutes idle",
+                        self.config.idle_lock_minutes
+                    ));
+                    command = self.update(Message::Logout);
+                }
+            }
             Message::ClosePasswordModal => {
-                // check encrypted input rather than decrypt the password to allow one-way encryption for
-                // better security
-                if encrypt(&self.password_input) == get_encrypted_password() {
-                    self.active_view = self.desired_view.unwrap();
+                // never left in the input, even when the login is refused
+                let pin = std::mem::take(&mut self.password_input);
+                if let Some(seconds) = self.lockout.seconds_remaining() {
+                    toast::error(
+                        "Locked",
+                        &format!("Too many wrong PINs, try again in {} seconds", seconds),
+                    );
+                    return command;
+                }
+
+                let pending_action = self.pending_action.take();
+                let name = std::mem::take(&mut self.username_input);
+                let user = match self.users.authenticate(&name, &pin) {
+                    Some(user) => user,
+                    None => {
+                        self.log_security(format!("Failed login as {}", name.trim()));
+                        let locked_for = self.lockout.record_failure(&self.config);
+                        let result = self.lockout.save_yaml();
+                        self.report(result);
+                        match locked_for {
+                            Some(duration) => {
+                                self.log_security(format!(
+                                    "Login locked for {} seconds",
+                                    duration.num_seconds()
+                                ));
+                                toast::error(
+                                    "Locked",
+                                    &format!(
+                                        "Too many wrong PINs, try again in {} seconds",
+                                        duration.num_seconds()
+                                    ),
+                                );
+                            }
+                            None => toast::error("Access denied", "Incorrect name or PIN"),
+                        }
+                        return command;
+                    }
+                };
+                self.lockout.record_success();
+                let result = self.lockout.save_yaml();
+                self.report(result);
+
+                match pending_action {
+                    // nobody was logged in, so this was the login prompt
+                    _ if self.current_user.is_none() => {
+                        self.log_security(format!("{} logged in as {}", user.name, user.role));
+                        self.sales_journal.cashier = user.name.clone();
+                        self.current_user = Some(user);
+                    }
+                    Some(PendingAction::OpenView(view))
+                        if user.role >= self.users.permissions.view_role(view) =>
+                    {
+                        self.log_security(format!("{} unlocked the {:?} view", user.name, view));
+                        self.override_user = Some(user);
+                        self.active_view = view;
+                    }
+                    Some(PendingAction::Message(action, message))
+                        if user.role >= self.users.permissions.action_role(action) =>
+                    {
+                        self.log_security(format!("{} allowed someone to {}", user.name, action));
+                        // only for this one message
+                        let previous = self.override_user.replace(user);
+                        command = self.update(*message);
+                        self.override_user = previous;
+                    }
+                    _ => toast::error(
+                        "Access denied",
+                        &format!("{} isn't allowed to do that", user.name),
+                    ),
+                }
+            }
+            Message::RequirePermission(action, message) => {
+                if self.permitted(self.users.permissions.action_role(action)) {
+                    command = self.update(*message);
                 } else {
-                    notify("Access denied", "Incorrect Password");
+                    self.pending_action = Some(PendingAction::Message(action, message));
+                    command = focus(self.username_input_id.clone());
                 }
-                self.desired_view = None;
-                self.password_input.clear();
             }
+            Message::UsernameChanged(v) => self.username_input = v,
             Message::PasswordChanged(v) => self.password_input = v,
+            Message::ConfirmPasswordChanged(v) => self.confirm_password_input = v,
+            Message::CreateFirstAdmin => {
+                if self.username_input.trim().is_empty() {
+                    toast::warning("Invalid user", "Enter a name");
+                } else if !valid_pin(&self.password_input) {
+                    toast::warning("Invalid user", "The PIN has to be at least 4 digits");
+                } else if self.password_input != self.confirm_password_input {
+                    toast::warning("Invalid user", "The PINs don't match");
+                } else {
+                    self.users
+                        .set_user(&self.username_input, &self.password_input, Role::Admin);
+                    command = self.update(Message::ClosePasswordModal);
+                }
+                self.confirm_password_input.clear();
+            }
+            Message::Logout => {
+                if let Some(user) = &self.current_user {
+                    self.log_security(format!("{} logged out", user.name));
+                }
+                self.pending_action = None;
+                self.current_user = None;
+                self.override_user = None;
+                self.sales_journal.cashier.clear();
+                self.save_config();
+                self.active_view = ViewIndex::Transactions;
+                command = focus(self.username_input_id.clone());
+            }
             Message::EditItem(item) => {
                 self.active_view = ViewIndex::ItemCreation;
                 self.item_creation_view.set_item(Some(item));
             }
+            Message::DataError(error) => self.errors.push(error),
+            Message::DismissError(index) => {
+                if index < self.errors.len() {
+                    self.errors.remove(index);
+                }
+            }
+            Message::CreateEmptyItemDB => {
+                let audit_log = std::mem::take(&mut self.item_db.audit_log);
+                self.item_db = ItemDB::default();
+                self.item_db.audit_log = audit_log;
+                self.save_recovered_item_db();
+            }
+            Message::RestoreItemDBBackup(index) => {
+                match ItemDB::load_yaml(&format!("./item_db.yaml.{}", index)) {
+                    Ok(mut item_db) => {
+                        item_db.audit_log = std::mem::take(&mut self.item_db.audit_log);
+                        item_db.record_opening_stock();
+                        if let Err(error) = self.suppliers.import_legacy_names(&mut item_db) {
+                            self.errors.push(error);
+                        }
+                        self.item_db = item_db;
+                        self.save_recovered_item_db();
+                    }
+                    Err(error) => self.item_db_error = Some(error),
+                }
+            }
+            Message::Quit => {
+                // never overwrite a database that couldn't be loaded with an empty one
+                if self.item_db.is_dirty() && self.can_save() {
+                    if let Err(error) = self.storage.save_items(&mut self.item_db) {
+                        eprintln!("{}", error);
+                    }
+                }
+                if self.settings_view.take_config_changed() {
+                    if let Err(error) = self.config.save_yaml("./config.yaml") {
+                        eprintln!("{}", error);
+                    }
+                }
+                self.should_exit = true;
+                println!("Closing");
+            }
+            Message::Toast(message) => self.toasts.update(message),
         }
+        self.save_changes();
+        self.show_toasts();
         command
     }
 
     fn view(&self) -> Element {
-        let content: Element = Tabs::new(self.active_view.to_usize(), |index| {
+        if let Some(error) = &self.load_error {
+            return render_recovery(error, &self.config, false);
+        }
+        if let Some(error) = &self.item_db_error {
+            return render_recovery(error, &self.config, true);
+        }
+
+        let tabs: Element = Tabs::new(self.active_view.to_usize(), |index| {
             Message::SetActiveView(ViewIndex::from_usize(index))
         })
         .push(
             TabLabel::Text("Transactions".to_string()),
-            self.transactions_view.view(&self.item_db),
+            self.transactions_view.view(
+                &self.item_db,
+                &self.sales_journal,
+                &self.config,
+                &self.toasts,
+            ),
         )
         .push(
             TabLabel::Text("Inventory".to_string()),
@@ -239,27 +755,101 @@ impl Application for App {
         )
         .push(
             TabLabel::Text("Item Creation".to_string()),
-            self.item_creation_view.view(),
+            self.item_creation_view.view(&self.suppliers),
+        )
+        .push(
+            TabLabel::Text("Stock".to_string()),
+            self.stock_view.view(&self.item_db, &self.purchase_orders),
+        )
+        .push(
+            TabLabel::Text("Suppliers".to_string()),
+            self.suppliers_view.view(&self.suppliers, &self.item_db),
+        )
+        .push(
+            TabLabel::Text("Cash Up".to_string()),
+            self.cash_up_view.view(&self.cash_drawer),
+        )
+        .push(
+            TabLabel::Text("Audit".to_string()),
+            self.audit_view.view(&self.item_db),
         )
         .push(
             TabLabel::Text("Settings".to_string()),
-            self.settings_view.view(),
+            self.settings_view.view(&self.config, &self.users),
         )
         .text_size(20)
         .tab_bar_height(iced::Length::Shrink)
         .into();
 
-        let element: Element = Modal::new(self.desired_view.is_some(), content, || {
-            render_password_prompt(&self.password_input, self.password_input_id.clone())
+        let content: Element = Column::with_children(
+            self.errors
+                .iter()
+                .enumerate()
+                .map(|(index, error)| render_error_banner(index, error))
+                .collect(),
+        )
+        .push(tabs)
+        .into();
+
+        let title = match (
+            self.lockout.seconds_remaining(),
+            &self.current_user,
+            &self.pending_action,
+        ) {
+            (Some(seconds), _, _) => {
+                format!("Too many wrong PINs, try again in {} seconds", seconds)
+            }
+            (None, None, _) => "Log in".to_owned(),
+            (None, Some(_), Some(PendingAction::OpenView(view))) => format!(
+                "A {} needs to log in to open this",
+                self.users.permissions.view_role(*view)
+            ),
+            (None, Some(_), Some(PendingAction::Message(action, _))) => format!(
+                "A {} needs to log in to {}",
+                self.users.permissions.action_role(*action),
+                action
+            ),
+            (None, Some(_), None) => String::new(),
+        };
+
+        let show_modal = self.current_user.is_none() || self.pending_action.is_some();
+        let element: Element = Modal::new(show_modal, content, move || {
+            let prompt = if self.users.needs_setup() {
+                render_setup_prompt(
+                    &self.username_input,
+                    self.username_input_id.clone(),
+                    &self.password_input,
+                    &self.confirm_password_input,
+                )
+            } else {
+                render_password_prompt(
+                    title.clone(),
+                    &self.username_input,
+                    self.username_input_id.clone(),
+                    &self.password_input,
+                    self.password_input_id.clone(),
+                )
+            };
+            column![self.toasts.view_active(), prompt]
+                .spacing(10)
+                .width(Length::Units(300))
+                .into()
         })
         .into();
 
-        element
+        // only one overlay can be shown at a time, so while a modal is open it shows the toasts
+        let transactions_modal =
+            self.active_view == ViewIndex::Transactions && self.transactions_view.has_modal();
+        FloatingElement::new(element, || self.toasts.view())
+            .anchor(Anchor::NorthEast)
+            .offset(Offset { x: 20.0, y: 60.0 })
+            .hide(show_modal || transactions_modal)
+            .into()
     }
 
     // The user can change the scale factor in the settings
     fn scale_factor(&self) -> f64 {
-        self.settings_view.ui_scale
+        self.config.ui_scale
     }
 
     // Handle exiting to save the database properly
@@ -268,12 +858,237 @@ impl Application for App {
     }
 }
 
-/// Utility to render the password modal
-fn render_password_prompt<'a>(password_input: &String, id: Id) -> Element<'a> {
+impl App {
+    /// The highest role of the logged in user and whoever unlocked the open view
+    fn role(&self) -> Option<Role> {
+        self.current_user
+            .iter()
+            .chain(self.override_user.iter())
+            .map(|user| user.role)
+            .max()
+    }
+
+    /// Who to record as making a change, including who approved it if they aren't the same
+    fn acting_user(&self) -> String {
+        match (&self.current_user, &self.override_user) {
+            (Some(user), Some(approver)) if user.name != approver.name => {
+                format!("{} (approved by {})", user.name, approver.name)
+            }
+            (Some(user), _) => user.name.clone(),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// Whether someone with at least the role is at the till
+    fn permitted(&self, required: Role) -> bool {
+        self.role().map_or(false, |role| role >= required)
+    }
+
+    /// Puts toasts raised anywhere since the last update on screen
+    fn show_toasts(&mut self) {
+        for toast in self.toasts.collect() {
+            if self.config.os_notifications {
+                notify(&toast.title, &toast.body);
+            }
+        }
+    }
+
+    /// Whether everything loaded, so saving can't overwrite anything with defaults
+    fn can_save(&self) -> bool {
+        self.item_db_error.is_none() && self.load_error.is_none()
+    }
+
+    /// Writes new sales, user and supplier changes straight away, items are saved on the tick instead
+    fn save_changes(&mut self) {
+        if !self.can_save() {
+            return;
+        }
+        let result = self.storage.save_sales(&mut self.sales_journal);
+        self.report(result);
+        if self.users.is_dirty() {
+            let result = self.storage.save_users(&mut self.users);
+            self.report(result);
+        }
+        if self.suppliers.is_dirty() {
+            let result = self.storage.save_suppliers(&mut self.suppliers);
+            self.report(result);
+        }
+    }
+
+    /// Writes the config if it was changed in the settings, returning whether it's saved
+    fn save_config(&mut self) -> bool {
+        if !self.settings_view.take_config_changed() {
+            return true;
+        }
+        let result = self.config.save_yaml("./config.yaml");
+        let saved = result.is_ok();
+        self.report(result);
+        saved
+    }
+
+    /// Shows a banner if saving failed, only once while it keeps failing the same way
+    fn report(&mut self, result: Result<(), DataError>) {
+        if let Err(error) = result {
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    /// Writes to the security log, showing a banner if it can't be written
+    fn log_security(&mut self, event: impl Display) {
+        let result = self.security_log.record(event);
+        self.report(result);
+    }
+
+    /// Writes the item database picked on the recovery screen, leaving the screen if it worked
+    fn save_recovered_item_db(&mut self) {
+        self.item_db_error = self.storage.replace_items(&mut self.item_db).err();
+    }
+}
+
+/// The original YAML storage, with the files it has always used
+fn yaml_storage(config: &Config) -> YamlStorage {
+    YamlStorage::new(
+        "./item_db.yaml",
+        config.item_db_backups,
+        "./stock_movements.jsonl",
+        "./sales_journal.jsonl",
+        "./users.yaml",
+        "./suppliers.yaml",
+    )
+}
+
+/// Opens the storage backend chosen in the config
+/// The first time SQLite is used, everything already in the YAML files is imported into it
+fn open_storage(config: &Config) -> Result<Box<dyn Storage>, DataError> {
+    match config.storage {
+        StorageBackend::Yaml => Ok(Box::new(yaml_storage(config))),
+        StorageBackend::Sqlite => {
+            let mut sqlite = SqliteStorage::open("./pos.sqlite")?;
+            if sqlite.is_new() && Path::new("./item_db.yaml").exists() {
+                if let Err(error) = storage::import(&mut yaml_storage(config), &mut sqlite) {
+                    // try again from the start next time rather than carry on with half of it
+                    drop(sqlite);
+                    let _ = fs::remove_file("./pos.sqlite");
+                    return Err(error);
+                }
+            }
+            Ok(Box::new(sqlite))
+        }
+    }
+}
+
+/// Utility to render a dismissable error above the tabs
+fn render_error_banner<'a>(index: usize, error: &DataError) -> Element<'a> {
+    container(
+        row![
+            text(error).width(Length::Fill),
+            button("Dismiss").on_press(Message::DismissError(index)),
+        ]
+        .spacing(10)
+        .align_items(Alignment::Center),
+    )
+    .style(ContainerStyle::Error)
+    .width(Length::Fill)
+    .padding(10)
+    .into()
+}
+
+/// Utility to render the screen shown instead of the tabs when stored data couldn't be loaded
+/// Only the item database can be started over or restored from a backup
+fn render_recovery<'a>(error: &DataError, config: &Config, item_db: bool) -> Element<'a> {
+    if !item_db {
+        return column![
+            text("The stored data couldn't be loaded").size(30),
+            container(text(error))
+                .style(ContainerStyle::Error)
+                .width(Length::Fill)
+                .padding(10),
+            button(text("Quit")).on_press(Message::Quit),
+        ]
+        .padding(20)
+        .spacing(20)
+        .into();
+    }
+
+    let backups = (1..=config.item_db_backups)
+        .filter(|index| Path::new(&format!("./item_db.yaml.{}", index)).exists())
+        .map(|index| {
+            button(text(format!("Restore backup {}", index)))
+                .on_press(Message::RestoreItemDBBackup(index))
+                .into()
+        })
+        .collect();
+
+    column![
+        text("The item database couldn't be loaded").size(30),
+        container(text(error))
+            .style(ContainerStyle::Error)
+            .width(Length::Fill)
+            .padding(10),
+        row(backups).spacing(10),
+        row![
+            button(text("Create empty database"))
+                .style(ButtonStyle::Important)
+                .on_press(Message::CreateEmptyItemDB),
+            Space::with_width(Length::Fill),
+            button(text("Quit")).on_press(Message::Quit),
+        ]
+        .spacing(10),
+    ]
+    .padding(20)
+    .spacing(20)
+    .into()
+}
+
+/// Utility to render the first run modal, which makes the first admin
+fn render_setup_prompt<'a>(
+    username_input: &String,
+    username_id: Id,
+    password_input: &String,
+    confirm_password_input: &String,
+) -> Element<'a> {
     column![
-        text("A Password is required"),
-        text_input("Enter Password", password_input, Message::PasswordChanged)
-            .id(id)
+        text("Welcome! Create the admin account to get started"),
+        text_input("Name", username_input, Message::UsernameChanged).id(username_id),
+        text_input("PIN", password_input, Message::PasswordChanged).password(),
+        text_input(
+            "Confirm PIN",
+            confirm_password_input,
+            Message::ConfirmPasswordChanged
+        )
+        .password()
+        .on_submit(Message::CreateFirstAdmin),
+        button(
+            text("Create")
+                .width(Length::Fill)
+                .horizontal_alignment(Horizontal::Center)
+        )
+        .on_press(Message::CreateFirstAdmin)
+        .width(Length::Fill)
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .width(Length::Units(300))
+    .into()
+}
+
+/// Utility to render the login modal
+fn render_password_prompt<'a>(
+    title: String,
+    username_input: &String,
+    username_id: Id,
+    password_input: &String,
+    password_id: Id,
+) -> Element<'a> {
+    column![
+        text(title),
+        text_input("Name", username_input, Message::UsernameChanged)
+            .id(username_id)
+            .on_submit(Message::ClosePasswordModal),
+        text_input("PIN", password_input, Message::PasswordChanged)
+            .id(password_id)
             .password()
             .on_submit(Message::ClosePasswordModal),
         button(
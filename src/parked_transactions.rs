@@ -0,0 +1,71 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind};
+
+use crate::{data_error::DataError, transaction::Transaction, utils::write_atomic};
+
+/// A transaction put aside to be finished later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParkedTransaction {
+    pub label: String,
+    pub parked_at: DateTime<Local>,
+    pub transaction: Transaction,
+}
+
+/// All parked transactions, saved to disk on every change so they survive a restart
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParkedTransactions {
+    #[serde(skip)]
+    path: String,
+    pub transactions: Vec<ParkedTransaction>,
+}
+
+impl ParkedTransactions {
+    /// Loads parked transactions from a YAML file given the path, starting empty if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let mut parked = match fs::read_to_string(path) {
+            Ok(string) => serde_yaml::from_str::<Self>(&string)
+                .map_err(|error| DataError::parse(path, error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(DataError::io(path, error)),
+        };
+        parked.path = path.to_owned();
+        Ok(parked)
+    }
+
+    /// None parked, saved to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Parks a transaction under a label, it stays parked until the app closes if it can't be saved
+    pub fn park(&mut self, label: String, transaction: Transaction) -> Result<(), DataError> {
+        self.transactions.push(ParkedTransaction {
+            label,
+            parked_at: Local::now(),
+            transaction,
+        });
+        self.save_yaml()
+    }
+
+    /// Removes a parked transaction and returns it
+    /// It's kept parked if it can't be saved without it, so it isn't recalled twice after a restart
+    pub fn recall(&mut self, index: usize) -> Result<Transaction, DataError> {
+        let parked = self.transactions.remove(index);
+        if let Err(error) = self.save_yaml() {
+            self.transactions.insert(index, parked);
+            return Err(error);
+        }
+        Ok(parked.transaction)
+    }
+
+    /// Saves itself to the YAML file it was loaded from
+    fn save_yaml(&self) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(&self.path, &string).map_err(|error| DataError::io(&self.path, error))
+    }
+}
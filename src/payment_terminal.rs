@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// How long to wait for the terminal to pick up the connection, before the customer is involved
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a terminal said about a card payment
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentResult {
+    Approved {
+        auth_code: String,
+    },
+    Declined,
+    /// The terminal didn't answer in time, e.g. the customer walked off
+    TimedOut,
+    /// The terminal couldn't be reached or sent something we don't understand
+    Failed(String),
+}
+
+impl Display for PaymentResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentResult::Approved { auth_code } => write!(f, "Approved, auth code {}", auth_code),
+            PaymentResult::Declined => write!(f, "Declined"),
+            PaymentResult::TimedOut => write!(f, "Timed out"),
+            PaymentResult::Failed(reason) => write!(f, "Failed: {}", reason),
+        }
+    }
+}
+
+/// Something that can take card payments
+pub trait PaymentProvider: Send {
+    /// Asks for a payment of the amount in pence, blocking until the terminal answers
+    fn request_payment(&self, amount: u32) -> PaymentResult;
+
+    /// Takes back an approved payment given its auth code, blocking until the terminal answers
+    fn reverse_payment(&self, amount: u32, auth_code: &str) -> PaymentResult;
+}
+
+/// Which payment terminal to use, stored in the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TerminalConfig {
+    /// The simulator in src/bin/terminal_simulator.rs, listening on a TCP address
+    Simulator { address: String },
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self::Simulator {
+            address: "127.0.0.1:7878".to_owned(),
+        }
+    }
+}
+
+impl TerminalConfig {
+    /// Makes the provider for this terminal, giving up on a payment after the timeout in seconds
+    pub fn provider(&self, timeout: u64) -> Box<dyn PaymentProvider> {
+        match self {
+            TerminalConfig::Simulator { address } => Box::new(SimulatedTerminal {
+                address: address.clone(),
+                timeout: Duration::from_secs(timeout),
+            }),
+        }
+    }
+}
+
+/// Talks to the simulated terminal over TCP, one connection per payment
+/// Sends "PAY <pence>" or "REVERSE <pence> <auth code>" and expects "APPROVED <auth code>"
+/// or "DECLINED" back, one line each
+pub struct SimulatedTerminal {
+    address: String,
+    timeout: Duration,
+}
+
+impl PaymentProvider for SimulatedTerminal {
+    fn request_payment(&self, amount: u32) -> PaymentResult {
+        self.send(&format!("PAY {}", amount))
+    }
+
+    fn reverse_payment(&self, amount: u32, auth_code: &str) -> PaymentResult {
+        self.send(&format!("REVERSE {} {}", amount, auth_code))
+    }
+}
+
+impl SimulatedTerminal {
+    /// Sends one request and waits for the answer
+    fn send(&self, request: &str) -> PaymentResult {
+        let mut stream = match connect(&self.address) {
+            Ok(stream) => stream,
+            Err(error) => {
+                return PaymentResult::Failed(format!("Couldn't reach the terminal: {}", error))
+            }
+        };
+        if let Err(error) = stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(CONNECT_TIMEOUT)))
+        {
+            return PaymentResult::Failed(error.to_string());
+        }
+        if let Err(error) = writeln!(stream, "{}", request) {
+            return PaymentResult::Failed(error.to_string());
+        }
+
+        let mut response = String::new();
+        match BufReader::new(stream).read_line(&mut response) {
+            Ok(0) => return PaymentResult::Failed("The terminal hung up".to_owned()),
+            Ok(_) => {}
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return PaymentResult::TimedOut
+            }
+            Err(error) => return PaymentResult::Failed(error.to_string()),
+        }
+
+        let mut words = response.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("APPROVED"), Some(auth_code)) => PaymentResult::Approved {
+                auth_code: auth_code.to_owned(),
+            },
+            (Some("DECLINED"), _) => PaymentResult::Declined,
+            _ => PaymentResult::Failed(format!("Unexpected response: {}", response.trim())),
+        }
+    }
+}
+
+/// Connects to the first address the terminal's address resolves to that answers in time
+fn connect(address: &str) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(ErrorKind::InvalidInput, "the address didn't resolve")
+    }))
+}
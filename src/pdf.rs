@@ -0,0 +1,113 @@
+use std::{fs, io};
+
+/// A4 in points
+const PAGE_WIDTH: usize = 595;
+const PAGE_HEIGHT: usize = 842;
+const MARGIN: usize = 50;
+const FONT_SIZE: usize = 10;
+const LINE_HEIGHT: usize = 12;
+const LINES_PER_PAGE: usize = (PAGE_HEIGHT - 2 * MARGIN) / LINE_HEIGHT;
+
+/// Writes plain text to a PDF in a fixed width font, over as many A4 pages as it needs
+/// Kept to what printers and viewers need, so documents look like the printed receipts
+pub fn write_text_pdf(path: &str, text: &str) -> io::Result<()> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let pages = lines.chunks(LINES_PER_PAGE).collect::<Vec<_>>();
+    // an empty document still gets a page
+    let pages = if pages.is_empty() {
+        vec![&lines[..]]
+    } else {
+        pages
+    };
+
+    // catalog, page tree and font first, then each page followed by its contents
+    let page_ids = (0..pages.len())
+        .map(|index| 4 + index * 2)
+        .collect::<Vec<_>>();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    for (page, id) in pages.iter().zip(page_ids.iter()) {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                id + 1
+            )
+            .into_bytes(),
+        );
+
+        let mut content = format!(
+            "BT\n/F1 {} Tf\n{} TL\n{} {} Td\n",
+            FONT_SIZE,
+            LINE_HEIGHT,
+            MARGIN,
+            PAGE_HEIGHT - MARGIN - FONT_SIZE
+        )
+        .into_bytes();
+        for line in page.iter() {
+            content.push(b'(');
+            content.extend(encode(line));
+            content.extend(b") Tj T*\n");
+        }
+        content.extend(b"ET");
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).into_bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .into_bytes(),
+    );
+
+    fs::write(path, pdf)
+}
+
+/// Escapes a line for a PDF string in the WinAnsi encoding, anything it doesn't have becomes ?
+fn encode(line: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for c in line.chars() {
+        match c {
+            '\\' | '(' | ')' => bytes.extend([b'\\', c as u8]),
+            ' '..='~' => bytes.push(c as u8),
+            '£' => bytes.push(0xA3),
+            '€' => bytes.push(0x80),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
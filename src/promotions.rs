@@ -0,0 +1,357 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::ErrorKind};
+
+use crate::{
+    data_error::DataError,
+    transaction::{PromotionSaving, Transaction},
+};
+
+/// How a promotion works out the price
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PromotionRule {
+    /// A number of the same item for a fixed price, e.g. 3 for £1.50
+    MultiBuy {
+        barcode: u64,
+        quantity: u32,
+        price: u32,
+    },
+    /// One of each of the items for a fixed price, e.g. meal deals
+    /// An item can be listed more than once to need more of it
+    Bundle { barcodes: Vec<u64>, price: u32 },
+    /// Buy some of an item and get more of it for free
+    BuyXGetY { barcode: u64, buy: u32, free: u32 },
+}
+
+impl PromotionRule {
+    /// Units of each item needed to apply the rule once
+    fn requirements(&self) -> Vec<(u64, u32)> {
+        match self {
+            PromotionRule::MultiBuy {
+                barcode, quantity, ..
+            } => vec![(*barcode, *quantity)],
+            PromotionRule::Bundle { barcodes, .. } => {
+                let mut requirements: Vec<(u64, u32)> = Vec::new();
+                for barcode in barcodes {
+                    match requirements.iter_mut().find(|(code, _)| code == barcode) {
+                        Some((_, count)) => *count += 1,
+                        None => requirements.push((*barcode, 1)),
+                    }
+                }
+                requirements
+            }
+            PromotionRule::BuyXGetY { barcode, buy, free } => vec![(*barcode, buy + free)],
+        }
+    }
+
+    /// How much applying the rule once saves, given the normal price of each item
+    fn savings(&self, unit_price: &impl Fn(u64) -> u32) -> u32 {
+        match self {
+            PromotionRule::MultiBuy { price, .. } | PromotionRule::Bundle { price, .. } => self
+                .requirements()
+                .iter()
+                .map(|(barcode, count)| unit_price(*barcode) * count)
+                .sum::<u32>()
+                .saturating_sub(*price),
+            PromotionRule::BuyXGetY { barcode, free, .. } => unit_price(*barcode) * free,
+        }
+    }
+}
+
+/// A promotion with the dates it runs between, both inclusive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub name: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub rule: PromotionRule,
+}
+
+/// A promotion that can be applied to a transaction, with what it needs and saves each time
+struct Candidate<'a> {
+    promotion: &'a Promotion,
+    requirements: Vec<(u64, u32)>,
+    savings: u32,
+}
+
+/// All promotions, loaded from a YAML file
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotions {
+    pub promotions: Vec<Promotion>,
+}
+
+impl Promotions {
+    /// Loads promotions from a YAML file given the path, starting empty if it doesn't exist
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        match fs::read_to_string(path) {
+            Ok(string) => {
+                serde_yaml::from_str::<Self>(&string).map_err(|error| DataError::parse(path, error))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(DataError::io(path, error)),
+        }
+    }
+
+    /// Works out the promotions that save the customer the most and stores the savings on
+    /// the lines of the transaction
+    pub fn apply(&self, transaction: &mut Transaction, date: NaiveDate) {
+        for t_item in transaction.items.iter_mut() {
+            t_item.promotions.clear();
+        }
+
+        // how many of each item there are to use up
+        let mut available: HashMap<u64, u32> = HashMap::new();
+        let mut prices: HashMap<u64, u32> = HashMap::new();
+        // weighed and individually priced lines don't count towards promotions
+        for t_item in transaction
+            .items
+            .iter()
+            .filter(|t_item| !t_item.is_measured())
+        {
+            *available.entry(t_item.item.barcode).or_default() += t_item.quantity;
+            prices.insert(t_item.item.barcode, t_item.item.price);
+        }
+        let unit_price = |barcode: u64| prices.get(&barcode).copied().unwrap_or(0);
+
+        let candidates = self
+            .promotions
+            .iter()
+            .filter(|promotion| promotion.start <= date && date <= promotion.end)
+            .map(|promotion| Candidate {
+                promotion,
+                requirements: promotion.rule.requirements(),
+                savings: promotion.rule.savings(&unit_price),
+            })
+            // leave out anything that can't be applied even once
+            .filter(|candidate| {
+                candidate.savings > 0
+                    && !candidate.requirements.is_empty()
+                    && candidate.requirements.iter().all(|(barcode, count)| {
+                        *count > 0 && available.get(barcode).copied().unwrap_or(0) >= *count
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        let (_, times) = best_combination(&candidates, &mut available);
+
+        for (candidate, times) in candidates.iter().zip(times) {
+            if times == 0 {
+                continue;
+            }
+
+            // share the savings out between the items by their normal price
+            let total_savings = (candidate.savings * times) as u64;
+            let regular_price: u64 = candidate
+                .requirements
+                .iter()
+                .map(|(barcode, count)| (unit_price(*barcode) * count) as u64)
+                .sum();
+            let mut remaining = total_savings;
+
+            for (index, (barcode, count)) in candidate.requirements.iter().enumerate() {
+                let share = if index == candidate.requirements.len() - 1 {
+                    remaining
+                } else {
+                    total_savings * (unit_price(*barcode) * count) as u64 / regular_price
+                };
+                remaining -= share;
+
+                if let Some(t_item) = transaction
+                    .items
+                    .iter_mut()
+                    .find(|t_item| t_item.item.barcode == *barcode && !t_item.is_measured())
+                {
+                    t_item.promotions.push(PromotionSaving {
+                        name: candidate.promotion.name.clone(),
+                        amount: share as u32,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Finds how many times to apply each candidate to save the most in total
+/// Tries every combination, which is fine for the handful of promotions a basket qualifies for
+/// Returns the total savings and the times each candidate is applied
+fn best_combination(
+    candidates: &[Candidate],
+    available: &mut HashMap<u64, u32>,
+) -> (u32, Vec<u32>) {
+    let (candidate, rest) = match candidates.split_first() {
+        Some(split) => split,
+        None => return (0, Vec::new()),
+    };
+
+    let max_times = candidate
+        .requirements
+        .iter()
+        .map(|(barcode, count)| available.get(barcode).copied().unwrap_or(0) / count)
+        .min()
+        .unwrap_or(0);
+
+    let mut best: Option<(u32, Vec<u32>)> = None;
+    for times in 0..=max_times {
+        for (barcode, count) in candidate.requirements.iter() {
+            *available.entry(*barcode).or_default() -= count * times;
+        }
+        let (rest_savings, mut rest_times) = best_combination(rest, available);
+        for (barcode, count) in candidate.requirements.iter() {
+            *available.entry(*barcode).or_default() += count * times;
+        }
+
+        let savings = rest_savings + candidate.savings * times;
+        if best
+            .as_ref()
+            .map_or(true, |(best_savings, _)| savings > *best_savings)
+        {
+            rest_times.insert(0, times);
+            best = Some((savings, rest_times));
+        }
+    }
+
+    best.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Item;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 10, 1).unwrap()
+    }
+
+    fn promotion(name: &str, rule: PromotionRule) -> Promotion {
+        Promotion {
+            name: name.to_owned(),
+            start: date(),
+            end: date(),
+            rule,
+        }
+    }
+
+    fn item(barcode: u64, price: u32) -> Item {
+        Item {
+            barcode,
+            price,
+            ..Default::default()
+        }
+    }
+
+    /// Each promotion applied to the transaction with what it saved in total
+    fn savings(transaction: &Transaction) -> Vec<(String, u32)> {
+        let mut savings: Vec<(String, u32)> = Vec::new();
+        for saving in transaction
+            .items
+            .iter()
+            .flat_map(|t_item| &t_item.promotions)
+        {
+            match savings.iter_mut().find(|(name, _)| *name == saving.name) {
+                Some((_, amount)) => *amount += saving.amount,
+                None => savings.push((saving.name.clone(), saving.amount)),
+            }
+        }
+        savings
+    }
+
+    #[test]
+    fn best_price_over_biggest_single_saving() {
+        // taking 3 for £2.20 first would leave one over and save only 80p
+        let promotions = Promotions {
+            promotions: vec![
+                promotion(
+                    "3 for £2.20",
+                    PromotionRule::MultiBuy {
+                        barcode: 1,
+                        quantity: 3,
+                        price: 220,
+                    },
+                ),
+                promotion(
+                    "2 for £1.50",
+                    PromotionRule::MultiBuy {
+                        barcode: 1,
+                        quantity: 2,
+                        price: 150,
+                    },
+                ),
+            ],
+        };
+        let mut transaction = Transaction::default();
+        for _ in 0..4 {
+            transaction.add_item(&item(1, 100));
+        }
+
+        promotions.apply(&mut transaction, date());
+        assert_eq!(savings(&transaction), vec![("2 for £1.50".to_owned(), 100)]);
+    }
+
+    #[test]
+    fn bundle_savings_shared_by_price() {
+        let promotions = Promotions {
+            promotions: vec![promotion(
+                "Meal deal",
+                PromotionRule::Bundle {
+                    barcodes: vec![1, 2],
+                    price: 360,
+                },
+            )],
+        };
+        let mut transaction = Transaction::default();
+        transaction.add_item(&item(1, 300));
+        transaction.add_item(&item(2, 150));
+
+        promotions.apply(&mut transaction, date());
+        assert_eq!(transaction.items[0].promotion_savings(), 60);
+        assert_eq!(transaction.items[1].promotion_savings(), 30);
+    }
+
+    #[test]
+    fn buy_x_get_y() {
+        let promotions = Promotions {
+            promotions: vec![promotion(
+                "Buy 2 get 1 free",
+                PromotionRule::BuyXGetY {
+                    barcode: 1,
+                    buy: 2,
+                    free: 1,
+                },
+            )],
+        };
+        let mut transaction = Transaction::default();
+        for _ in 0..5 {
+            transaction.add_item(&item(1, 100));
+        }
+
+        promotions.apply(&mut transaction, date());
+        assert_eq!(transaction.items[0].promotion_savings(), 100);
+    }
+
+    #[test]
+    fn only_running_promotions_or_counted_lines() {
+        let promotions = Promotions {
+            promotions: vec![promotion(
+                "2 for £1.50",
+                PromotionRule::MultiBuy {
+                    barcode: 1,
+                    quantity: 2,
+                    price: 150,
+                },
+            )],
+        };
+        let mut transaction = Transaction::default();
+        transaction.add_item(&item(1, 100));
+        transaction.add_priced_item(&item(1, 100), 100);
+
+        promotions.apply(&mut transaction, date());
+        assert!(savings(&transaction).is_empty());
+
+        transaction.add_item(&item(1, 100));
+        promotions.apply(&mut transaction, date().succ_opt().unwrap());
+        assert!(savings(&transaction).is_empty());
+        promotions.apply(&mut transaction, date());
+        assert_eq!(savings(&transaction), vec![("2 for £1.50".to_owned(), 50)]);
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, fs, io::ErrorKind};
+
+use crate::{
+    data_error::DataError,
+    item_db::ItemDB,
+    pdf::write_text_pdf,
+    suppliers::Suppliers,
+    utils::{format_stock, write_atomic},
+};
+
+/// How far along an order is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    /// Not sent to the supplier yet, can still be deleted
+    Draft,
+    Sent,
+    /// Some of it has been delivered
+    PartReceived,
+    Received,
+}
+
+impl Display for OrderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OrderStatus::Draft => "Draft",
+            OrderStatus::Sent => "Sent",
+            OrderStatus::PartReceived => "Part received",
+            OrderStatus::Received => "Received",
+        })
+    }
+}
+
+/// One item on an order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub barcode: u64,
+    pub name: String,
+    /// What the supplier calls it
+    #[serde(default)]
+    pub product_code: String,
+    /// In grams for items sold by weight
+    pub quantity: u32,
+    pub sold_by_weight: bool,
+    /// How much has been delivered so far
+    #[serde(default)]
+    pub received: u32,
+}
+
+impl OrderLine {
+    /// How much is still to be delivered
+    pub fn outstanding(&self) -> u32 {
+        self.quantity.saturating_sub(self.received)
+    }
+}
+
+/// An order of items from one supplier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub number: u64,
+    /// The name of the supplier when the order was made
+    pub supplier: String,
+    /// The id of the supplier in the registry
+    #[serde(default)]
+    pub supplier_id: Option<u64>,
+    /// Our account number with the supplier
+    #[serde(default)]
+    pub account_number: String,
+    pub created: DateTime<Local>,
+    pub sent: Option<DateTime<Local>>,
+    pub status: OrderStatus,
+    pub lines: Vec<OrderLine>,
+}
+
+impl PurchaseOrder {
+    /// Whether more is expected to be delivered against it
+    pub fn is_open(&self) -> bool {
+        self.status != OrderStatus::Received
+    }
+
+    /// The name its exported files are given
+    pub fn file_name(&self) -> String {
+        format!("PO-{:06}", self.number)
+    }
+
+    /// Create a string with the order in plain text, laid out like a receipt
+    pub fn generate_document(&self) -> String {
+        let mut string = String::from("\n\n\n\n");
+        string.push_str(&format!(
+            "Swansea Food Centre     {}\n",
+            self.created.format("%d. %m. %Y")
+        ));
+        string.push_str(&format!("Purchase order {}\n", self.file_name()));
+        string.push_str(&format!("Supplier: {}\n", self.supplier));
+        if !self.account_number.is_empty() {
+            string.push_str(&format!("Account: {}\n", self.account_number));
+        }
+        string.push_str("========================================\n");
+
+        //     12       28
+        // quantity | name
+        for line in &self.lines {
+            string.push_str(&format!(
+                "{0: <12}{1: <28}\n",
+                format_stock(line.quantity, line.sold_by_weight),
+                line.name,
+            ));
+            let codes = format!("            {0: <14}{1}", line.barcode, line.product_code);
+            string.push_str(codes.trim_end());
+            string.push('\n');
+        }
+        string.push_str("========================================\n");
+        string.push_str(&format!("{} lines\n", self.lines.len()));
+
+        string
+    }
+
+    /// The lines as CSV with a header row, quantities of items sold by weight are in kg
+    pub fn generate_csv(&self) -> String {
+        let mut string =
+            String::from("order,supplier,account,barcode,product_code,name,quantity,unit\n");
+        for line in &self.lines {
+            let (quantity, unit) = if line.sold_by_weight {
+                (format!("{:.3}", line.quantity as f64 / 1000.0), "kg")
+            } else {
+                (line.quantity.to_string(), "each")
+            };
+            string.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                self.file_name(),
+                csv_field(&self.supplier),
+                csv_field(&self.account_number),
+                line.barcode,
+                csv_field(&line.product_code),
+                csv_field(&line.name),
+                quantity,
+                unit
+            ));
+        }
+        string
+    }
+
+    /// Writes the order as CSV and PDF files into a directory, returning the path of the PDF
+    pub fn export(&self, directory: &str) -> Result<String, DataError> {
+        let pdf_path = self.write_pdf(directory)?;
+        let csv_path = format!("{}/{}.csv", directory, self.file_name());
+        write_atomic(&csv_path, &self.generate_csv())
+            .map_err(|error| DataError::io(&csv_path, error))?;
+        Ok(pdf_path)
+    }
+
+    /// Writes the order as a PDF into a directory, returning its path
+    pub fn write_pdf(&self, directory: &str) -> Result<String, DataError> {
+        fs::create_dir_all(directory).map_err(|error| DataError::io(directory, error))?;
+
+        let path = format!("{}/{}.pdf", directory, self.file_name());
+        write_text_pdf(&path, self.generate_document().trim_start())
+            .map_err(|error| DataError::io(&path, error))?;
+        Ok(path)
+    }
+}
+
+/// Quotes a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Every purchase order, saved to disk on every change
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurchaseOrders {
+    #[serde(skip)]
+    path: String,
+    pub orders: Vec<PurchaseOrder>,
+}
+
+impl PurchaseOrders {
+    /// Loads orders from a YAML file given the path, starting with none if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let mut orders = match fs::read_to_string(path) {
+            Ok(string) => serde_yaml::from_str::<Self>(&string)
+                .map_err(|error| DataError::parse(path, error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(DataError::io(path, error)),
+        };
+        orders.path = path.to_owned();
+        Ok(orders)
+    }
+
+    /// No orders, saved to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, number: u64) -> Option<&PurchaseOrder> {
+        self.orders.iter().find(|order| order.number == number)
+    }
+
+    /// Makes a draft order for each preferred supplier of the items that need reordering
+    /// Items already on an open order are left out, as are items without a supplier
+    /// Returns how many orders were made and how many items had no supplier
+    pub fn generate(
+        &mut self,
+        item_db: &ItemDB,
+        suppliers: &Suppliers,
+    ) -> Result<(usize, usize), DataError> {
+        let mut by_supplier: BTreeMap<u64, Vec<OrderLine>> = BTreeMap::new();
+        let mut no_supplier = 0;
+        for item in item_db.items().iter().filter(|item| item.needs_reorder()) {
+            if self.is_on_order(item.barcode) {
+                continue;
+            }
+            let link = match item.preferred_supplier() {
+                Some(link) => link,
+                None => {
+                    no_supplier += 1;
+                    continue;
+                }
+            };
+            by_supplier
+                .entry(link.supplier)
+                .or_default()
+                .push(OrderLine {
+                    barcode: item.barcode,
+                    name: item.name.clone(),
+                    product_code: link.product_code.clone(),
+                    quantity: item.reorder_quantity,
+                    sold_by_weight: item.sold_by_weight,
+                    received: 0,
+                });
+        }
+
+        let generated = by_supplier.len();
+        let mut number = self
+            .orders
+            .iter()
+            .map(|order| order.number)
+            .max()
+            .unwrap_or(0);
+        for (id, lines) in by_supplier {
+            number += 1;
+            self.orders.push(PurchaseOrder {
+                number,
+                supplier: suppliers.name(id),
+                supplier_id: Some(id),
+                account_number: suppliers
+                    .get(id)
+                    .map_or(String::new(), |supplier| supplier.account_number.clone()),
+                created: Local::now(),
+                sent: None,
+                status: OrderStatus::Draft,
+                lines,
+            });
+        }
+
+        if generated > 0 {
+            self.save_yaml()?;
+        }
+        Ok((generated, no_supplier))
+    }
+
+    /// Whether an item is on an order that hasn't been fully delivered
+    fn is_on_order(&self, barcode: u64) -> bool {
+        self.orders
+            .iter()
+            .filter(|order| order.is_open())
+            .any(|order| {
+                order
+                    .lines
+                    .iter()
+                    .any(|line| line.barcode == barcode && line.outstanding() > 0)
+            })
+    }
+
+    pub fn mark_sent(&mut self, number: u64) -> Result<(), DataError> {
+        if let Some(order) = self.orders.iter_mut().find(|order| order.number == number) {
+            order.status = OrderStatus::Sent;
+            order.sent = Some(Local::now());
+        }
+        self.save_yaml()
+    }
+
+    /// Deletes an order that hasn't been sent yet
+    pub fn delete_draft(&mut self, number: u64) -> Result<(), DataError> {
+        self.orders
+            .retain(|order| order.number != number || order.status != OrderStatus::Draft);
+        self.save_yaml()
+    }
+
+    /// Counts a delivery against an order, items that weren't ordered are ignored
+    /// Only orders that have been sent and aren't fully delivered can be received against
+    pub fn receive(&mut self, number: u64, delivery: &[(u64, u32)]) -> Result<(), DataError> {
+        let order = match self.orders.iter_mut().find(|order| {
+            order.number == number
+                && matches!(order.status, OrderStatus::Sent | OrderStatus::PartReceived)
+        }) {
+            Some(order) => order,
+            None => return Ok(()),
+        };
+
+        for (barcode, quantity) in delivery {
+            if let Some(line) = order.lines.iter_mut().find(|line| line.barcode == *barcode) {
+                line.received += quantity;
+            }
+        }
+        order.status = if order.lines.iter().all(|line| line.outstanding() == 0) {
+            OrderStatus::Received
+        } else {
+            OrderStatus::PartReceived
+        };
+        self.save_yaml()
+    }
+
+    /// Saves itself to the YAML file it was loaded from
+    fn save_yaml(&self) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(&self.path, &string).map_err(|error| DataError::io(&self.path, error))
+    }
+}
@@ -0,0 +1,555 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::{
+    item::TaxClass,
+    transaction::{AgeCheck, TaxSummary, Transaction},
+    utils::format_price,
+};
+
+/// How a sale was paid for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentMethod {
+    Cash,
+    Card,
+}
+
+impl Display for PaymentMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PaymentMethod::Cash => "Cash",
+            PaymentMethod::Card => "Card",
+        })
+    }
+}
+
+/// One payment towards a transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tender {
+    pub method: PaymentMethod,
+    /// Amount handed over in pence, negative for money paid back on refunds
+    pub amount: i32,
+    /// Auth code from the terminal for card payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_code: Option<String>,
+}
+
+/// Why goods were returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefundReason {
+    Damaged,
+    Faulty,
+    WrongItem,
+    NotWanted,
+    Other,
+}
+
+impl RefundReason {
+    pub const ALL: [RefundReason; 5] = [
+        RefundReason::Damaged,
+        RefundReason::Faulty,
+        RefundReason::WrongItem,
+        RefundReason::NotWanted,
+        RefundReason::Other,
+    ];
+}
+
+impl Display for RefundReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RefundReason::Damaged => "Damaged",
+            RefundReason::Faulty => "Faulty",
+            RefundReason::WrongItem => "Wrong item",
+            RefundReason::NotWanted => "Not wanted",
+            RefundReason::Other => "Other",
+        })
+    }
+}
+
+/// Whether a record is a normal sale or a refund of an earlier one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SaleKind {
+    #[default]
+    Sale,
+    Refund {
+        /// Transaction number of the sale being refunded
+        original: u64,
+        reason: RefundReason,
+    },
+}
+
+impl SaleKind {
+    /// Whether this is a refund against the sale with the given transaction number
+    pub fn is_refund_of(&self, number: u64) -> bool {
+        match self {
+            SaleKind::Refund { original, .. } => *original == number,
+            SaleKind::Sale => false,
+        }
+    }
+}
+
+/// A single line of a completed sale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaleLine {
+    pub barcode: u64,
+    pub name: String,
+    /// Price charged per unit, in pence
+    pub unit_price: u32,
+    /// Negative for refunded lines
+    pub quantity: i32,
+    /// Weight of one of the line in grams, for items sold by weight
+    #[serde(default)]
+    pub weight: Option<u32>,
+    /// Amount saved on the whole line by promotions, in pence
+    #[serde(default)]
+    pub promotion_savings: u32,
+    /// Amount taken off the whole line by a discount, in pence
+    /// For refunded lines, the part of their price that wasn't paid, e.g. their share of discounts
+    #[serde(default)]
+    pub discount: u32,
+    #[serde(default)]
+    pub discount_reason: Option<String>,
+    #[serde(default)]
+    pub tax_class: TaxClass,
+    /// For refunded lines, which line of the original sale they return
+    /// Sale lines have their own index
+    pub original_line: usize,
+}
+
+impl SaleLine {
+    /// How much stock the line moved, in grams for items sold by weight
+    /// Negative for refunded lines
+    pub fn stock_quantity(&self) -> i32 {
+        self.weight
+            .map_or(self.quantity, |weight| weight as i32 * self.quantity)
+    }
+
+    /// Price of the whole line after promotions and its discount, negative for refunded lines
+    pub fn total(&self) -> i32 {
+        let total = self.unit_price as i32 * self.quantity.abs()
+            - self.promotion_savings as i32
+            - self.discount as i32;
+        total * self.quantity.signum()
+    }
+}
+
+/// A completed sale as stored in the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaleRecord {
+    /// Sequential transaction number, also printed on the receipt
+    pub number: u64,
+    pub timestamp: DateTime<Local>,
+    #[serde(default)]
+    pub kind: SaleKind,
+    pub lines: Vec<SaleLine>,
+    /// Amount taken off the whole basket, in pence
+    #[serde(default)]
+    pub discount: u32,
+    #[serde(default)]
+    pub discount_reason: Option<String>,
+    /// Negative for refunds
+    pub total: i32,
+    /// VAT totals for each rate
+    #[serde(default)]
+    pub tax: Vec<TaxSummary>,
+    /// Every payment taken, in order
+    #[serde(default)]
+    pub tenders: Vec<Tender>,
+    /// Change given back from the cash tendered
+    pub change: u32,
+    /// Age checks done for restricted items
+    #[serde(default)]
+    pub age_checks: Vec<AgeCheck>,
+    /// Who was logged in at the till
+    #[serde(default)]
+    pub cashier: String,
+}
+
+impl SaleRecord {
+    /// Total tendered with a payment method, before any change is given
+    pub fn tendered(&self, method: PaymentMethod) -> i32 {
+        self.tenders
+            .iter()
+            .filter(|tender| tender.method == method)
+            .map(|tender| tender.amount)
+            .sum()
+    }
+
+    /// Price actually paid for a whole line, after the line and basket discounts
+    pub fn paid_line_total(&self, index: usize) -> i64 {
+        let lines_total: i64 = self.lines.iter().map(|line| line.total() as i64).sum();
+        if lines_total == 0 {
+            return 0;
+        }
+
+        // share the basket discount out between the lines by their value,
+        // the last line gets what's left so the lines add up to the total
+        let share = |line: &SaleLine| line.total() as i64 * self.total as i64 / lines_total;
+        if index + 1 == self.lines.len() {
+            self.total as i64 - self.lines[..index].iter().map(share).sum::<i64>()
+        } else {
+            share(&self.lines[index])
+        }
+    }
+
+    /// Create a string with the refund slip in plain text
+    pub fn generate_refund_receipt(&self) -> String {
+        let original = match self.kind {
+            SaleKind::Refund { original, .. } => original,
+            SaleKind::Sale => return "".to_string(),
+        };
+
+        let mut string = String::from("\n\n\n\n");
+        string.push_str(&format!(
+            "Swansea Food Centre     {}\n",
+            self.timestamp.format("%d. %m. %H:%M:%S")
+        ));
+        string.push_str(&format!(
+            "Refund no. {} for receipt no. {}\n",
+            self.number, original
+        ));
+        string.push_str(&format!("Served by {}\n", self.cashier));
+        string.push_str("========================================\n");
+
+        for line in &self.lines {
+            string.push_str(&format!(
+                "{0: <3}{1: <30}{2: >7}\n",
+                line.quantity.unsigned_abs(),
+                &line.name,
+                format_price(line.total().unsigned_abs()),
+            ));
+        }
+
+        string.push_str("========================================\n");
+
+        string.push_str(&format!(
+            "{0: <33}{1: >7}\n",
+            "TOTAL REFUNDED:",
+            format_price(self.total.unsigned_abs())
+        ));
+        for tender in &self.tenders {
+            string.push_str(&format!(
+                "{0: <33}{1: >7}\n",
+                format!("REFUNDED TO {}:", tender.method.to_string().to_uppercase()),
+                format_price(tender.amount.unsigned_abs())
+            ));
+        }
+
+        string
+    }
+}
+
+/// Append-only journal of every completed sale
+/// New records are written out by the storage backend after each update
+#[derive(Debug, Default, PartialEq)]
+pub struct SalesJournal {
+    pub records: Vec<SaleRecord>,
+    /// Who is logged in, stamped on every new record
+    pub cashier: String,
+    /// How many of the records have been saved
+    saved: usize,
+}
+
+impl SalesJournal {
+    /// Makes the journal from the records already saved
+    pub fn new(records: Vec<SaleRecord>) -> Self {
+        Self {
+            saved: records.len(),
+            records,
+            cashier: String::new(),
+        }
+    }
+
+    /// The number the next recorded sale will get
+    pub fn next_number(&self) -> u64 {
+        self.records.last().map_or(1, |record| record.number + 1)
+    }
+
+    /// Gets a record given its transaction number
+    pub fn get(&self, number: u64) -> Option<&SaleRecord> {
+        self.records.iter().find(|record| record.number == number)
+    }
+
+    /// Refunded lines returning a line of a sale
+    fn refunds_of<'a>(
+        &'a self,
+        original: &'a SaleRecord,
+        index: usize,
+    ) -> impl Iterator<Item = &'a SaleLine> {
+        self.records
+            .iter()
+            .filter(|record| record.kind.is_refund_of(original.number))
+            .flat_map(|record| record.lines.iter())
+            .filter(move |line| line.original_line == index)
+    }
+
+    /// How many units of a line of a sale have been refunded already
+    pub fn refunded_quantity(&self, original: &SaleRecord, index: usize) -> u32 {
+        self.refunds_of(original, index)
+            .map(|line| line.quantity.unsigned_abs())
+            .sum()
+    }
+
+    /// How much refunding some units of a line of a sale gives back, their share of what was paid
+    /// The last units refunded get whatever of the line is left, so nothing is lost to rounding
+    pub fn refund_amount(&self, original: &SaleRecord, index: usize, quantity: u32) -> u32 {
+        let sold = original.lines[index].quantity.unsigned_abs() as i64;
+        let paid = original.paid_line_total(index);
+        if sold == 0 || quantity == 0 {
+            return 0;
+        }
+
+        let refunded = self.refunded_quantity(original, index) as i64;
+        let amount = if refunded + quantity as i64 >= sold {
+            let refunded_amount: i64 = self
+                .refunds_of(original, index)
+                .map(|line| -line.total() as i64)
+                .sum();
+            paid - refunded_amount
+        } else {
+            paid * quantity as i64 / sold
+        };
+        amount.max(0) as u32
+    }
+
+    /// Records a completed transaction and returns the stored record
+    /// Anything tendered over the total is given back as change
+    pub fn record_sale(&mut self, transaction: &Transaction, tenders: Vec<Tender>) -> &SaleRecord {
+        let tendered: i32 = tenders.iter().map(|tender| tender.amount).sum();
+        let total = transaction.total_price();
+
+        let record = SaleRecord {
+            number: self.next_number(),
+            timestamp: Local::now(),
+            kind: SaleKind::Sale,
+            lines: transaction
+                .items
+                .iter()
+                .enumerate()
+                .map(|(index, t_item)| SaleLine {
+                    barcode: t_item.item.barcode,
+                    name: t_item.item.name.clone(),
+                    unit_price: t_item.unit_price(),
+                    quantity: t_item.quantity as i32,
+                    weight: t_item.unit_weight(),
+                    promotion_savings: t_item.promotion_savings(),
+                    discount: t_item.discount_amount(),
+                    discount_reason: t_item
+                        .discount
+                        .as_ref()
+                        .map(|discount| discount.reason.clone()),
+                    tax_class: t_item.item.tax_class,
+                    original_line: index,
+                })
+                .collect(),
+            discount: transaction.discount_amount(),
+            discount_reason: transaction
+                .discount
+                .as_ref()
+                .map(|discount| discount.reason.clone()),
+            total: total as i32,
+            tax: transaction.tax_breakdown(),
+            tenders,
+            change: (tendered - total as i32).max(0) as u32,
+            age_checks: transaction.age_checks.clone(),
+            cashier: self.cashier.clone(),
+        };
+
+        self.append(record);
+        self.records.last().unwrap()
+    }
+
+    /// Records a refund against an earlier sale and returns the stored record
+    /// `quantities` are the amounts being returned of each line of the original sale
+    pub fn record_refund(
+        &mut self,
+        original: &SaleRecord,
+        quantities: &[u32],
+        reason: RefundReason,
+    ) -> &SaleRecord {
+        // refund what was actually paid, discounts included
+        let lines = original
+            .lines
+            .iter()
+            .zip(quantities.iter())
+            .enumerate()
+            .filter(|(_index, (_line, quantity))| **quantity > 0)
+            .map(|(index, (line, quantity))| {
+                let price = line.unit_price * quantity;
+                SaleLine {
+                    barcode: line.barcode,
+                    name: line.name.clone(),
+                    unit_price: line.unit_price,
+                    quantity: -(*quantity as i32),
+                    weight: line.weight,
+                    promotion_savings: 0,
+                    discount: price.saturating_sub(self.refund_amount(original, index, *quantity)),
+                    discount_reason: None,
+                    tax_class: line.tax_class,
+                    original_line: index,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // money goes back to the card it came from first, anything else is paid back in cash
+        let amount = -lines.iter().map(|line| line.total()).sum::<i32>();
+        let card_refunded: i32 = self
+            .records
+            .iter()
+            .filter(|record| record.kind.is_refund_of(original.number))
+            .map(|record| -record.tendered(PaymentMethod::Card))
+            .sum();
+        let card = amount
+            .min(original.tendered(PaymentMethod::Card) - card_refunded)
+            .max(0);
+        let tenders = [
+            (PaymentMethod::Card, card),
+            (PaymentMethod::Cash, amount - card),
+        ]
+        .into_iter()
+        .filter(|(_method, amount)| *amount > 0)
+        .map(|(method, amount)| Tender {
+            method,
+            amount: -amount,
+            auth_code: None,
+        })
+        .collect();
+
+        let record = SaleRecord {
+            number: self.next_number(),
+            timestamp: Local::now(),
+            kind: SaleKind::Refund {
+                original: original.number,
+                reason,
+            },
+            total: lines.iter().map(|line| line.total()).sum(),
+            tax: TaxSummary::summarise(lines.iter().map(|line| (line.tax_class, line.total()))),
+            lines,
+            discount: 0,
+            discount_reason: None,
+            tenders,
+            change: 0,
+            age_checks: Vec::new(),
+            cashier: self.cashier.clone(),
+        };
+
+        self.append(record);
+        self.records.last().unwrap()
+    }
+
+    /// Adds a record to the end of the journal, to be saved after the update
+    fn append(&mut self, record: SaleRecord) {
+        self.records.push(record);
+    }
+
+    /// Records that haven't been saved yet, oldest first
+    pub fn unsaved(&self) -> &[SaleRecord] {
+        &self.records[self.saved..]
+    }
+
+    /// Counts every record as saved
+    pub fn mark_saved(&mut self) {
+        self.saved = self.records.len();
+    }
+
+    /// Counts no record as saved, so the next save writes all of them
+    pub fn mark_all_unsaved(&mut self) {
+        self.saved = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(quantity: i32, original_line: usize) -> SaleLine {
+        SaleLine {
+            barcode: 1,
+            name: "Kofola 2l".to_owned(),
+            unit_price: 100,
+            quantity,
+            weight: None,
+            promotion_savings: 0,
+            discount: 0,
+            discount_reason: None,
+            tax_class: TaxClass::Standard,
+            original_line,
+        }
+    }
+
+    fn tender(method: PaymentMethod, amount: i32) -> Tender {
+        Tender {
+            method,
+            amount,
+            auth_code: None,
+        }
+    }
+
+    /// The same item on two lines, with 33p off the basket
+    fn sale() -> SaleRecord {
+        SaleRecord {
+            number: 1,
+            timestamp: Local::now(),
+            kind: SaleKind::Sale,
+            lines: vec![line(3, 0), line(1, 1)],
+            discount: 33,
+            discount_reason: Some("Damaged".to_owned()),
+            total: 367,
+            tax: Vec::new(),
+            tenders: vec![
+                tender(PaymentMethod::Card, 200),
+                tender(PaymentMethod::Cash, 200),
+            ],
+            change: 33,
+            age_checks: Vec::new(),
+            cashier: "admin".to_owned(),
+        }
+    }
+
+    #[test]
+    fn basket_discount_shared_between_lines() {
+        let sale = sale();
+        assert_eq!(sale.paid_line_total(0), 275);
+        // the last line gets what's left
+        assert_eq!(sale.paid_line_total(1), 92);
+    }
+
+    #[test]
+    fn last_units_refunded_get_what_is_left() {
+        let sale = sale();
+        let mut journal = SalesJournal::new(vec![sale.clone()]);
+        assert_eq!(journal.refund_amount(&sale, 0, 1), 91);
+        assert_eq!(journal.refund_amount(&sale, 0, 3), 275);
+
+        journal.record_refund(&sale, &[1, 0], RefundReason::NotWanted);
+        assert_eq!(journal.refund_amount(&sale, 0, 2), 184);
+        // the other line of the same item is still unrefunded
+        assert_eq!(journal.refunded_quantity(&sale, 0), 1);
+        assert_eq!(journal.refunded_quantity(&sale, 1), 0);
+        assert_eq!(journal.refund_amount(&sale, 1, 1), 92);
+    }
+
+    #[test]
+    fn refunds_add_up_to_what_was_paid() {
+        let sale = sale();
+        let mut journal = SalesJournal::new(vec![sale.clone()]);
+
+        let refund = journal.record_refund(&sale, &[1, 1], RefundReason::Faulty);
+        assert_eq!(refund.total, -183);
+        assert_eq!(refund.lines[1].original_line, 1);
+        // back to the card first
+        assert_eq!(refund.tenders, vec![tender(PaymentMethod::Card, -183)]);
+
+        let refund = journal.record_refund(&sale, &[2, 0], RefundReason::Faulty);
+        assert_eq!(refund.total, -184);
+        assert_eq!(
+            refund.tenders,
+            vec![
+                tender(PaymentMethod::Card, -17),
+                tender(PaymentMethod::Cash, -167),
+            ]
+        );
+        assert!(refund.kind.is_refund_of(1));
+    }
+}
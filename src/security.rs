@@ -0,0 +1,117 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use crate::{config::Config, data_error::DataError, utils::write_atomic};
+
+/// Counts failed logins and locks the login prompt for longer after each run of them
+/// Saved on every change so restarting the app doesn't reset it
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lockout {
+    #[serde(skip)]
+    path: String,
+    /// Failed attempts since the last login or lockout
+    pub failed_attempts: u32,
+    /// Lockouts since the last login, each one lasts twice as long as the one before
+    pub lockouts: u32,
+    pub locked_until: Option<DateTime<Local>>,
+}
+
+impl Lockout {
+    /// Loads the lockout from a YAML file given the path, starting unlocked if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let mut lockout = match fs::read_to_string(path) {
+            Ok(string) => serde_yaml::from_str::<Self>(&string)
+                .map_err(|error| DataError::parse(path, error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(DataError::io(path, error)),
+        };
+        lockout.path = path.to_owned();
+        Ok(lockout)
+    }
+
+    /// Unlocked with no failed attempts, saved to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Whole seconds left until logging in is allowed again, if locked
+    pub fn seconds_remaining(&self) -> Option<i64> {
+        let remaining = (self.locked_until? - Local::now()).num_seconds();
+        (remaining >= 0).then_some(remaining + 1)
+    }
+
+    /// Counts a failed attempt, returning how long the login is locked for if it was one too many
+    pub fn record_failure(&mut self, config: &Config) -> Option<Duration> {
+        self.failed_attempts += 1;
+
+        let mut locked_for = None;
+        if self.failed_attempts >= config.max_failed_attempts {
+            // double each time, but never more than a day
+            let seconds = config
+                .lockout_seconds
+                .saturating_mul(1 << self.lockouts.min(20))
+                .min(24 * 60 * 60);
+            let duration = Duration::seconds(seconds as i64);
+
+            self.locked_until = Some(Local::now() + duration);
+            self.failed_attempts = 0;
+            self.lockouts += 1;
+            locked_for = Some(duration);
+        }
+        locked_for
+    }
+
+    /// Clears everything after a successful login
+    pub fn record_success(&mut self) {
+        self.failed_attempts = 0;
+        self.lockouts = 0;
+        self.locked_until = None;
+    }
+
+    /// Saves itself to the YAML file it was loaded from, after every change
+    /// A lockout still holds until the app is closed when it can't be saved
+    pub fn save_yaml(&self) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(&self.path, &string).map_err(|error| DataError::io(&self.path, error))
+    }
+}
+
+/// Plain text log of logins, locks and failed attempts, one line each
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SecurityLog {
+    path: String,
+}
+
+impl SecurityLog {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+
+    /// Appends an event to the log with the time it happened
+    pub fn record(&self, event: impl Display) -> Result<(), DataError> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                writeln!(
+                    file,
+                    "{} {}",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    event
+                )
+            })
+            .map_err(|error| DataError::io(&self.path, error))
+    }
+}
@@ -1,75 +1,272 @@
 use iced::{
-    widget::{button, column, row, slider, text, text_input},
-    Length,
+    widget::{button, column, row, scrollable, slider, text, text_input, Column},
+    Alignment, Length,
 };
 
 use crate::{
-    utils::{notify, set_password},
-    Element, Message,
+    config::Config,
+    theme::ButtonStyle,
+    toast,
+    users::{valid_pin, Action, Role, Users},
+    Element, Message, ViewIndex,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct SettingsView {
-    input_password: String,
-    pub ui_scale: f64,
-}
-
-impl Default for SettingsView {
-    fn default() -> Self {
-        Self {
-            input_password: "".to_owned(),
-            ui_scale: 1.0,
-        }
-    }
+    input_user_name: String,
+    input_user_pin: String,
+    input_user_role: Role,
+    /// Whether the config changed since it was last saved
+    config_changed: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SettingsMessage {
-    PasswordChanged(String),
+    UserNameChanged(String),
+    UserPinChanged(String),
+    UserRoleSelected(Role),
+    SaveUser,
+    RemoveUser(String),
+    ViewRoleSelected(ViewIndex, Role),
+    ActionRoleSelected(Action, Role),
     ScaleChanged(f64),
-    SavePassword,
+    DiscountThresholdChanged(u32),
+    IdleLockChanged(u32),
+    OsNotificationsToggled,
+    /// Saving also happens when leaving the view
+    SaveSettings,
 }
 
 impl SettingsView {
-    pub fn view(&self) -> Element {
-        column![
-            // password
+    pub fn view(&self, config: &Config, users: &Users) -> Element {
+        let user_list = Column::with_children(
+            users
+                .users
+                .iter()
+                .map(|user| {
+                    row![
+                        text(&user.name).width(Length::Fill),
+                        text(user.role).width(Length::Units(120)),
+                        button("Remove").on_press(Message::Settings(SettingsMessage::RemoveUser(
+                            user.name.clone()
+                        ))),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        // the lowest role for each view and action, anyone above it can too
+        let view_roles = Column::with_children(
+            ViewIndex::ALL
+                .iter()
+                .map(|&view| {
+                    permission_row(
+                        view.to_string(),
+                        users.permissions.view_role(view),
+                        move |role| SettingsMessage::ViewRoleSelected(view, role),
+                    )
+                })
+                .collect(),
+        )
+        .spacing(5);
+        let action_roles = Column::with_children(
+            Action::ALL
+                .iter()
+                .map(|&action| {
+                    permission_row(
+                        action.to_string(),
+                        users.permissions.action_role(action),
+                        move |role| SettingsMessage::ActionRoleSelected(action, role),
+                    )
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        let content = column![
+            // user accounts
+            text("Users").size(30),
+            user_list,
             row![
-                text_input("Admin Password", &self.input_password, |input| {
-                    Message::Settings(SettingsMessage::PasswordChanged(input))
+                text_input("Name", &self.input_user_name, |input| {
+                    Message::Settings(SettingsMessage::UserNameChanged(input))
                 }),
-                button(text("Save Password"))
-                    .on_press(Message::Settings(SettingsMessage::SavePassword))
+                text_input("PIN", &self.input_user_pin, |input| {
+                    Message::Settings(SettingsMessage::UserPinChanged(input))
+                })
+                .password()
+                .width(Length::Units(120)),
             ]
+            .push(role_buttons(
+                self.input_user_role,
+                SettingsMessage::UserRoleSelected
+            ))
+            .push(button(text("Save User")).on_press(Message::Settings(SettingsMessage::SaveUser)))
             .spacing(10)
             .width(Length::Fill),
+            // who can open each view and do each action
+            text("Permissions").size(30),
+            text("Who can open").size(20),
+            view_roles,
+            text("Who can").size(20),
+            action_roles,
             // ui scale
             row![
                 "UI Scale",
-                slider(0.5..=2.0, self.ui_scale, |value| {
+                slider(0.5..=2.0, config.ui_scale, |value| {
                     Message::Settings(SettingsMessage::ScaleChanged(value))
                 })
                 .step(0.01),
-                text(self.ui_scale).width(Length::Units(60)),
+                text(config.ui_scale).width(Length::Units(60)),
+            ]
+            .spacing(10),
+            // discounts above this need a supervisor
+            row![
+                "Discounts needing approval above",
+                slider(0..=100, config.discount_password_threshold, |value| {
+                    Message::Settings(SettingsMessage::DiscountThresholdChanged(value))
+                }),
+                text(format!("{}%", config.discount_password_threshold)).width(Length::Units(60)),
             ]
             .spacing(10),
+            // 0 never locks
+            row![
+                "Lock when idle for",
+                slider(0..=60, config.idle_lock_minutes, |value| {
+                    Message::Settings(SettingsMessage::IdleLockChanged(value))
+                }),
+                text(if config.idle_lock_minutes == 0 {
+                    "Never".to_owned()
+                } else {
+                    format!("{} min", config.idle_lock_minutes)
+                })
+                .width(Length::Units(60)),
+            ]
+            .spacing(10),
+            row![
+                "Desktop notifications",
+                button(text(if config.os_notifications { "On" } else { "Off" }))
+                    .style(if config.os_notifications {
+                        ButtonStyle::ItemSelected
+                    } else {
+                        ButtonStyle::Item
+                    })
+                    .on_press(Message::Settings(SettingsMessage::OsNotificationsToggled)),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+            button(text("Save Settings"))
+                .style(ButtonStyle::Important)
+                .on_press(Message::Settings(SettingsMessage::SaveSettings)),
         ]
         .padding(20)
-        .spacing(10)
-        .into()
+        .spacing(10);
+
+        scrollable(content).height(Length::Fill).into()
     }
 
-    pub fn update(&mut self, message: SettingsMessage) {
+    pub fn update(&mut self, message: SettingsMessage, config: &mut Config, users: &mut Users) {
         match message {
-            SettingsMessage::PasswordChanged(value) => self.input_password = value,
-            SettingsMessage::SavePassword => {
-                set_password(&self.input_password);
-                notify(
-                    "Password set!",
-                    &format!("Set password to {}", self.input_password),
-                )
+            SettingsMessage::UserNameChanged(value) => self.input_user_name = value,
+            SettingsMessage::UserPinChanged(value) => self.input_user_pin = value,
+            SettingsMessage::UserRoleSelected(role) => self.input_user_role = role,
+            SettingsMessage::SaveUser => {
+                if self.input_user_name.trim().is_empty() {
+                    toast::warning("Invalid user", "Enter a name");
+                } else if !valid_pin(&self.input_user_pin) {
+                    toast::warning("Invalid user", "The PIN has to be at least 4 digits");
+                } else if !users.set_user(
+                    &self.input_user_name,
+                    &self.input_user_pin,
+                    self.input_user_role,
+                ) {
+                    toast::error("Can't save user", "There has to be at least one admin");
+                } else {
+                    toast::success(
+                        "User saved",
+                        &format!("Saved {} as {}", self.input_user_name, self.input_user_role),
+                    );
+                    self.input_user_name.clear();
+                    self.input_user_pin.clear();
+                    self.input_user_role = Role::default();
+                }
+            }
+            SettingsMessage::RemoveUser(name) => {
+                if !users.remove_user(&name) {
+                    toast::error("Can't remove user", "There has to be at least one admin");
+                }
+            }
+            SettingsMessage::ViewRoleSelected(view, role) => {
+                users.permissions.views.insert(view, role);
+                users.mark_dirty();
+            }
+            SettingsMessage::ActionRoleSelected(action, role) => {
+                users.permissions.actions.insert(action, role);
+                users.mark_dirty();
+            }
+            SettingsMessage::ScaleChanged(value) => {
+                config.ui_scale = value;
+                self.config_changed = true;
             }
-            SettingsMessage::ScaleChanged(value) => self.ui_scale = value,
+            SettingsMessage::DiscountThresholdChanged(value) => {
+                config.discount_password_threshold = value;
+                self.config_changed = true;
+            }
+            SettingsMessage::IdleLockChanged(value) => {
+                config.idle_lock_minutes = value;
+                self.config_changed = true;
+            }
+            SettingsMessage::OsNotificationsToggled => {
+                config.os_notifications = !config.os_notifications;
+                self.config_changed = true;
+            }
+            // saved by the app, which knows where
+            SettingsMessage::SaveSettings => {}
         }
     }
+
+    /// Whether the config needs saving, only true once for each round of changes
+    pub fn take_config_changed(&mut self) -> bool {
+        std::mem::take(&mut self.config_changed)
+    }
+}
+
+/// A button for each role, with the selected one highlighted
+fn role_buttons<'a>(selected: Role, on_press: impl Fn(Role) -> SettingsMessage) -> Element<'a> {
+    Row::with_children(
+        Role::ALL
+            .iter()
+            .map(|&role| {
+                button(text(role))
+                    .style(if role == selected {
+                        ButtonStyle::ItemSelected
+                    } else {
+                        ButtonStyle::Item
+                    })
+                    .on_press(Message::Settings(on_press(role)))
+                    .into()
+            })
+            .collect(),
+    )
+    .spacing(10)
+    .into()
+}
+
+/// The name of a view or action with the lowest role allowed to use it
+fn permission_row<'a>(
+    name: String,
+    selected: Role,
+    on_press: impl Fn(Role) -> SettingsMessage,
+) -> Element<'a> {
+    row![
+        text(name).width(Length::Fill),
+        role_buttons(selected, on_press),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
 }
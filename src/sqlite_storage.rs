@@ -0,0 +1,565 @@
+use chrono::{DateTime, Local};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    data_error::DataError,
+    item::Item,
+    item_db::{ItemDB, StockMovement},
+    sales_journal::{SaleLine, SaleRecord, SalesJournal},
+    storage::Storage,
+    suppliers::{Supplier, Suppliers},
+    users::{User, Users},
+};
+
+/// Schema changes in order, each one is applied once and counted in `PRAGMA user_version`
+/// Never edit one that has been released, add a new one instead
+const MIGRATIONS: &[&str] = &[
+    // 1: first version
+    "CREATE TABLE items (
+        barcode INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        price INTEGER NOT NULL,
+        image_path TEXT,
+        amount_in_stock INTEGER NOT NULL,
+        tax_class TEXT NOT NULL,
+        sold_by_weight INTEGER NOT NULL,
+        age_restricted INTEGER NOT NULL,
+        position INTEGER NOT NULL
+    );
+    CREATE INDEX items_position ON items (position);
+    CREATE TABLE sales (
+        number INTEGER PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        discount INTEGER NOT NULL,
+        discount_reason TEXT,
+        total INTEGER NOT NULL,
+        tax TEXT NOT NULL,
+        tenders TEXT NOT NULL,
+        change INTEGER NOT NULL,
+        age_checks TEXT NOT NULL,
+        cashier TEXT NOT NULL
+    );
+    CREATE TABLE sale_lines (
+        sale_number INTEGER NOT NULL REFERENCES sales (number),
+        position INTEGER NOT NULL,
+        barcode INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        unit_price INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        weight INTEGER,
+        promotion_savings INTEGER NOT NULL,
+        discount INTEGER NOT NULL,
+        discount_reason TEXT,
+        tax_class TEXT NOT NULL,
+        original_line INTEGER NOT NULL,
+        PRIMARY KEY (sale_number, position)
+    );
+    CREATE TABLE stock_movements (
+        id INTEGER PRIMARY KEY,
+        time TEXT NOT NULL,
+        barcode INTEGER NOT NULL,
+        change INTEGER NOT NULL,
+        user TEXT NOT NULL
+    );
+    CREATE INDEX stock_movements_barcode ON stock_movements (barcode);
+    CREATE TABLE users (
+        name TEXT PRIMARY KEY COLLATE NOCASE,
+        pin TEXT NOT NULL,
+        role TEXT NOT NULL
+    );
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 2: reasons for stock movements, the ones already recorded are marked as migrated
+    "ALTER TABLE stock_movements ADD COLUMN reason TEXT NOT NULL DEFAULT 'Migrated';
+    ALTER TABLE stock_movements ADD COLUMN supplier TEXT;
+    ALTER TABLE stock_movements ADD COLUMN reference TEXT;",
+    // 3: reordering
+    "ALTER TABLE items ADD COLUMN reorder_point INTEGER;
+    ALTER TABLE items ADD COLUMN reorder_quantity INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE items ADD COLUMN supplier TEXT;",
+    // 4: supplier registry, the supplier names from 3 are moved into it when loaded
+    "CREATE TABLE suppliers (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        contact TEXT NOT NULL,
+        phone TEXT NOT NULL,
+        email TEXT NOT NULL,
+        account_number TEXT NOT NULL,
+        lead_time_days INTEGER NOT NULL
+    );
+    ALTER TABLE items ADD COLUMN suppliers TEXT NOT NULL DEFAULT '[]';",
+    // 5: costing and categories
+    "ALTER TABLE items ADD COLUMN cost_price INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE items ADD COLUMN category TEXT NOT NULL DEFAULT '';
+    ALTER TABLE stock_movements ADD COLUMN unit_cost INTEGER;",
+];
+
+/// Stores everything in one SQLite database, only writing the rows that changed
+pub struct SqliteStorage {
+    path: String,
+    connection: Connection,
+    /// Whether the database didn't exist before it was opened, so it should be imported into
+    created: bool,
+}
+
+impl SqliteStorage {
+    /// Opens the database given the path, creating it and bringing the schema up to date as needed
+    pub fn open(path: &str) -> Result<Self, DataError> {
+        let mut connection =
+            Connection::open(path).map_err(|error| DataError::database(path, error))?;
+        let created = migrate(&mut connection).map_err(|error| DataError::database(path, error))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            connection,
+            created,
+        })
+    }
+
+    /// Whether the database was only just made
+    pub fn is_new(&self) -> bool {
+        self.created
+    }
+
+    fn error(&self, error: rusqlite::Error) -> DataError {
+        DataError::database(&self.path, error)
+    }
+
+    /// Writes the changed items and new stock movements, deleting everything else first if replacing
+    /// Items are stored by barcode, so nothing is written while two items share one
+    fn write_items(&mut self, item_db: &mut ItemDB, replace: bool) -> Result<(), DataError> {
+        if let Some(barcode) = item_db.duplicate_barcode() {
+            return Err(DataError::DuplicateBarcode(barcode));
+        }
+
+        let connection = &mut self.connection;
+        let result = (|| {
+            let transaction = connection.transaction()?;
+            if replace {
+                transaction.execute("DELETE FROM items", [])?;
+                transaction.execute("DELETE FROM stock_movements", [])?;
+            }
+            for (position, item) in item_db.changed_items() {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO items (barcode, name, price, image_path,
+                        amount_in_stock, tax_class, sold_by_weight, age_restricted,
+                        reorder_point, reorder_quantity, supplier, suppliers, cost_price,
+                        category, position)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    params![
+                        item.barcode as i64,
+                        item.name,
+                        item.price,
+                        item.image_path,
+                        item.amount_in_stock,
+                        enum_text(&item.tax_class),
+                        item.sold_by_weight,
+                        item.age_restricted,
+                        item.reorder_point,
+                        item.reorder_quantity,
+                        item.supplier,
+                        json_text(&item.suppliers)?,
+                        item.cost_price,
+                        item.category,
+                        position,
+                    ],
+                )?;
+            }
+            let mut removed = false;
+            for barcode in item_db.removed_barcodes() {
+                transaction.execute("DELETE FROM items WHERE barcode = ?1", [barcode as i64])?;
+                removed = true;
+            }
+            if removed && !replace {
+                // every item after a removed one moved up a place
+                let mut statement =
+                    transaction.prepare("UPDATE items SET position = ?1 WHERE barcode = ?2")?;
+                for (position, item) in item_db.items().iter().enumerate() {
+                    statement.execute(params![position, item.barcode as i64])?;
+                }
+            }
+            for movement in item_db.unsaved_movements() {
+                transaction.execute(
+                    "INSERT INTO stock_movements (time, barcode, change, reason, user, supplier,
+                        reference, unit_cost)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        movement.time.to_rfc3339(),
+                        movement.barcode as i64,
+                        movement.change,
+                        enum_text(&movement.reason),
+                        movement.user,
+                        movement.supplier,
+                        movement.reference,
+                        movement.unit_cost,
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })();
+
+        result.map_err(|error| self.error(error))?;
+        item_db.mark_saved();
+        Ok(())
+    }
+}
+
+/// Applies the migrations the database hasn't had yet, returning whether it was empty
+fn migrate(connection: &mut Connection) -> rusqlite::Result<bool> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        // each one fully applies or not at all
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+    Ok(version == 0)
+}
+
+impl Storage for SqliteStorage {
+    fn load_items(&mut self) -> Result<ItemDB, DataError> {
+        let movements = self
+            .connection
+            .prepare(
+                "SELECT time, barcode, change, reason, user, supplier, reference,
+                    unit_cost
+                FROM stock_movements ORDER BY id",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok(StockMovement {
+                            time: get_time(row, 0)?,
+                            barcode: row.get::<_, i64>(1)? as u64,
+                            change: row.get(2)?,
+                            reason: get_enum(row, 3)?,
+                            user: row.get(4)?,
+                            supplier: row.get(5)?,
+                            reference: row.get(6)?,
+                            unit_cost: row.get(7)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(|error| self.error(error))?;
+
+        let items = self
+            .connection
+            .prepare(
+                "SELECT barcode, name, price, image_path, amount_in_stock, tax_class,
+                    sold_by_weight, age_restricted, reorder_point, reorder_quantity, supplier,
+                    suppliers, cost_price, category
+                FROM items ORDER BY position",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok(Item {
+                            barcode: row.get::<_, i64>(0)? as u64,
+                            name: row.get(1)?,
+                            price: row.get(2)?,
+                            image_path: row.get(3)?,
+                            amount_in_stock: row.get(4)?,
+                            tax_class: get_enum(row, 5)?,
+                            sold_by_weight: row.get(6)?,
+                            age_restricted: row.get(7)?,
+                            reorder_point: row.get(8)?,
+                            reorder_quantity: row.get(9)?,
+                            supplier: row.get(10)?,
+                            suppliers: get_json(row, 11)?,
+                            cost_price: row.get(12)?,
+                            category: row.get(13)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(|error| self.error(error))?;
+
+        let mut item_db = ItemDB::new(items);
+        item_db.set_movements(movements);
+        Ok(item_db)
+    }
+
+    fn replace_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        item_db.mark_all_changed();
+        self.write_items(item_db, true)
+    }
+
+    fn save_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        self.write_items(item_db, false)
+    }
+
+    fn load_sales(&mut self) -> Result<Vec<SaleRecord>, DataError> {
+        let connection = &self.connection;
+        let result = (|| {
+            let mut lines: BTreeMap<u64, Vec<SaleLine>> = BTreeMap::new();
+            let mut statement = connection.prepare(
+                "SELECT sale_number, barcode, name, unit_price, quantity, weight,
+                    promotion_savings, discount, discount_reason, tax_class, original_line
+                FROM sale_lines ORDER BY sale_number, position",
+            )?;
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                lines
+                    .entry(row.get::<_, i64>(0)? as u64)
+                    .or_default()
+                    .push(SaleLine {
+                        barcode: row.get::<_, i64>(1)? as u64,
+                        name: row.get(2)?,
+                        unit_price: row.get(3)?,
+                        quantity: row.get(4)?,
+                        weight: row.get(5)?,
+                        promotion_savings: row.get(6)?,
+                        discount: row.get(7)?,
+                        discount_reason: row.get(8)?,
+                        tax_class: get_enum(row, 9)?,
+                        original_line: row.get(10)?,
+                    });
+            }
+
+            let mut statement = connection.prepare(
+                "SELECT number, timestamp, kind, discount, discount_reason, total, tax,
+                    tenders, change, age_checks, cashier
+                FROM sales ORDER BY number",
+            )?;
+            let records = statement
+                .query_map([], |row| {
+                    let number = row.get::<_, i64>(0)? as u64;
+                    Ok(SaleRecord {
+                        number,
+                        timestamp: get_time(row, 1)?,
+                        kind: get_json(row, 2)?,
+                        lines: Vec::new(),
+                        discount: row.get(3)?,
+                        discount_reason: row.get(4)?,
+                        total: row.get(5)?,
+                        tax: get_json(row, 6)?,
+                        tenders: get_json(row, 7)?,
+                        change: row.get(8)?,
+                        age_checks: get_json(row, 9)?,
+                        cashier: row.get(10)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(records
+                .into_iter()
+                .map(|mut record| {
+                    record.lines = lines.remove(&record.number).unwrap_or_default();
+                    record
+                })
+                .collect())
+        })();
+
+        result.map_err(|error| self.error(error))
+    }
+
+    fn save_sales(&mut self, sales_journal: &mut SalesJournal) -> Result<(), DataError> {
+        if sales_journal.unsaved().is_empty() {
+            return Ok(());
+        }
+
+        let connection = &mut self.connection;
+        let result = (|| {
+            let transaction = connection.transaction()?;
+            for record in sales_journal.unsaved() {
+                transaction.execute(
+                    "INSERT INTO sales (number, timestamp, kind, discount, discount_reason,
+                        total, tax, tenders, change, age_checks, cashier)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        record.number as i64,
+                        record.timestamp.to_rfc3339(),
+                        json_text(&record.kind)?,
+                        record.discount,
+                        record.discount_reason,
+                        record.total,
+                        json_text(&record.tax)?,
+                        json_text(&record.tenders)?,
+                        record.change,
+                        json_text(&record.age_checks)?,
+                        record.cashier,
+                    ],
+                )?;
+                for (position, line) in record.lines.iter().enumerate() {
+                    transaction.execute(
+                        "INSERT INTO sale_lines (sale_number, position, barcode, name,
+                            unit_price, quantity, weight, promotion_savings, discount,
+                            discount_reason, tax_class, original_line)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                        params![
+                            record.number as i64,
+                            position,
+                            line.barcode as i64,
+                            line.name,
+                            line.unit_price,
+                            line.quantity,
+                            line.weight,
+                            line.promotion_savings,
+                            line.discount,
+                            line.discount_reason,
+                            enum_text(&line.tax_class),
+                            line.original_line,
+                        ],
+                    )?;
+                }
+            }
+            transaction.commit()
+        })();
+
+        result.map_err(|error| self.error(error))?;
+        sales_journal.mark_saved();
+        Ok(())
+    }
+
+    fn load_users(&mut self) -> Result<Users, DataError> {
+        let connection = &self.connection;
+        let result = (|| {
+            let users = connection
+                .prepare("SELECT name, pin, role FROM users ORDER BY rowid")?
+                .query_map([], |row| {
+                    Ok(User {
+                        name: row.get(0)?,
+                        pin: row.get(1)?,
+                        role: get_enum(row, 2)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            let permissions = connection
+                .query_row(
+                    "SELECT value FROM settings WHERE key = 'permissions'",
+                    [],
+                    |row| get_json(row, 0),
+                )
+                .optional()?
+                .unwrap_or_default();
+
+            let mut loaded = Users::default();
+            loaded.users = users;
+            loaded.permissions = permissions;
+            Ok(loaded)
+        })();
+
+        result.map_err(|error| self.error(error))
+    }
+
+    /// Rewrites every user, there are only ever a handful
+    fn save_users(&mut self, users: &mut Users) -> Result<(), DataError> {
+        let connection = &mut self.connection;
+        let result = (|| {
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM users", [])?;
+            for user in users.users.iter() {
+                transaction.execute(
+                    "INSERT INTO users (name, pin, role) VALUES (?1, ?2, ?3)",
+                    params![user.name, user.pin, enum_text(&user.role)],
+                )?;
+            }
+            transaction.execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('permissions', ?1)",
+                [json_text(&users.permissions)?],
+            )?;
+            transaction.commit()
+        })();
+
+        result.map_err(|error| self.error(error))?;
+        users.mark_saved();
+        Ok(())
+    }
+
+    fn load_suppliers(&mut self) -> Result<Suppliers, DataError> {
+        let result = self
+            .connection
+            .prepare(
+                "SELECT id, name, contact, phone, email, account_number, lead_time_days
+                FROM suppliers ORDER BY id",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok(Supplier {
+                            id: row.get::<_, i64>(0)? as u64,
+                            name: row.get(1)?,
+                            contact: row.get(2)?,
+                            phone: row.get(3)?,
+                            email: row.get(4)?,
+                            account_number: row.get(5)?,
+                            lead_time_days: row.get(6)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            });
+
+        result
+            .map(Suppliers::new)
+            .map_err(|error| self.error(error))
+    }
+
+    /// Rewrites every supplier, like users there aren't many
+    fn save_suppliers(&mut self, suppliers: &mut Suppliers) -> Result<(), DataError> {
+        let connection = &mut self.connection;
+        let result = (|| {
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM suppliers", [])?;
+            for supplier in suppliers.suppliers.iter() {
+                transaction.execute(
+                    "INSERT INTO suppliers (id, name, contact, phone, email, account_number,
+                        lead_time_days)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        supplier.id as i64,
+                        supplier.name,
+                        supplier.contact,
+                        supplier.phone,
+                        supplier.email,
+                        supplier.account_number,
+                        supplier.lead_time_days,
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })();
+
+        result.map_err(|error| self.error(error))?;
+        suppliers.mark_saved();
+        Ok(())
+    }
+}
+
+/// Unit enums are stored as the name of the variant
+fn enum_text<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// Anything more complicated is stored as JSON
+fn json_text<T: Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value)
+        .map_err(|error| rusqlite::Error::ToSqlConversionFailure(error.into()))
+}
+
+fn get_enum<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    serde_json::from_value(serde_json::Value::String(row.get(index)?))
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, error.into()))
+}
+
+fn get_json<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    serde_json::from_str(&row.get::<_, String>(index)?)
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, error.into()))
+}
+
+fn get_time(row: &Row, index: usize) -> rusqlite::Result<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(&row.get::<_, String>(index)?)
+        .map(|time| time.with_timezone(&Local))
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, error.into()))
+}
@@ -0,0 +1,239 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind};
+
+use crate::{
+    data_error::DataError,
+    item_db::{ItemDB, MovementReason},
+    utils::write_atomic,
+};
+
+/// How many of one item were counted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockCount {
+    pub barcode: u64,
+    /// In grams for items sold by weight
+    pub counted: u32,
+    /// The recorded stock when the item was first counted, so sales and deliveries
+    /// before the count is applied aren't counted as a variance
+    pub expected: u32,
+}
+
+/// The difference between what was counted and the recorded stock of an item
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variance {
+    pub barcode: u64,
+    pub name: String,
+    pub expected: u32,
+    pub counted: u32,
+    pub sold_by_weight: bool,
+    /// Negative when less was counted than recorded
+    pub difference: i32,
+    /// The difference at retail price, in pence
+    pub value: i32,
+}
+
+/// A count of some or all of the stock, saved on every change so it can be finished after a restart
+/// Only the items counted are adjusted, so a shelf or category can be counted on its own
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockTake {
+    #[serde(skip)]
+    path: String,
+    /// None when no count is going on
+    pub started: Option<DateTime<Local>>,
+    /// In the order the items were first counted
+    pub counts: Vec<StockCount>,
+}
+
+impl StockTake {
+    /// Loads the count from a YAML file given the path, with none going on if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        let mut stock_take = match fs::read_to_string(path) {
+            Ok(string) => serde_yaml::from_str::<Self>(&string)
+                .map_err(|error| DataError::parse(path, error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(DataError::io(path, error)),
+        };
+        stock_take.path = path.to_owned();
+        Ok(stock_take)
+    }
+
+    /// An empty count that will be saved to the path
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub fn start(&mut self) -> Result<(), DataError> {
+        self.started = Some(Local::now());
+        self.counts.clear();
+        self.save_yaml()
+    }
+
+    /// Adds to the count of an item, counting the same item twice adds both up
+    /// `in_stock` is its recorded stock right now
+    pub fn count(&mut self, barcode: u64, quantity: u32, in_stock: u32) -> Result<(), DataError> {
+        match self
+            .counts
+            .iter_mut()
+            .find(|count| count.barcode == barcode)
+        {
+            Some(count) => count.counted += quantity,
+            None => self.counts.push(StockCount {
+                barcode,
+                counted: quantity,
+                expected: in_stock,
+            }),
+        }
+        self.save_yaml()
+    }
+
+    /// Forgets the count of an item, so it's left as it is or can be counted again
+    pub fn remove(&mut self, barcode: u64) -> Result<(), DataError> {
+        self.counts.retain(|count| count.barcode != barcode);
+        self.save_yaml()
+    }
+
+    /// Ends the count without changing any stock
+    pub fn discard(&mut self) -> Result<(), DataError> {
+        self.started = None;
+        self.counts.clear();
+        self.save_yaml()
+    }
+
+    /// The variance of every counted item still in the database, against its stock when it was counted
+    pub fn variances(&self, item_db: &ItemDB) -> Vec<Variance> {
+        self.counts
+            .iter()
+            .filter_map(|count| {
+                let item = item_db.get_item(count.barcode)?;
+                let difference = count.counted as i32 - count.expected as i32;
+                let value = if item.sold_by_weight {
+                    // price is per kg
+                    (difference as i64 * item.price as i64 / 1000) as i32
+                } else {
+                    difference * item.price as i32
+                };
+                Some(Variance {
+                    barcode: item.barcode,
+                    name: item.name.clone(),
+                    expected: count.expected,
+                    counted: count.counted,
+                    sold_by_weight: item.sold_by_weight,
+                    difference,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    /// Adjusts the stock of every counted item by its variance and ends the count,
+    /// so anything sold or delivered since it was counted is kept
+    /// Items that can't be adjusted are skipped so the rest still are
+    pub fn apply(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        let mut result = Ok(());
+        for variance in self.variances(item_db) {
+            if variance.difference == 0 {
+                continue;
+            }
+            if let Err(error) = item_db.move_stock(
+                variance.barcode,
+                variance.difference,
+                MovementReason::StockTake,
+            ) {
+                result = Err(error);
+            }
+        }
+
+        self.discard()?;
+        result
+    }
+
+    /// Saves itself to the YAML file it was loaded from
+    fn save_yaml(&self) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(&self.path, &string).map_err(|error| DataError::io(&self.path, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audit_log::AuditLog, item::Item};
+    use std::path::{Path, PathBuf};
+
+    /// A directory of its own for each test, removed at the end of it
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("stock_take_{}", name));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn item_db(directory: &Path) -> ItemDB {
+        let mut item_db = ItemDB::new(vec![
+            Item {
+                barcode: 1,
+                name: "Kofola 2l".to_owned(),
+                price: 250,
+                amount_in_stock: 10,
+                ..Default::default()
+            },
+            Item {
+                barcode: 2,
+                name: "Apples".to_owned(),
+                price: 500,
+                amount_in_stock: 3000,
+                sold_by_weight: true,
+                ..Default::default()
+            },
+        ]);
+        item_db.audit_log =
+            AuditLog::load(directory.join("audit_log.jsonl").to_str().unwrap()).unwrap();
+        item_db
+    }
+
+    fn stock_take(directory: &Path) -> StockTake {
+        StockTake::new(directory.join("stock_take.yaml").to_str().unwrap())
+    }
+
+    #[test]
+    fn variance_at_retail() {
+        let directory = temp_dir("variance");
+        let item_db = item_db(&directory);
+        let mut stock_take = stock_take(&directory);
+        stock_take.count(1, 5, 10).unwrap();
+        // counting the same item again adds to it
+        stock_take.count(1, 3, 10).unwrap();
+        stock_take.count(2, 2800, 3000).unwrap();
+
+        let variances = stock_take.variances(&item_db);
+        assert_eq!((variances[0].difference, variances[0].value), (-2, -500));
+        // 200g of £5/kg
+        assert_eq!((variances[1].difference, variances[1].value), (-200, -100));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn sales_after_counting_arent_a_variance() {
+        let directory = temp_dir("sales");
+        let mut item_db = item_db(&directory);
+        let mut stock_take = stock_take(&directory);
+        stock_take.count(1, 8, 10).unwrap();
+        item_db.move_stock(1, -3, MovementReason::Sale).unwrap();
+
+        assert_eq!(stock_take.variances(&item_db)[0].difference, -2);
+        stock_take.apply(&mut item_db).unwrap();
+        assert_eq!(item_db.get_item(1).unwrap().amount_in_stock, 5);
+        assert!(!stock_take.is_active());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,681 @@
+use iced::{
+    widget::{button, column, container, row, scrollable, text, text_input, Column, Row},
+    Alignment, Length,
+};
+
+use crate::{
+    data_error::DataError,
+    item::Item,
+    item_db::{ItemDB, MovementReason, StockMovement},
+    purchase_orders::{OrderStatus, PurchaseOrder, PurchaseOrders},
+    suppliers::Suppliers,
+    theme::ButtonStyle,
+    toast,
+    users::Action,
+    utils::{format_price, format_stock, parse_price, parse_weight},
+    Element, Message,
+};
+
+/// Most movements shown for the selected item, newest first
+const MAX_MOVEMENTS: usize = 100;
+/// Where purchase orders are exported to
+const EXPORT_DIRECTORY: &str = "./purchase_orders";
+
+/// What the stock view is being used for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StockMode {
+    #[default]
+    GoodsIn,
+    Adjustment,
+    PurchaseOrders,
+}
+
+/// One item of the delivery being booked in
+#[derive(Debug, Clone, PartialEq)]
+struct DeliveryLine {
+    barcode: u64,
+    quantity: u32,
+    /// What each cost if it was typed in, otherwise the supplier's cost price is used
+    unit_cost: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StockView {
+    mode: StockMode,
+    input_barcode: String,
+    input_quantity: String,
+    input_supplier: String,
+    input_reference: String,
+    input_unit_cost: String,
+    delivery: Vec<DeliveryLine>,
+    reason: MovementReason,
+    /// The item whose movements are shown, the last one scanned
+    selected: Option<u64>,
+    /// The number of the purchase order the delivery is for
+    order: Option<u64>,
+    /// The number of the purchase order shown next to the list
+    selected_order: Option<u64>,
+}
+
+impl Default for StockView {
+    fn default() -> Self {
+        Self {
+            mode: StockMode::default(),
+            input_barcode: String::new(),
+            input_quantity: String::new(),
+            input_supplier: String::new(),
+            input_reference: String::new(),
+            input_unit_cost: String::new(),
+            delivery: Vec::new(),
+            reason: MovementReason::Breakage,
+            selected: None,
+            order: None,
+            selected_order: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StockMessage {
+    ModeSelected(StockMode),
+    BarcodeChanged(String),
+    QuantityChanged(String),
+    SupplierChanged(String),
+    ReferenceChanged(String),
+    UnitCostChanged(String),
+    /// Adds the scanned item to the delivery
+    AddLine,
+    RemoveLine(usize),
+    BookIn,
+    ReasonSelected(MovementReason),
+    Adjust,
+    GenerateOrders,
+    SelectOrder(u64),
+    PrintOrder(u64),
+    ExportOrder(u64),
+    MarkOrderSent(u64),
+    DeleteOrder(u64),
+    /// Starts booking in a delivery for the order
+    ReceiveOrder(u64),
+    ClearOrder,
+}
+
+fn map(message: StockMessage) -> Message {
+    Message::Stock(message)
+}
+
+impl StockView {
+    pub fn view<'a>(
+        &'a self,
+        item_db: &'a ItemDB,
+        purchase_orders: &'a PurchaseOrders,
+    ) -> Element<'a> {
+        let mode_button = |label, mode| {
+            button(label)
+                .style(if self.mode == mode {
+                    ButtonStyle::ItemSelected
+                } else {
+                    ButtonStyle::Item
+                })
+                .on_press(map(StockMessage::ModeSelected(mode)))
+        };
+
+        let (form, side) = match self.mode {
+            StockMode::GoodsIn => (
+                self.render_goods_in(item_db, purchase_orders),
+                self.render_movements(item_db),
+            ),
+            StockMode::Adjustment => (
+                self.render_adjustment(item_db),
+                self.render_movements(item_db),
+            ),
+            StockMode::PurchaseOrders => (
+                self.render_orders(purchase_orders),
+                render_order_document(
+                    self.selected_order
+                        .and_then(|number| purchase_orders.get(number)),
+                ),
+            ),
+        };
+
+        column![
+            row![
+                mode_button("Goods In", StockMode::GoodsIn),
+                mode_button("Adjust Stock", StockMode::Adjustment),
+                mode_button("Purchase Orders", StockMode::PurchaseOrders),
+            ]
+            .spacing(10),
+            row![
+                container(form).width(Length::FillPortion(3)),
+                container(side).width(Length::FillPortion(2)),
+            ]
+            .spacing(20),
+        ]
+        .padding(20)
+        .spacing(20)
+        .into()
+    }
+
+    fn render_goods_in(&self, item_db: &ItemDB, purchase_orders: &PurchaseOrders) -> Element {
+        let lines = Column::with_children(
+            self.delivery
+                .iter()
+                .enumerate()
+                .map(|(index, line)| {
+                    let (name, quantity) = match item_db.get_item(line.barcode) {
+                        Some(item) => (
+                            item.name.clone(),
+                            format_stock(line.quantity, item.sold_by_weight),
+                        ),
+                        None => (line.barcode.to_string(), line.quantity.to_string()),
+                    };
+                    row![
+                        text(name).width(Length::Fill),
+                        text(line.unit_cost.map_or(String::new(), |unit_cost| {
+                            format!("at {}", format_price(unit_cost))
+                        })),
+                        text(quantity),
+                        button("x").on_press(map(StockMessage::RemoveLine(index))),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        // what's still to come on the order, next to what's been scanned so far
+        let order: Element = match self.order.and_then(|number| purchase_orders.get(number)) {
+            Some(order) => column![
+                row![
+                    text(format!(
+                        "Receiving {} from {}",
+                        order.file_name(),
+                        order.supplier
+                    ))
+                    .size(25)
+                    .width(Length::Fill),
+                    button("Not for an order").on_press(map(StockMessage::ClearOrder)),
+                ]
+                .spacing(10)
+                .align_items(Alignment::Center),
+                Column::with_children(
+                    order
+                        .lines
+                        .iter()
+                        .filter(|line| line.outstanding() > 0)
+                        .map(|line| {
+                            let scanned = self
+                                .delivery
+                                .iter()
+                                .find(|scanned| scanned.barcode == line.barcode)
+                                .map_or(0, |scanned| scanned.quantity);
+                            row![
+                                text(&line.name).width(Length::Fill),
+                                text(format!(
+                                    "{} of {}",
+                                    format_stock(scanned, line.sold_by_weight),
+                                    format_stock(line.outstanding(), line.sold_by_weight)
+                                )),
+                            ]
+                            .spacing(10)
+                            .into()
+                        })
+                        .collect(),
+                )
+                .spacing(5),
+            ]
+            .spacing(10)
+            .into(),
+            None => column![].into(),
+        };
+
+        column![
+            order,
+            row![
+                text_input("Supplier (optional)", &self.input_supplier, |string| {
+                    map(StockMessage::SupplierChanged(string))
+                }),
+                text_input(
+                    "Delivery note (optional)",
+                    &self.input_reference,
+                    |string| { map(StockMessage::ReferenceChanged(string)) }
+                ),
+            ]
+            .spacing(10),
+            row![
+                self.render_scan_row("Quantity received", map(StockMessage::AddLine)),
+                text_input("Unit cost (optional)", &self.input_unit_cost, |string| {
+                    map(StockMessage::UnitCostChanged(string))
+                })
+                .on_submit(map(StockMessage::AddLine))
+                .width(Length::Units(200)),
+            ]
+            .spacing(10),
+            scrollable(lines).height(Length::Fill),
+            button("Book In")
+                .style(ButtonStyle::Important)
+                .on_press(map(StockMessage::BookIn)),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn render_adjustment(&self, item_db: &ItemDB) -> Element {
+        let reasons = Row::with_children(
+            MovementReason::ADJUSTMENTS
+                .iter()
+                .map(|reason| {
+                    button(text(reason))
+                        .style(if self.reason == *reason {
+                            ButtonStyle::ItemSelected
+                        } else {
+                            ButtonStyle::Item
+                        })
+                        .on_press(map(StockMessage::ReasonSelected(*reason)))
+                        .into()
+                })
+                .collect(),
+        )
+        .spacing(10);
+
+        let hint = if self.reason == MovementReason::StockTake {
+            "Stock counted"
+        } else {
+            "Quantity lost"
+        };
+        let adjust =
+            Message::RequirePermission(Action::AdjustStock, Box::new(map(StockMessage::Adjust)));
+
+        let current = match self.selected_item(item_db) {
+            Some(item) => text(format!(
+                "{} in stock: {}",
+                item.name,
+                format_stock(item.amount_in_stock, item.sold_by_weight)
+            )),
+            None => text(""),
+        };
+
+        column![
+            reasons,
+            self.render_scan_row(hint, adjust.clone()),
+            current,
+            button("Adjust")
+                .style(ButtonStyle::Important)
+                .on_press(adjust),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn render_orders(&self, purchase_orders: &PurchaseOrders) -> Element {
+        let rows = Column::with_children(
+            purchase_orders
+                .orders
+                .iter()
+                .rev()
+                .map(|order| {
+                    let number = order.number;
+                    let mut actions = row![
+                        button("Print").on_press(map(StockMessage::PrintOrder(number))),
+                        button("Export").on_press(map(StockMessage::ExportOrder(number))),
+                    ]
+                    .spacing(5);
+                    actions = match order.status {
+                        OrderStatus::Draft => actions
+                            .push(
+                                button("Mark Sent")
+                                    .on_press(map(StockMessage::MarkOrderSent(number))),
+                            )
+                            .push(
+                                button("Delete").on_press(map(StockMessage::DeleteOrder(number))),
+                            ),
+                        OrderStatus::Sent | OrderStatus::PartReceived => actions.push(
+                            button("Receive")
+                                .style(ButtonStyle::Important)
+                                .on_press(map(StockMessage::ReceiveOrder(number))),
+                        ),
+                        OrderStatus::Received => actions,
+                    };
+
+                    row![
+                        button(text(format!(
+                            "{}  {}  {} lines",
+                            order.file_name(),
+                            order.supplier,
+                            order.lines.len()
+                        )))
+                        .style(if self.selected_order == Some(number) {
+                            ButtonStyle::ItemSelected
+                        } else {
+                            ButtonStyle::Item
+                        })
+                        .on_press(map(StockMessage::SelectOrder(number)))
+                        .width(Length::Fill),
+                        text(order.status.to_string()).width(Length::Units(110)),
+                        actions,
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        column![
+            row![
+                text("Orders are made for items at or below their reorder point")
+                    .width(Length::Fill),
+                button("Generate Purchase Orders")
+                    .style(ButtonStyle::Important)
+                    .on_press(map(StockMessage::GenerateOrders)),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+            scrollable(rows).height(Length::Fill),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    /// Barcode and quantity inputs, submitting either sends the message
+    fn render_scan_row(&self, quantity_hint: &str, submit: Message) -> Element {
+        row![
+            text_input("Scan or type a barcode", &self.input_barcode, |string| {
+                map(StockMessage::BarcodeChanged(string))
+            })
+            .on_submit(submit.clone()),
+            text_input(quantity_hint, &self.input_quantity, |string| {
+                map(StockMessage::QuantityChanged(string))
+            })
+            .on_submit(submit)
+            .width(Length::Units(200)),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    /// The ledger of the last item scanned
+    fn render_movements(&self, item_db: &ItemDB) -> Element {
+        let item = match self.selected_item(item_db) {
+            Some(item) => item,
+            None => return text("Scan an item to see its stock movements").into(),
+        };
+
+        let rows = Column::with_children(
+            item_db
+                .movements_of(item.barcode)
+                .take(MAX_MOVEMENTS)
+                .map(|movement| render_movement(item, movement))
+                .collect(),
+        )
+        .spacing(5);
+
+        column![
+            text(format!("Movements of {}", item.name)).size(25),
+            scrollable(rows).height(Length::Fill),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn selected_item<'a>(&self, item_db: &'a ItemDB) -> Option<&'a Item> {
+        self.selected.and_then(|barcode| item_db.get_item(barcode))
+    }
+
+    /// The item and quantity typed in, an empty quantity is one
+    /// Quantities of items sold by weight are in kg
+    fn parse_input(&self, item_db: &ItemDB) -> Option<(u64, u32)> {
+        let item = self
+            .input_barcode
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|barcode| item_db.get_item(barcode));
+        let item = match item {
+            Some(item) => item,
+            None => {
+                toast::warning("Invalid item", "Item with barcode couldn't be found");
+                return None;
+            }
+        };
+
+        let input = self.input_quantity.trim();
+        let quantity = match (input.is_empty(), item.sold_by_weight) {
+            (true, _) => Ok(1),
+            (false, true) => parse_weight(input),
+            (false, false) => input.parse().map_err(|_| ()),
+        };
+        match quantity {
+            Ok(quantity) => Some((item.barcode, quantity)),
+            Err(_) => {
+                toast::warning(
+                    "Invalid quantity",
+                    "Enter a whole number, or kg when sold by weight",
+                );
+                None
+            }
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        message: StockMessage,
+        item_db: &mut ItemDB,
+        purchase_orders: &mut PurchaseOrders,
+        suppliers: &Suppliers,
+    ) -> Result<(), DataError> {
+        match message {
+            StockMessage::ModeSelected(mode) => self.mode = mode,
+            StockMessage::BarcodeChanged(v) => self.input_barcode = v,
+            StockMessage::QuantityChanged(v) => self.input_quantity = v,
+            StockMessage::SupplierChanged(v) => self.input_supplier = v,
+            StockMessage::ReferenceChanged(v) => self.input_reference = v,
+            StockMessage::UnitCostChanged(v) => self.input_unit_cost = v,
+            StockMessage::AddLine => {
+                let unit_cost = match self.input_unit_cost.trim() {
+                    "" => None,
+                    input => match parse_price(input) {
+                        Ok(unit_cost) => Some(unit_cost),
+                        Err(_) => {
+                            toast::warning("Invalid unit cost", "Enter a price, e.g. 1.20");
+                            return Ok(());
+                        }
+                    },
+                };
+                if let Some((barcode, quantity)) = self.parse_input(item_db) {
+                    match self
+                        .delivery
+                        .iter_mut()
+                        .find(|line| line.barcode == barcode)
+                    {
+                        Some(line) => {
+                            line.quantity += quantity;
+                            line.unit_cost = unit_cost.or(line.unit_cost);
+                        }
+                        None => self.delivery.push(DeliveryLine {
+                            barcode,
+                            quantity,
+                            unit_cost,
+                        }),
+                    }
+                    self.selected = Some(barcode);
+                    self.input_barcode.clear();
+                    self.input_quantity.clear();
+                    self.input_unit_cost.clear();
+                }
+            }
+            StockMessage::RemoveLine(index) => {
+                if index < self.delivery.len() {
+                    self.delivery.remove(index);
+                }
+            }
+            StockMessage::BookIn => {
+                if self.delivery.is_empty() {
+                    toast::warning("Nothing to book in", "Scan the items delivered first");
+                    return Ok(());
+                }
+
+                let optional =
+                    |input: &str| Some(input.trim().to_owned()).filter(|s| !s.is_empty());
+                let supplier = optional(&self.input_supplier);
+                let reference = optional(&self.input_reference);
+                // lines without a unit cost are costed at the supplier's price, if they're in the registry
+                let supplier_id = supplier.as_ref().and_then(|name| {
+                    suppliers
+                        .suppliers
+                        .iter()
+                        .find(|registered| registered.name.eq_ignore_ascii_case(name))
+                        .map(|registered| registered.id)
+                });
+                // items deleted since they were scanned are skipped so the rest still get booked in
+                let mut result = Ok(());
+                let delivery = std::mem::take(&mut self.delivery);
+                for line in delivery.iter() {
+                    let unit_cost = line.unit_cost.or_else(|| {
+                        let item = item_db.get_item(line.barcode)?;
+                        item.suppliers
+                            .iter()
+                            .find(|link| Some(link.supplier) == supplier_id)
+                            .map(|link| link.cost_price)
+                            .filter(|cost_price| *cost_price > 0)
+                    });
+                    if let Err(error) = item_db.receive_goods(
+                        line.barcode,
+                        line.quantity,
+                        supplier.clone(),
+                        reference.clone(),
+                        unit_cost,
+                    ) {
+                        result = Err(error);
+                    }
+                }
+
+                if let Some(number) = self.order.take() {
+                    let received = delivery
+                        .iter()
+                        .map(|line| (line.barcode, line.quantity))
+                        .collect::<Vec<_>>();
+                    purchase_orders.receive(number, &received)?;
+                }
+
+                toast::success(
+                    "Goods booked in",
+                    &reference.map_or(String::new(), |reference| {
+                        format!("Delivery note {}", reference)
+                    }),
+                );
+                self.input_supplier.clear();
+                self.input_reference.clear();
+                return result;
+            }
+            StockMessage::ReasonSelected(reason) => self.reason = reason,
+            StockMessage::Adjust => {
+                let (barcode, quantity) = match self.parse_input(item_db) {
+                    Some(input) => input,
+                    None => return Ok(()),
+                };
+                self.selected = Some(barcode);
+
+                let change = if self.reason == MovementReason::StockTake {
+                    let stock = item_db
+                        .get_item(barcode)
+                        .map_or(0, |item| item.amount_in_stock);
+                    quantity as i32 - stock as i32
+                } else {
+                    -(quantity as i32)
+                };
+                if change == 0 {
+                    toast::warning("Nothing to adjust", "The stock is already right");
+                    return Ok(());
+                }
+
+                item_db.move_stock(barcode, change, self.reason)?;
+                toast::success("Stock adjusted", &self.reason.to_string());
+                self.input_barcode.clear();
+                self.input_quantity.clear();
+            }
+            StockMessage::GenerateOrders => {
+                let (generated, no_supplier) = purchase_orders.generate(item_db, suppliers)?;
+                if generated > 0 {
+                    toast::success(
+                        "Purchase orders generated",
+                        &format!("{} draft orders", generated),
+                    );
+                } else if no_supplier == 0 {
+                    toast::warning("Nothing to order", "No items are below their reorder point");
+                }
+                if no_supplier > 0 {
+                    toast::warning(
+                        "Items without a supplier",
+                        &format!("{} items to reorder have no supplier set", no_supplier),
+                    );
+                }
+            }
+            StockMessage::SelectOrder(number) => self.selected_order = Some(number),
+            StockMessage::PrintOrder(number) => {
+                if let Some(order) = purchase_orders.get(number) {
+                    let path = order.write_pdf(EXPORT_DIRECTORY)?;
+                    toast::success("Purchase order ready to print", &path);
+                }
+            }
+            StockMessage::ExportOrder(number) => {
+                if let Some(order) = purchase_orders.get(number) {
+                    let path = order.export(EXPORT_DIRECTORY)?;
+                    toast::success("Purchase order exported", &path);
+                }
+            }
+            StockMessage::MarkOrderSent(number) => purchase_orders.mark_sent(number)?,
+            StockMessage::DeleteOrder(number) => purchase_orders.delete_draft(number)?,
+            StockMessage::ReceiveOrder(number) => {
+                if let Some(order) = purchase_orders.get(number) {
+                    self.mode = StockMode::GoodsIn;
+                    self.order = Some(number);
+                    self.input_supplier = order.supplier.clone();
+                }
+            }
+            StockMessage::ClearOrder => self.order = None,
+        }
+        Ok(())
+    }
+}
+
+/// Utility to render an order as it's printed
+fn render_order_document<'a>(order: Option<&PurchaseOrder>) -> Element<'a> {
+    match order {
+        Some(order) => scrollable(text(order.generate_document().trim_start()).size(16))
+            .height(Length::Fill)
+            .into(),
+        None => text("Select an order to see it").into(),
+    }
+}
+
+/// Utility to render one line of an item's ledger
+fn render_movement<'a>(item: &Item, movement: &StockMovement) -> Element<'a> {
+    let change = format!(
+        "{}{}",
+        if movement.change < 0 { "-" } else { "+" },
+        format_stock(movement.change.unsigned_abs(), item.sold_by_weight)
+    );
+    let unit_cost = movement
+        .unit_cost
+        .map(|unit_cost| format!("at {}", format_price(unit_cost)));
+    let details = [&movement.supplier, &movement.reference, &unit_cost]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    row![
+        text(movement.time.format("%Y-%m-%d %H:%M")).width(Length::Units(150)),
+        text(movement.reason).width(Length::Units(180)),
+        text(change).width(Length::Units(100)),
+        text(&movement.user).width(Length::Units(120)),
+        text(details).width(Length::Fill),
+    ]
+    .spacing(10)
+    .into()
+}
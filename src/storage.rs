@@ -0,0 +1,262 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use crate::{
+    data_error::DataError,
+    item_db::ItemDB,
+    sales_journal::{SaleRecord, SalesJournal},
+    suppliers::Suppliers,
+    users::Users,
+    utils::{rotate_backups, write_atomic},
+};
+
+/// Which storage backend keeps the items, sales, users and suppliers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageBackend {
+    /// Items, users and suppliers in YAML files, sales in a JSON lines journal
+    #[default]
+    Yaml,
+    /// Everything in one embedded SQLite database
+    Sqlite,
+}
+
+impl Display for StorageBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StorageBackend::Yaml => "YAML",
+            StorageBackend::Sqlite => "SQLite",
+        })
+    }
+}
+
+/// Somewhere the items, sales, users and suppliers are kept between runs
+/// Each save only has to write what changed since the last one
+pub trait Storage {
+    /// Loads every item and stock movement, the audit log is kept separately
+    fn load_items(&mut self) -> Result<ItemDB, DataError>;
+    /// Replaces every stored item and stock movement, e.g. when importing or starting over
+    fn replace_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError>;
+    /// Saves the items changed and the stock movements recorded since the last save
+    fn save_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError>;
+
+    /// Loads every sale and refund, oldest first
+    fn load_sales(&mut self) -> Result<Vec<SaleRecord>, DataError>;
+    /// Saves the sales recorded since the last save
+    fn save_sales(&mut self, sales_journal: &mut SalesJournal) -> Result<(), DataError>;
+
+    fn load_users(&mut self) -> Result<Users, DataError>;
+    fn save_users(&mut self, users: &mut Users) -> Result<(), DataError>;
+
+    fn load_suppliers(&mut self) -> Result<Suppliers, DataError>;
+    fn save_suppliers(&mut self, suppliers: &mut Suppliers) -> Result<(), DataError>;
+}
+
+/// Copies everything from one backend into another
+pub fn import(from: &mut dyn Storage, to: &mut dyn Storage) -> Result<(), DataError> {
+    let mut item_db = from.load_items()?;
+    item_db.mark_all_changed();
+    to.replace_items(&mut item_db)?;
+
+    let mut sales_journal = SalesJournal::new(from.load_sales()?);
+    sales_journal.mark_all_unsaved();
+    to.save_sales(&mut sales_journal)?;
+
+    let mut users = from.load_users()?;
+    users.mark_dirty();
+    to.save_users(&mut users)?;
+
+    let mut suppliers = from.load_suppliers()?;
+    suppliers.mark_dirty();
+    to.save_suppliers(&mut suppliers)
+}
+
+/// The original storage, items, users and suppliers are rewritten in full on every save
+/// Sales and stock movements are kept in JSON lines files that are only appended to
+pub struct YamlStorage {
+    item_db_path: String,
+    /// Old versions of the item database to keep
+    backups: usize,
+    /// Whether the item database has been backed up since it was opened
+    backed_up: bool,
+    stock_movements_path: String,
+    sales_journal_path: String,
+    users_path: String,
+    suppliers_path: String,
+}
+
+impl YamlStorage {
+    pub fn new(
+        item_db_path: &str,
+        backups: usize,
+        stock_movements_path: &str,
+        sales_journal_path: &str,
+        users_path: &str,
+        suppliers_path: &str,
+    ) -> Self {
+        Self {
+            item_db_path: item_db_path.to_owned(),
+            backups,
+            backed_up: false,
+            stock_movements_path: stock_movements_path.to_owned(),
+            sales_journal_path: sales_journal_path.to_owned(),
+            users_path: users_path.to_owned(),
+            suppliers_path: suppliers_path.to_owned(),
+        }
+    }
+
+    /// Saves the item database, backing it up first if this is the first save since it was opened
+    /// So each backup is the item database as it was when a session started, not at the last save
+    fn save_item_db(&mut self, item_db: &ItemDB) -> Result<(), DataError> {
+        if !self.backed_up {
+            rotate_backups(&self.item_db_path, self.backups)
+                .map_err(|error| DataError::io(&self.item_db_path, error))?;
+            self.backed_up = true;
+        }
+        item_db.save_yaml(&self.item_db_path)
+    }
+}
+
+impl Storage for YamlStorage {
+    fn load_items(&mut self) -> Result<ItemDB, DataError> {
+        let mut item_db = ItemDB::load_yaml(&self.item_db_path)?;
+        item_db.set_movements(load_jsonl(&self.stock_movements_path)?);
+        Ok(item_db)
+    }
+
+    fn replace_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        self.save_item_db(item_db)?;
+        let lines = jsonl(&item_db.movements)?;
+        write_atomic(&self.stock_movements_path, &lines)
+            .map_err(|error| DataError::io(&self.stock_movements_path, error))?;
+        item_db.mark_saved();
+        Ok(())
+    }
+
+    fn save_items(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        self.save_item_db(item_db)?;
+        append_jsonl(&self.stock_movements_path, item_db.unsaved_movements())?;
+        item_db.mark_saved();
+        Ok(())
+    }
+
+    fn load_sales(&mut self) -> Result<Vec<SaleRecord>, DataError> {
+        load_jsonl(&self.sales_journal_path)
+    }
+
+    fn save_sales(&mut self, sales_journal: &mut SalesJournal) -> Result<(), DataError> {
+        append_jsonl(&self.sales_journal_path, sales_journal.unsaved())?;
+        sales_journal.mark_saved();
+        Ok(())
+    }
+
+    fn load_users(&mut self) -> Result<Users, DataError> {
+        Users::load_yaml(&self.users_path)
+    }
+
+    fn save_users(&mut self, users: &mut Users) -> Result<(), DataError> {
+        users.save_yaml(&self.users_path)?;
+        users.mark_saved();
+        Ok(())
+    }
+
+    fn load_suppliers(&mut self) -> Result<Suppliers, DataError> {
+        Suppliers::load_yaml(&self.suppliers_path)
+    }
+
+    fn save_suppliers(&mut self, suppliers: &mut Suppliers) -> Result<(), DataError> {
+        suppliers.save_yaml(&self.suppliers_path)?;
+        suppliers.mark_saved();
+        Ok(())
+    }
+}
+
+/// Reads a JSON lines file, starting empty if it doesn't exist yet
+/// A last line cut off while it was being written is removed from the file, so appending carries on
+pub fn load_jsonl<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, DataError> {
+    let string = match fs::read_to_string(path) {
+        Ok(string) => string,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(DataError::io(path, error)),
+    };
+
+    // every record is written along with its newline, so anything after the last one is torn
+    let complete = string.rfind('\n').map_or(0, |end| end + 1);
+    if complete < string.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(complete as u64))
+            .map_err(|error| DataError::io(path, error))?;
+    }
+
+    string[..complete]
+        .lines()
+        .enumerate()
+        .filter(|(_index, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str::<T>(line).map_err(|error| DataError::Parse {
+                path: path.to_owned(),
+                message: error.to_string(),
+                line: Some(index + 1),
+            })
+        })
+        .collect()
+}
+
+/// One JSON record per line
+fn jsonl<T: Serialize>(records: &[T]) -> Result<String, DataError> {
+    let mut lines = String::new();
+    for record in records {
+        lines += &serde_json::to_string(record)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Appends records to the end of a JSON lines file
+fn append_jsonl<T: Serialize>(path: &str, records: &[T]) -> Result<(), DataError> {
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let lines = jsonl(records)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|error| DataError::io(path, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torn_last_line_is_dropped() {
+        let directory = std::env::temp_dir().join("storage_torn_last_line");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("records.jsonl");
+        let path = path.to_str().unwrap();
+        fs::write(path, "[1]\n[2]\n[3").unwrap();
+
+        let records: Vec<Vec<u32>> = load_jsonl(path).unwrap();
+        assert_eq!(records, vec![vec![1], vec![2]]);
+        // the next record starts on a line of its own
+        append_jsonl(path, &[vec![4]]).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "[1]\n[2]\n[4]\n");
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let records: Vec<u32> = load_jsonl("./does_not_exist.jsonl").unwrap();
+        assert!(records.is_empty());
+    }
+}
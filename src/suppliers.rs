@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind};
+
+use crate::{data_error::DataError, item::ItemSupplier, item_db::ItemDB, utils::write_atomic};
+
+/// Someone items are bought from
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Supplier {
+    /// Items link to suppliers by this, so it never changes or gets reused
+    pub id: u64,
+    pub name: String,
+    /// Who to talk to there
+    #[serde(default)]
+    pub contact: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub email: String,
+    /// Our account number with them
+    #[serde(default)]
+    pub account_number: String,
+    /// How many days an order usually takes to arrive
+    #[serde(default)]
+    pub lead_time_days: u32,
+}
+
+/// Every supplier, in the order they were added
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suppliers {
+    pub suppliers: Vec<Supplier>,
+    /// Whether anything changed since the last save
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Suppliers {
+    pub fn new(suppliers: Vec<Supplier>) -> Self {
+        Self {
+            suppliers,
+            dirty: false,
+        }
+    }
+
+    /// Loads suppliers from a YAML file given the path, starting with none if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        match fs::read_to_string(path) {
+            Ok(string) => {
+                serde_yaml::from_str::<Self>(&string).map_err(|error| DataError::parse(path, error))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(DataError::io(path, error)),
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Supplier> {
+        self.suppliers.iter().find(|supplier| supplier.id == id)
+    }
+
+    /// The name of a supplier, or a placeholder if it was removed
+    pub fn name(&self, id: u64) -> String {
+        self.get(id)
+            .map_or(format!("Supplier #{}", id), |supplier| {
+                supplier.name.clone()
+            })
+    }
+
+    /// Adds a supplier if its id is 0, otherwise replaces the one with its id
+    /// Returns the id it was saved with
+    pub fn set_supplier(&mut self, mut supplier: Supplier) -> u64 {
+        match self
+            .suppliers
+            .iter_mut()
+            .find(|existing| supplier.id != 0 && existing.id == supplier.id)
+        {
+            Some(existing) => *existing = supplier.clone(),
+            None => {
+                supplier.id = self.next_id();
+                self.suppliers.push(supplier.clone());
+            }
+        }
+        self.dirty = true;
+        supplier.id
+    }
+
+    /// Removes a supplier, refusing while any item still links to it
+    pub fn remove_supplier(&mut self, id: u64, item_db: &ItemDB) -> bool {
+        let linked = item_db
+            .items()
+            .iter()
+            .any(|item| item.suppliers.iter().any(|link| link.supplier == id));
+        if linked {
+            return false;
+        }
+        self.suppliers.retain(|supplier| supplier.id != id);
+        self.dirty = true;
+        true
+    }
+
+    fn next_id(&self) -> u64 {
+        self.suppliers
+            .iter()
+            .map(|supplier| supplier.id)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Items saved before there was a registry only have the name of their supplier,
+    /// each name is added as a supplier if it isn't one yet and the item linked to it
+    pub fn import_legacy_names(&mut self, item_db: &mut ItemDB) -> Result<(), DataError> {
+        let legacy = item_db
+            .items()
+            .iter()
+            .filter(|item| item.supplier.is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for old in legacy {
+            let name = old.supplier.clone().unwrap_or_default();
+            let id = match self
+                .suppliers
+                .iter()
+                .find(|supplier| supplier.name.eq_ignore_ascii_case(name.trim()))
+            {
+                Some(supplier) => supplier.id,
+                None => self.set_supplier(Supplier {
+                    name: name.trim().to_owned(),
+                    ..Default::default()
+                }),
+            };
+
+            let mut new = old.clone();
+            new.supplier = None;
+            if !new.suppliers.iter().any(|link| link.supplier == id) {
+                // it was the preferred supplier
+                new.suppliers.insert(
+                    0,
+                    ItemSupplier {
+                        supplier: id,
+                        ..Default::default()
+                    },
+                );
+            }
+            item_db.replace_item(&old, new)?;
+        }
+        Ok(())
+    }
+
+    /// Whether anything changed since the last save
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Counts the suppliers as changed, so the next save writes them
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Forgets the changes once they've been saved
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Saves itself to a YAML file
+    pub fn save_yaml(&self, path: &str) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(path, &string).map_err(|error| DataError::io(path, error))
+    }
+}
@@ -0,0 +1,209 @@
+use iced::{
+    widget::{button, column, row, scrollable, text, text_input, Column},
+    Alignment, Length,
+};
+
+use crate::{
+    item_db::ItemDB,
+    suppliers::{Supplier, Suppliers},
+    theme::ButtonStyle,
+    toast, Element, Message,
+};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SuppliersView {
+    /// The id of the supplier being edited, None when adding one
+    selected: Option<u64>,
+    input_name: String,
+    input_contact: String,
+    input_phone: String,
+    input_email: String,
+    input_account_number: String,
+    input_lead_time: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SuppliersMessage {
+    SupplierSelected(u64),
+    NewSupplier,
+    NameChanged(String),
+    ContactChanged(String),
+    PhoneChanged(String),
+    EmailChanged(String),
+    AccountNumberChanged(String),
+    LeadTimeChanged(String),
+    SaveSupplier,
+    RemoveSupplier(u64),
+}
+
+fn map(message: SuppliersMessage) -> Message {
+    Message::Suppliers(message)
+}
+
+impl SuppliersView {
+    pub fn view<'a>(&'a self, suppliers: &'a Suppliers, item_db: &'a ItemDB) -> Element<'a> {
+        let supplier_list = Column::with_children(
+            suppliers
+                .suppliers
+                .iter()
+                .map(|supplier| {
+                    let items = item_db
+                        .items()
+                        .iter()
+                        .filter(|item| {
+                            item.suppliers
+                                .iter()
+                                .any(|link| link.supplier == supplier.id)
+                        })
+                        .count();
+                    button(text(format!("{}  ({} items)", supplier.name, items)))
+                        .style(if self.selected == Some(supplier.id) {
+                            ButtonStyle::ItemSelected
+                        } else {
+                            ButtonStyle::Item
+                        })
+                        .on_press(map(SuppliersMessage::SupplierSelected(supplier.id)))
+                        .width(Length::Fill)
+                        .into()
+                })
+                .collect(),
+        )
+        .spacing(5);
+
+        let mut actions = row![button(text("Save Supplier"))
+            .style(ButtonStyle::Important)
+            .on_press(map(SuppliersMessage::SaveSupplier))]
+        .spacing(10);
+        if let Some(id) = self.selected {
+            actions = actions
+                .push(button(text("Remove")).on_press(map(SuppliersMessage::RemoveSupplier(id))));
+        }
+
+        row![
+            // left side
+            column![
+                button(text("New Supplier")).on_press(map(SuppliersMessage::NewSupplier)),
+                scrollable(supplier_list).height(Length::Fill),
+            ]
+            .spacing(10)
+            .width(Length::FillPortion(1)),
+            // right side
+            column![
+                text(if self.selected.is_some() {
+                    "Edit supplier"
+                } else {
+                    "New supplier"
+                })
+                .size(30),
+                text_input("Name", &self.input_name, |input| {
+                    map(SuppliersMessage::NameChanged(input))
+                }),
+                text_input("Contact name", &self.input_contact, |input| {
+                    map(SuppliersMessage::ContactChanged(input))
+                }),
+                row![
+                    text_input("Phone", &self.input_phone, |input| {
+                        map(SuppliersMessage::PhoneChanged(input))
+                    }),
+                    text_input("Email", &self.input_email, |input| {
+                        map(SuppliersMessage::EmailChanged(input))
+                    }),
+                ]
+                .spacing(10),
+                row![
+                    text_input("Account number", &self.input_account_number, |input| {
+                        map(SuppliersMessage::AccountNumberChanged(input))
+                    }),
+                    text_input("Lead time (days)", &self.input_lead_time, |input| {
+                        map(SuppliersMessage::LeadTimeChanged(input))
+                    })
+                    .width(Length::Units(160)),
+                ]
+                .spacing(10),
+                actions,
+            ]
+            .spacing(10)
+            .align_items(Alignment::Start)
+            .width(Length::FillPortion(2)),
+        ]
+        .padding(20)
+        .spacing(20)
+        .into()
+    }
+
+    /// Fills the inputs with a supplier to edit, or clears them to add one
+    fn set_supplier(&mut self, supplier: Option<&Supplier>) {
+        *self = Self::default();
+        if let Some(supplier) = supplier {
+            self.selected = Some(supplier.id);
+            self.input_name = supplier.name.clone();
+            self.input_contact = supplier.contact.clone();
+            self.input_phone = supplier.phone.clone();
+            self.input_email = supplier.email.clone();
+            self.input_account_number = supplier.account_number.clone();
+            self.input_lead_time = supplier.lead_time_days.to_string();
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        message: SuppliersMessage,
+        suppliers: &mut Suppliers,
+        item_db: &ItemDB,
+    ) {
+        match message {
+            SuppliersMessage::SupplierSelected(id) => self.set_supplier(suppliers.get(id)),
+            SuppliersMessage::NewSupplier => self.set_supplier(None),
+            SuppliersMessage::NameChanged(value) => self.input_name = value,
+            SuppliersMessage::ContactChanged(value) => self.input_contact = value,
+            SuppliersMessage::PhoneChanged(value) => self.input_phone = value,
+            SuppliersMessage::EmailChanged(value) => self.input_email = value,
+            SuppliersMessage::AccountNumberChanged(value) => self.input_account_number = value,
+            SuppliersMessage::LeadTimeChanged(value) => self.input_lead_time = value,
+            SuppliersMessage::SaveSupplier => {
+                let lead_time_days = match self.input_lead_time.trim() {
+                    "" => Ok(0),
+                    input => input.parse(),
+                };
+                let name = self.input_name.trim();
+                if name.is_empty() {
+                    toast::warning("Invalid supplier", "Enter a name");
+                } else if suppliers.suppliers.iter().any(|supplier| {
+                    Some(supplier.id) != self.selected && supplier.name.eq_ignore_ascii_case(name)
+                }) {
+                    toast::warning(
+                        "Invalid supplier",
+                        "There's already a supplier with that name",
+                    );
+                } else if let Ok(lead_time_days) = lead_time_days {
+                    let id = suppliers.set_supplier(Supplier {
+                        id: self.selected.unwrap_or(0),
+                        name: name.to_owned(),
+                        contact: self.input_contact.trim().to_owned(),
+                        phone: self.input_phone.trim().to_owned(),
+                        email: self.input_email.trim().to_owned(),
+                        account_number: self.input_account_number.trim().to_owned(),
+                        lead_time_days,
+                    });
+                    toast::success("Supplier saved", name);
+                    self.set_supplier(suppliers.get(id));
+                } else {
+                    toast::warning(
+                        "Invalid supplier",
+                        "The lead time has to be a number of days",
+                    );
+                }
+            }
+            SuppliersMessage::RemoveSupplier(id) => {
+                if suppliers.remove_supplier(id, item_db) {
+                    self.set_supplier(None);
+                } else {
+                    toast::warning(
+                        "Can't remove supplier",
+                        "Items are still bought from them, remove it from those items first",
+                    );
+                }
+            }
+        }
+    }
+}
@@ -15,6 +15,11 @@ const BACKGROUND: Color = Color::from_rgb(1.0, 1.0, 1.0);
 const BACKGROUND_DARKER: Color = Color::from_rgb(0.9, 0.9, 0.9);
 const TEXT_COLOR: Color = Color::from_rgb(0.0, 0.0, 0.0);
 const ACCENT: Color = Color::from_rgb(0.8, 0.2, 0.2);
+const ERROR_BACKGROUND: Color = Color::from_rgb(1.0, 0.85, 0.85);
+const WARNING_BACKGROUND: Color = Color::from_rgb(1.0, 0.95, 0.8);
+const WARNING_BORDER: Color = Color::from_rgb(0.85, 0.6, 0.0);
+const SUCCESS_BACKGROUND: Color = Color::from_rgb(0.85, 0.95, 0.85);
+const SUCCESS_BORDER: Color = Color::from_rgb(0.2, 0.6, 0.2);
 const BORDER_RADIUS: f32 = 5.0;
 
 /// The theme for the application
@@ -155,16 +160,49 @@ impl button::StyleSheet for MyTheme {
     }
 }
 
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ContainerStyle {
+    #[default]
+    Default,
+    /// Banners and toasts for things that went wrong
+    Error,
+    Warning,
+    Success,
+}
+
 impl container::StyleSheet for MyTheme {
-    type Style = ();
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            // text_color: (),
-            background: Some(Background::Color(BACKGROUND_DARKER)),
-            // border_radius: (),
-            border_width: 1.0,
-            border_color: Color::BLACK,
-            ..Default::default()
+    type Style = ContainerStyle;
+    fn appearance(&self, style: &Self::Style) -> container::Appearance {
+        match style {
+            ContainerStyle::Default => container::Appearance {
+                // text_color: (),
+                background: Some(Background::Color(BACKGROUND_DARKER)),
+                // border_radius: (),
+                border_width: 1.0,
+                border_color: Color::BLACK,
+                ..Default::default()
+            },
+            ContainerStyle::Error => container::Appearance {
+                background: Some(Background::Color(ERROR_BACKGROUND)),
+                border_radius: BORDER_RADIUS,
+                border_width: 1.0,
+                border_color: ACCENT,
+                ..Default::default()
+            },
+            ContainerStyle::Warning => container::Appearance {
+                background: Some(Background::Color(WARNING_BACKGROUND)),
+                border_radius: BORDER_RADIUS,
+                border_width: 1.0,
+                border_color: WARNING_BORDER,
+                ..Default::default()
+            },
+            ContainerStyle::Success => container::Appearance {
+                background: Some(Background::Color(SUCCESS_BACKGROUND)),
+                border_radius: BORDER_RADIUS,
+                border_width: 1.0,
+                border_color: SUCCESS_BORDER,
+                ..Default::default()
+            },
         }
     }
 }
@@ -0,0 +1,235 @@
+use chrono::{DateTime, Local};
+use iced::{
+    widget::{button, column, container, row, scrollable, text, Column},
+    Alignment, Length,
+};
+use std::{
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{theme::ContainerStyle, Element, Message};
+
+/// Most toasts kept in the history panel, oldest are dropped first
+const MAX_HISTORY: usize = 200;
+
+/// How serious a toast is, which sets its colour and how long it stays up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastLevel {
+    /// How long it's shown before going away by itself
+    fn duration(&self) -> Duration {
+        Duration::from_secs(match self {
+            ToastLevel::Success => 3,
+            ToastLevel::Warning => 6,
+            ToastLevel::Error => 10,
+        })
+    }
+
+    fn style(&self) -> ContainerStyle {
+        match self {
+            ToastLevel::Success => ContainerStyle::Success,
+            ToastLevel::Warning => ContainerStyle::Warning,
+            ToastLevel::Error => ContainerStyle::Error,
+        }
+    }
+}
+
+impl Display for ToastLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ToastLevel::Success => "Success",
+            ToastLevel::Warning => "Warning",
+            ToastLevel::Error => "Error",
+        })
+    }
+}
+
+/// A short message shown over the open view
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toast {
+    pub level: ToastLevel,
+    pub title: String,
+    pub body: String,
+    pub time: DateTime<Local>,
+}
+
+/// Toasts raised since `App::update` last collected them
+/// Global so anything can raise one without having the app state at hand
+static QUEUE: Mutex<Vec<Toast>> = Mutex::new(Vec::new());
+
+fn push(level: ToastLevel, title: &str, body: &str) {
+    let toast = Toast {
+        level,
+        title: title.to_owned(),
+        body: body.to_owned(),
+        time: Local::now(),
+    };
+    // a panic elsewhere while holding the lock can't leave the queue half written
+    QUEUE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(toast);
+}
+
+/// Tells the user something worked
+pub fn success(title: &str, body: &str) {
+    push(ToastLevel::Success, title, body);
+}
+
+/// Tells the user something needs their attention, e.g. invalid input
+pub fn warning(title: &str, body: &str) {
+    push(ToastLevel::Warning, title, body);
+}
+
+/// Tells the user something failed
+pub fn error(title: &str, body: &str) {
+    push(ToastLevel::Error, title, body);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToastMessage {
+    Dismiss(usize),
+    ToggleHistory,
+    ClearHistory,
+}
+
+fn map(message: ToastMessage) -> Message {
+    Message::Toast(message)
+}
+
+/// The toasts on screen and every one shown since the app started
+#[derive(Debug, Default)]
+pub struct Toasts {
+    /// With when each was put on screen
+    active: Vec<(Toast, Instant)>,
+    history: Vec<Toast>,
+    show_history: bool,
+}
+
+impl Toasts {
+    /// Puts queued toasts on screen, returning them so they can be shown elsewhere too
+    pub fn collect(&mut self) -> Vec<Toast> {
+        let queued = std::mem::take(
+            &mut *QUEUE
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+
+        for toast in queued.iter() {
+            self.active.push((toast.clone(), Instant::now()));
+            self.history.push(toast.clone());
+        }
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+        }
+        queued
+    }
+
+    /// Removes toasts that have been up for long enough
+    pub fn expire(&mut self) {
+        self.active
+            .retain(|(toast, shown)| shown.elapsed() < toast.level.duration());
+    }
+
+    /// The toasts on screen with a button to open the history, or the history if it's open
+    pub fn view(&self) -> Element {
+        if self.show_history {
+            return self.view_history();
+        }
+
+        column![
+            self.view_active(),
+            button("History").on_press(map(ToastMessage::ToggleHistory)),
+        ]
+        .spacing(10)
+        .align_items(Alignment::End)
+        .width(Length::Units(350))
+        .into()
+    }
+
+    /// Just the toasts on screen, for showing inside modals
+    /// Only one overlay can be drawn at a time, so they can't float over an open modal
+    pub fn view_active(&self) -> Element {
+        Column::with_children(
+            self.active
+                .iter()
+                .enumerate()
+                .map(|(index, (toast, _))| render_toast(toast, Some(index)))
+                .collect(),
+        )
+        .spacing(10)
+        .into()
+    }
+
+    /// The panel with every toast shown so far, newest first
+    fn view_history(&self) -> Element {
+        let toasts = Column::with_children(
+            self.history
+                .iter()
+                .rev()
+                .map(|toast| render_toast(toast, None))
+                .collect(),
+        )
+        .spacing(10);
+
+        container(
+            column![
+                row![
+                    text("Notifications").size(25).width(Length::Fill),
+                    button("Clear").on_press(map(ToastMessage::ClearHistory)),
+                    button("Close").on_press(map(ToastMessage::ToggleHistory)),
+                ]
+                .spacing(10)
+                .align_items(Alignment::Center),
+                scrollable(toasts).height(Length::Units(400)),
+            ]
+            .spacing(10),
+        )
+        .padding(10)
+        .width(Length::Units(400))
+        .into()
+    }
+
+    pub fn update(&mut self, message: ToastMessage) {
+        match message {
+            ToastMessage::Dismiss(index) => {
+                if index < self.active.len() {
+                    self.active.remove(index);
+                }
+            }
+            ToastMessage::ToggleHistory => self.show_history = !self.show_history,
+            ToastMessage::ClearHistory => self.history.clear(),
+        }
+    }
+}
+
+/// Utility to render one toast, with a dismiss button if it's on screen
+fn render_toast<'a>(toast: &Toast, index: Option<usize>) -> Element<'a> {
+    let mut header = row![
+        text(format!("{}: {}", toast.level, toast.title)).width(Length::Fill),
+        text(toast.time.format("%H:%M:%S")).size(15),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center);
+    if let Some(index) = index {
+        header = header.push(button("x").on_press(map(ToastMessage::Dismiss(index))));
+    }
+
+    let mut content = column![header].spacing(5);
+    if !toast.body.is_empty() {
+        content = content.push(text(&toast.body).size(16));
+    }
+
+    container(content)
+        .style(toast.level.style())
+        .width(Length::Fill)
+        .padding(10)
+        .into()
+}
@@ -1,18 +1,160 @@
-use chrono;
+use chrono::{self, DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::{item::Item, utils::format_price};
+use crate::{
+    item::{Item, TaxClass},
+    sales_journal::SaleRecord,
+    utils::{format_price, format_signed_price, format_weight, parse_price},
+};
 
 /// Stores the state of a transaction
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
     pub items: Vec<TransactionItem>,
+    /// Discount on the whole basket, applied after the item discounts
+    #[serde(default)]
+    pub discount: Option<Discount>,
+    /// Every age check done during the transaction, kept for compliance
+    #[serde(default)]
+    pub age_checks: Vec<AgeCheck>,
 }
 
-/// An item in a transaction
-#[derive(Debug, Clone, PartialEq)]
+/// The cashier checking the age of a customer buying an age restricted item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgeCheck {
+    pub barcode: u64,
+    pub name: String,
+    /// True if the customer proved they were old enough, false if the sale was refused
+    pub approved: bool,
+    pub time: DateTime<Local>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransactionItem {
     pub item: Item,
     pub quantity: u32,
+    /// Weight in grams, for items sold by weight
+    #[serde(default)]
+    pub weight: Option<u32>,
+    /// Price in pence taken from a variable measure barcode, replacing the item price
+    #[serde(default)]
+    pub embedded_price: Option<u32>,
+    /// Savings from promotions, worked out again whenever the transaction changes
+    #[serde(default)]
+    pub promotions: Vec<PromotionSaving>,
+    #[serde(default)]
+    pub discount: Option<Discount>,
+}
+
+/// How much a promotion saves on a line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromotionSaving {
+    pub name: String,
+    pub amount: u32,
+}
+
+/// How a discount is calculated
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DiscountKind {
+    /// Percentage off the price
+    Percentage(u32),
+    /// Fixed amount off the price, in pence
+    Fixed(u32),
+}
+
+impl DiscountKind {
+    /// Parses a discount from a string
+    /// Parsable formats:
+    /// 10%
+    /// 1.50
+    /// £1.50
+    pub fn parse(input: &str) -> Result<Self, ()> {
+        let input = input.trim();
+        match input.strip_suffix('%') {
+            Some(percentage) => match percentage.trim().parse::<u32>() {
+                Ok(percentage) if percentage <= 100 => Ok(Self::Percentage(percentage)),
+                _ => Err(()),
+            },
+            None => parse_price(input).map(Self::Fixed),
+        }
+    }
+}
+
+/// A discount with the reason it was given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Discount {
+    pub kind: DiscountKind,
+    pub reason: String,
+    /// The biggest percentage of the price it has been allowed to take off,
+    /// a fixed discount grows as a percentage when the price goes down
+    #[serde(default)]
+    pub approved_percentage: u32,
+}
+
+impl Discount {
+    /// Calculates how much is taken off a price, never more than the price itself
+    pub fn amount(&self, price: u32) -> u32 {
+        match self.kind {
+            // round half a penny up
+            DiscountKind::Percentage(percentage) => (price * percentage.min(100) + 50) / 100,
+            DiscountKind::Fixed(amount) => amount.min(price),
+        }
+    }
+
+    /// Calculates what percentage of a price is taken off, rounded up
+    pub fn percentage_of(&self, price: u32) -> u32 {
+        if price == 0 {
+            return 0;
+        }
+        (self.amount(price) * 100 + price - 1) / price
+    }
+
+    /// Whether it takes off more of a price than the threshold and than was approved
+    pub fn needs_approval(&self, price: u32, threshold: u32) -> bool {
+        self.percentage_of(price) > threshold.max(self.approved_percentage)
+    }
+}
+
+/// VAT totals for one rate, worked out from VAT inclusive prices
+/// Negative for refunds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxSummary {
+    pub class: TaxClass,
+    pub net: i32,
+    pub tax: i32,
+    pub gross: i32,
+}
+
+impl TaxSummary {
+    /// Works out the VAT contained in a VAT inclusive amount, rounded to the nearest penny
+    pub fn from_gross(class: TaxClass, gross: i32) -> Self {
+        let rate = class.rate() as i64;
+        let numerator = gross as i64 * rate;
+        let denominator = 100 + rate;
+        // round halves away from zero
+        let tax = (numerator.abs() * 2 + denominator) / (denominator * 2) * numerator.signum();
+
+        Self {
+            class,
+            net: gross - tax as i32,
+            tax: tax as i32,
+            gross,
+        }
+    }
+
+    /// Adds up VAT inclusive amounts into a summary for each rate
+    /// VAT is rounded once per rate rather than per line
+    pub fn summarise(amounts: impl Iterator<Item = (TaxClass, i32)>) -> Vec<Self> {
+        let mut gross_by_class: BTreeMap<TaxClass, i32> = BTreeMap::new();
+        for (class, amount) in amounts {
+            *gross_by_class.entry(class).or_default() += amount;
+        }
+
+        gross_by_class
+            .into_iter()
+            .map(|(class, gross)| Self::from_gross(class, gross))
+            .collect()
+    }
 }
 
 impl TransactionItem {
@@ -21,22 +163,185 @@ impl TransactionItem {
         Self {
             item: item.clone(),
             quantity: 1,
+            weight: None,
+            embedded_price: None,
+            promotions: Vec::new(),
+            discount: None,
+        }
+    }
+
+    /// Whether the line was weighed or priced individually rather than counted
+    pub fn is_measured(&self) -> bool {
+        self.weight.is_some() || self.embedded_price.is_some()
+    }
+
+    /// Price of one of the line, worked out from the weight or barcode if there is one
+    pub fn unit_price(&self) -> u32 {
+        match (self.embedded_price, self.weight) {
+            (Some(price), _) => price,
+            // round to the nearest penny
+            (None, Some(weight)) => (self.item.price * weight + 500) / 1000,
+            (None, None) => self.item.price,
         }
     }
+
+    /// Weight of one of the line in grams, if the item is sold by weight
+    pub fn unit_weight(&self) -> Option<u32> {
+        match (self.weight, self.embedded_price) {
+            (Some(weight), _) => Some(weight),
+            // work the weight out from the price
+            (None, Some(price)) if self.item.sold_by_weight && self.item.price > 0 => {
+                Some(price * 1000 / self.item.price)
+            }
+            _ => None,
+        }
+    }
+
+    /// How much stock the line uses up, in grams for items sold by weight
+    pub fn stock_quantity(&self) -> u32 {
+        self.unit_weight()
+            .map_or(self.quantity, |weight| weight * self.quantity)
+    }
+
+    /// Price of the line before any promotions or discounts
+    pub fn subtotal(&self) -> u32 {
+        self.unit_price() * self.quantity
+    }
+
+    /// Amount saved on the line by promotions
+    pub fn promotion_savings(&self) -> u32 {
+        self.promotions.iter().map(|saving| saving.amount).sum()
+    }
+
+    /// Price of the line after promotions, which is what its discount applies to
+    pub fn price_after_promotions(&self) -> u32 {
+        self.subtotal().saturating_sub(self.promotion_savings())
+    }
+
+    /// Amount taken off the line by its discount
+    pub fn discount_amount(&self) -> u32 {
+        self.discount
+            .as_ref()
+            .map_or(0, |discount| discount.amount(self.price_after_promotions()))
+    }
+
+    /// Price of the line after promotions and discounts
+    pub fn total_price(&self) -> u32 {
+        self.price_after_promotions() - self.discount_amount()
+    }
 }
 
 impl Transaction {
+    /// Calculates price of all items after item discounts, but before the basket discount
+    pub fn subtotal(&self) -> u32 {
+        self.items
+            .iter()
+            .fold(0, |sum, item| sum + item.total_price())
+    }
+
+    /// Amount taken off by the basket discount
+    pub fn discount_amount(&self) -> u32 {
+        self.discount
+            .as_ref()
+            .map_or(0, |discount| discount.amount(self.subtotal()))
+    }
+
     /// Calculates total price of all items
     pub fn total_price(&self) -> u32 {
-        self.items.iter().fold(0, |sum, item| {
-            sum + (item.item.price * item.quantity as u32)
-        })
+        self.subtotal() - self.discount_amount()
+    }
+
+    /// Whether any discount takes off more than the threshold without being approved,
+    /// which happens when the price of what it was given on goes down
+    pub fn discounts_need_approval(&self, threshold: u32) -> bool {
+        let items = self.items.iter().any(|item| {
+            item.discount.as_ref().map_or(false, |discount| {
+                discount.needs_approval(item.price_after_promotions(), threshold)
+            })
+        });
+        let basket = self.discount.as_ref().map_or(false, |discount| {
+            discount.needs_approval(self.subtotal(), threshold)
+        });
+        items || basket
+    }
+
+    /// Approves every discount at the percentage it takes off now
+    pub fn approve_discounts(&mut self) {
+        for item in &mut self.items {
+            let price = item.price_after_promotions();
+            if let Some(discount) = &mut item.discount {
+                discount.approved_percentage = discount.percentage_of(price);
+            }
+        }
+        let subtotal = self.subtotal();
+        if let Some(discount) = &mut self.discount {
+            discount.approved_percentage = discount.percentage_of(subtotal);
+        }
+    }
+
+    /// Whether the customer's age has been checked and approved already
+    pub fn age_verified(&self) -> bool {
+        self.age_checks.iter().any(|check| check.approved)
+    }
+
+    /// Records the outcome of an age check for an item
+    pub fn record_age_check(&mut self, item: &Item, approved: bool) {
+        self.age_checks.push(AgeCheck {
+            barcode: item.barcode,
+            name: item.name.clone(),
+            approved,
+            time: Local::now(),
+        });
+    }
+
+    /// Calculates VAT totals for each rate in the transaction
+    pub fn tax_breakdown(&self) -> Vec<TaxSummary> {
+        TaxSummary::summarise(
+            self.items
+                .iter()
+                .zip(self.basket_discount_shares())
+                .map(|(item, share)| (item.item.tax_class, (item.total_price() - share) as i32)),
+        )
+    }
+
+    /// Shares the basket discount out between the lines by their price, to the penny
+    fn basket_discount_shares(&self) -> Vec<u32> {
+        let subtotal = self.subtotal() as u64;
+        let discount = self.discount_amount() as u64;
+        if subtotal == 0 || discount == 0 {
+            return vec![0; self.items.len()];
+        }
+
+        let mut shares = self
+            .items
+            .iter()
+            .map(|item| discount * item.total_price() as u64 / subtotal)
+            .collect::<Vec<_>>();
+
+        // give the pennies lost to rounding down to the lines that lost the most
+        let mut leftover = discount - shares.iter().sum::<u64>();
+        let mut by_remainder = (0..self.items.len()).collect::<Vec<_>>();
+        by_remainder.sort_by_key(|index| {
+            std::cmp::Reverse(discount * self.items[*index].total_price() as u64 % subtotal)
+        });
+        for index in by_remainder {
+            if leftover == 0 {
+                break;
+            }
+            shares[index] += 1;
+            leftover -= 1;
+        }
+
+        shares.into_iter().map(|share| share as u32).collect()
     }
 
     /// Adds an item to the transaction
     /// Returns true if the item has existed already
     pub fn add_item(&mut self, item: &Item) -> bool {
-        let found = self.items.iter_mut().find(|t_item| &t_item.item == item);
+        let found = self
+            .items
+            .iter_mut()
+            .find(|t_item| &t_item.item == item && !t_item.is_measured());
 
         match found {
             // If item exists already, add 1 to the quantity
@@ -52,6 +357,22 @@ impl Transaction {
         }
     }
 
+    /// Adds a weighed item to the transaction as a line of its own
+    pub fn add_weighed_item(&mut self, item: &Item, weight: u32) {
+        self.items.push(TransactionItem {
+            weight: Some(weight),
+            ..TransactionItem::new(item)
+        });
+    }
+
+    /// Adds an item with a price from a variable measure barcode as a line of its own
+    pub fn add_priced_item(&mut self, item: &Item, price: u32) {
+        self.items.push(TransactionItem {
+            embedded_price: Some(price),
+            ..TransactionItem::new(item)
+        });
+    }
+
     /// Modifies the quantity of an `Item` in the transaction
     pub fn modify_quantity(&mut self, item: &Item, quantity: i32) {
         let (index, found) = self
@@ -73,7 +394,7 @@ impl Transaction {
     }
 
     /// Create a string with the receipt in plain text
-    pub fn generate_receipt(&self) -> String {
+    pub fn generate_receipt(&self, record: &SaleRecord) -> String {
         if self.items.len() == 0 {
             "".to_string();
         }
@@ -82,6 +403,8 @@ impl Transaction {
             "Swansea Food Centre     {}\n",
             chrono::offset::Local::now().format("%d. %m. %H:%M:%S")
         ));
+        string.push_str(&format!("Receipt no. {}\n", record.number));
+        string.push_str(&format!("Served by {}\n", record.cashier));
         string.push_str("========================================\n");
 
         //  3     30      7
@@ -92,18 +415,188 @@ impl Transaction {
                 "{0: <3}{1: <30}{2: >7}\n",
                 &item.quantity,
                 &item.item.name,
-                format_price(&item.item.price * item.quantity),
+                format_price(item.subtotal()),
             ));
+            if let Some(weight) = item.unit_weight() {
+                string.push_str(&format!(
+                    "   {} @ {}/kg\n",
+                    format_weight(weight),
+                    format_price(item.item.price),
+                ));
+            }
+            for saving in &item.promotions {
+                string.push_str(&format!(
+                    "   {0: <29}{1: >8}\n",
+                    &saving.name,
+                    format!("-{}", format_price(saving.amount)),
+                ));
+            }
+            if let Some(discount) = &item.discount {
+                string.push_str(&format!(
+                    "   {0: <29}{1: >8}\n",
+                    &discount.reason,
+                    format!("-{}", format_price(item.discount_amount())),
+                ));
+            }
         }
 
         string.push_str("========================================\n");
 
+        if let Some(discount) = &self.discount {
+            string.push_str(&format!(
+                "{0: <33}{1: >7}\n",
+                "SUBTOTAL:",
+                format_price(self.subtotal())
+            ));
+            string.push_str(&format!(
+                "{0: <32}{1: >8}\n",
+                &discount.reason,
+                format!("-{}", format_price(self.discount_amount()))
+            ));
+        }
+
         string.push_str(&format!(
             "{0: <33}{1: >7}\n",
             "TOTAL PRICE PAID:",
             format_price(self.total_price())
         ));
+        for tender in &record.tenders {
+            string.push_str(&format!(
+                "{0: <33}{1: >7}\n",
+                format!("{}:", tender.method.to_string().to_uppercase()),
+                format_signed_price(tender.amount)
+            ));
+            if let Some(auth_code) = &tender.auth_code {
+                string.push_str(&format!("   AUTH CODE: {}\n", auth_code));
+            }
+        }
+        if record.change > 0 {
+            string.push_str(&format!(
+                "{0: <33}{1: >7}\n",
+                "CHANGE:",
+                format_price(record.change)
+            ));
+        }
+
+        string.push_str("========================================\n");
+        string.push_str(&format!(
+            "{0: <10}{1: >10}{2: >10}{3: >10}\n",
+            "VAT RATE", "NET", "VAT", "GROSS"
+        ));
+        for summary in self.tax_breakdown() {
+            string.push_str(&format!(
+                "{0: <10}{1: >10}{2: >10}{3: >10}\n",
+                format!("{}%", summary.class.rate()),
+                format_signed_price(summary.net),
+                format_signed_price(summary.tax),
+                format_signed_price(summary.gross),
+            ));
+        }
 
         string
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(barcode: u64, price: u32, tax_class: TaxClass) -> Item {
+        Item {
+            barcode,
+            price,
+            tax_class,
+            ..Default::default()
+        }
+    }
+
+    fn discount(kind: DiscountKind) -> Discount {
+        Discount {
+            kind,
+            reason: "Damaged".to_owned(),
+            approved_percentage: 0,
+        }
+    }
+
+    #[test]
+    fn vat_from_gross() {
+        assert_eq!(
+            TaxSummary::from_gross(TaxClass::Standard, 120),
+            TaxSummary {
+                class: TaxClass::Standard,
+                net: 100,
+                tax: 20,
+                gross: 120,
+            }
+        );
+        assert_eq!(TaxSummary::from_gross(TaxClass::Reduced, 105).tax, 5);
+        assert_eq!(TaxSummary::from_gross(TaxClass::Zero, 100).tax, 0);
+        // half a penny rounds away from zero, refunds included
+        assert_eq!(TaxSummary::from_gross(TaxClass::Standard, 3).tax, 1);
+        assert_eq!(TaxSummary::from_gross(TaxClass::Standard, -3).tax, -1);
+    }
+
+    #[test]
+    fn vat_rounded_once_per_rate() {
+        // 10p on its own has 2p VAT, three of them have 5p not 6p
+        let summaries = TaxSummary::summarise(
+            [
+                (TaxClass::Standard, 10),
+                (TaxClass::Zero, 50),
+                (TaxClass::Standard, 10),
+                (TaxClass::Standard, 10),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].class, TaxClass::Standard);
+        assert_eq!((summaries[0].gross, summaries[0].tax), (30, 5));
+        assert_eq!((summaries[1].gross, summaries[1].tax), (50, 0));
+    }
+
+    #[test]
+    fn vat_after_basket_discount() {
+        let mut transaction = Transaction::default();
+        transaction.add_item(&item(1, 600, TaxClass::Standard));
+        transaction.add_item(&item(2, 400, TaxClass::Zero));
+        transaction.discount = Some(discount(DiscountKind::Fixed(100)));
+
+        let breakdown = transaction.tax_breakdown();
+        assert_eq!((breakdown[0].gross, breakdown[0].tax), (540, 90));
+        assert_eq!((breakdown[1].gross, breakdown[1].tax), (360, 0));
+        let gross: i32 = breakdown.iter().map(|summary| summary.gross).sum();
+        assert_eq!(gross, transaction.total_price() as i32);
+    }
+
+    #[test]
+    fn discount_threshold() {
+        assert!(!discount(DiscountKind::Percentage(10)).needs_approval(1000, 10));
+        assert!(discount(DiscountKind::Percentage(11)).needs_approval(1000, 10));
+        // 1p off 999p is just over 0.1%, which rounds up
+        assert_eq!(discount(DiscountKind::Fixed(1)).percentage_of(999), 1);
+
+        let mut approved = discount(DiscountKind::Fixed(200));
+        assert!(approved.needs_approval(1000, 10));
+        approved.approved_percentage = 20;
+        assert!(!approved.needs_approval(1000, 10));
+        // the same amount off something cheaper is a bigger discount
+        assert!(approved.needs_approval(500, 10));
+    }
+
+    #[test]
+    fn discount_checked_again_when_price_drops() {
+        let bread = item(1, 200, TaxClass::Zero);
+        let mut transaction = Transaction::default();
+        transaction.add_item(&bread);
+        transaction.add_item(&bread);
+        transaction.items[0].discount = Some(discount(DiscountKind::Fixed(100)));
+        assert!(transaction.discounts_need_approval(10));
+
+        transaction.approve_discounts();
+        assert!(!transaction.discounts_need_approval(10));
+
+        // £1 off £2 instead of £4
+        transaction.modify_quantity(&bread, -1);
+        assert!(transaction.discounts_need_approval(10));
+    }
+}
@@ -1,18 +1,32 @@
 use iced::{
     alignment::{Horizontal, Vertical},
+    futures::channel::oneshot,
     keyboard::KeyCode,
-    widget::{button, column, image, row, scrollable, text, text_input, Column, Row},
-    Alignment, Event, Length, Renderer,
+    widget::{button, column, image, row, scrollable, text, text_input, Column, Row, Space},
+    Alignment, Command, Event, Length, Renderer,
 };
 
 use iced_aw::Modal;
+use std::{fs, thread};
 
 use crate::{
+    barcode::{parse_variable_measure, EmbeddedValue},
+    config::Config,
+    data_error::DataError,
     item::Item,
-    item_db::ItemDB,
+    item_db::{ItemDB, MovementReason},
+    parked_transactions::ParkedTransactions,
+    payment_terminal::PaymentResult,
+    pdf::write_text_pdf,
+    promotions::Promotions,
+    sales_journal::{PaymentMethod, RefundReason, SaleKind, SaleRecord, SalesJournal, Tender},
+    security::SecurityLog,
+    show_error,
     theme::ButtonStyle,
-    transaction::{Transaction, TransactionItem},
-    utils::{format_price, get_handle, notify, parse_price},
+    toast::{self, Toasts},
+    transaction::{Discount, DiscountKind, Transaction, TransactionItem},
+    users::Action,
+    utils::{format_price, format_weight, get_handle, parse_price, parse_weight},
     Message,
 };
 
@@ -25,15 +39,50 @@ pub struct TransactionsView {
     pub selected_index: usize,
     pub input_code: String,
 
-    input_cash_given: String,
+    input_tender_amount: String,
+    tenders: Vec<Tender>,
+    pending_card_amount: Option<u32>,
+    card_result: Option<PaymentResult>,
+    /// Waiting on the terminal to reverse the card payments being voided
+    reversing_tenders: bool,
+    input_weight: String,
+    weighing_item: Option<Item>,
+    age_check_item: Option<Item>,
     open_modal: Option<ModalType>,
+
+    input_refund_number: String,
+    refund_quantities: Vec<u32>,
+    refund_reason: Option<RefundReason>,
+
+    parked: ParkedTransactions,
+    input_park_label: String,
+
+    promotions: Promotions,
+
+    input_discount: String,
+    input_discount_reason: String,
+    discount_target: DiscountTarget,
+}
+
+/// What the discount modal applies a discount to
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum DiscountTarget {
+    /// The selected item
+    #[default]
+    Item,
+    Basket,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 enum ModalType {
-    CashOrCard,
-    CashChange,
-    CardAcceptOrDecline,
+    Tender,
+    CardPayment,
+    Refund,
+    EnterWeight,
+    AgeCheck,
+    Park,
+    Recall,
+    Discount,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,24 +91,77 @@ pub enum TransactionsMessage {
     AddItem(Item),
     FinishTransaction,
     ModifySelectedItemQuantity(i32),
-    SelectItem(Item),
-    CashSelected,
-    CardSelected,
-    PaymentAccepted,
-    PaymentDeclined,
-    CashGivenChanged(String),
+    SelectItem(usize),
+    TenderAmountChanged(String),
+    CashTender,
+    CardTender,
+    RemoveTender(usize),
+    /// Takes back every payment made so far, so the sale can be cancelled
+    /// Card payments are reversed on the terminal first
+    VoidTenders,
+    /// What the terminal said about reversing each card payment, in order
+    TendersReversed(Vec<PaymentResult>),
+    TerminalResponded(PaymentResult),
+    RetryCardPayment,
+    CancelCardPayment,
+    WeightChanged(String),
+    ConfirmWeight,
+    AgeConfirmed,
+    AgeRefused,
+    OpenRefund,
+    RefundNumberChanged(String),
+    ModifyRefundQuantity(usize, i32),
+    RefundReasonSelected(RefundReason),
+    ConfirmRefund,
+    OpenPark,
+    ParkLabelChanged(String),
+    ConfirmPark,
+    OpenRecall,
+    RecallTransaction(usize),
+    OpenDiscount,
+    DiscountChanged(String),
+    DiscountReasonChanged(String),
+    DiscountTargetSelected(DiscountTarget),
+    ApplyDiscount,
+    RemoveDiscount,
+    /// A supervisor allowing discounts that grew past the threshold, then maybe finishing
+    ApproveDiscounts {
+        finish: bool,
+    },
+    CloseModal,
 }
 
+const RECEIPT_DIRECTORY: &str = "./receipts";
+
 fn map(message: TransactionsMessage) -> Message {
     Message::Transactions(message)
 }
 
 impl TransactionsView {
-    pub fn view(&self, item_db: &ItemDB) -> Element {
+    pub fn new(parked: ParkedTransactions, promotions: Promotions) -> Self {
+        Self {
+            parked,
+            promotions,
+            ..Default::default()
+        }
+    }
+
+    /// Whether one of the modals is open, which covers the whole window
+    pub fn has_modal(&self) -> bool {
+        self.open_modal.is_some()
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        item_db: &ItemDB,
+        sales_journal: &'a SalesJournal,
+        config: &'a Config,
+        toasts: &'a Toasts,
+    ) -> Element<'a> {
         // ====================================== LEFT HALF =============================================
 
         // quick add buttons
-        let left_half = column![item_db.items.iter().fold(Row::new(), |row, item| row
+        let left_half = column![item_db.items().iter().fold(Row::new(), |row, item| row
             .push(render_quick_item_button(item.clone())))]
         .padding(20)
         .spacing(10);
@@ -119,10 +221,45 @@ impl TransactionsView {
         .on_press(map(TransactionsMessage::FinishTransaction))
         .height(Length::Shrink);
 
+        let actions_bar = row![
+            button(text("Park"))
+                .on_press(map(TransactionsMessage::OpenPark))
+                .padding(10),
+            button(text(format!("Recall ({})", self.parked.transactions.len())))
+                .on_press(map(TransactionsMessage::OpenRecall))
+                .padding(10),
+            button(text("Discount"))
+                .on_press(map(TransactionsMessage::OpenDiscount))
+                .padding(10),
+            button(text("Refund"))
+                .on_press(Message::RequirePermission(
+                    Action::Refund,
+                    Box::new(map(TransactionsMessage::OpenRefund))
+                ))
+                .padding(10),
+            Space::new(Length::Fill, Length::Shrink),
+            button(text("Log out"))
+                .on_press(Message::Logout)
+                .padding(10),
+        ]
+        .spacing(10);
+
+        let basket_discount: Element = match &self.current_transaction.discount {
+            Some(discount) => text(format!(
+                "{}: -{}",
+                discount.reason,
+                format_price(self.current_transaction.discount_amount())
+            ))
+            .into(),
+            None => Space::new(Length::Shrink, Length::Shrink).into(),
+        };
+
         let right_half: Column<Message, Renderer<MyTheme>> = column![
             text(&self.input_code),
             render_transaction(&self.current_transaction, self.selected_index),
             quantity_bar,
+            actions_bar,
+            basket_discount,
             finish_transaction_button,
         ]
         .padding(20)
@@ -139,61 +276,660 @@ impl TransactionsView {
             if self.open_modal.is_none() {
                 return "unreachable".into();
             };
-            match self.open_modal.as_ref().unwrap() {
-                ModalType::CashOrCard => column![
-                    button("Cash")
-                        .on_press(Message::Transactions(TransactionsMessage::CashSelected)),
-                    button("Card")
-                        .on_press(Message::Transactions(TransactionsMessage::CardSelected))
-                ]
-                .into(),
-                ModalType::CashChange => column![
-                    text_input("Enter cash given", &self.input_cash_given, |string| {
-                        Message::Transactions(TransactionsMessage::CashGivenChanged(string))
-                    }),
-                    if let Ok(parsed) = parse_price(&self.input_cash_given) {
-                        let price = self.current_transaction.total_price();
-                        if parsed < price {
-                            text(format!("{} more needed", format_price(price - parsed)))
-                        } else {
-                            text(format!("Change: {}", format_price(parsed - price)))
-                        }
-                    } else {
-                        text("Invalid input")
-                    },
+            let modal: Element = match self.open_modal.as_ref().unwrap() {
+                ModalType::Tender => self.render_tender(),
+                ModalType::CardPayment => self.render_card_payment(),
+                ModalType::Refund => self.render_refund(sales_journal),
+                ModalType::EnterWeight => self.render_weight(),
+                ModalType::AgeCheck => self.render_age_check(),
+                ModalType::Park => column![
+                    text("Park transaction"),
+                    text_input("Enter a label", &self.input_park_label, |string| {
+                        map(TransactionsMessage::ParkLabelChanged(string))
+                    })
+                    .on_submit(map(TransactionsMessage::ConfirmPark)),
                     row![
-                        button("Accept")
-                            .on_press(Message::Transactions(TransactionsMessage::PaymentAccepted)),
-                        button("Decline")
-                            .on_press(Message::Transactions(TransactionsMessage::PaymentDeclined)),
+                        button("Park").on_press(map(TransactionsMessage::ConfirmPark)),
+                        button("Cancel").on_press(map(TransactionsMessage::CloseModal)),
                     ]
+                    .spacing(10)
                 ]
+                .spacing(10)
+                .width(Length::Units(400))
                 .into(),
-                ModalType::CardAcceptOrDecline => row![
-                    button("Accept")
-                        .on_press(Message::Transactions(TransactionsMessage::PaymentAccepted)),
-                    button("Decline")
-                        .on_press(Message::Transactions(TransactionsMessage::PaymentDeclined)),
+                ModalType::Recall => column![
+                    text("Recall transaction"),
+                    render_parked(&self.parked),
+                    button("Cancel").on_press(map(TransactionsMessage::CloseModal)),
                 ]
+                .spacing(10)
+                .width(Length::Units(600))
                 .into(),
-            }
+                ModalType::Discount => self.render_discount(config),
+            };
+            column![toasts.view_active(), modal].spacing(10).into()
         })
         .into();
 
         content
     }
 
-    /// Clear the transaction, print receipt
-    fn finish_transaction(&mut self, item_db: &mut ItemDB) {
-        println!("{}", self.current_transaction.generate_receipt());
-        item_db.update_quantities_from_transaction(&self.current_transaction);
+    /// Renders the contents of the tender modal, which takes payments until the basket is paid
+    fn render_tender(&self) -> Element {
+        if self.reversing_tenders {
+            return column![
+                text("Voiding payments").size(30),
+                text("Reversing card payments on the terminal..."),
+            ]
+            .spacing(10)
+            .width(Length::Units(500))
+            .into();
+        }
+
+        let tenders = column(
+            self.tenders
+                .iter()
+                .enumerate()
+                .map(|(index, tender)| {
+                    let mut tender_row = row![
+                        text(tender.method).width(Length::Fill),
+                        text(format_price(tender.amount as u32)),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center);
+                    // cash can be handed back, card payments can't be undone from here
+                    if tender.method == PaymentMethod::Cash {
+                        tender_row = tender_row.push(
+                            button("Remove")
+                                .on_press(map(TransactionsMessage::RemoveTender(index))),
+                        );
+                    }
+                    tender_row.into()
+                })
+                .collect(),
+        )
+        .spacing(10);
+
+        let amount = match self.tender_amount() {
+            Some(amount) => text(format!("Paying {}", format_price(amount))),
+            None => text("Invalid amount"),
+        };
+
+        // payments have to be voided before the sale can be cancelled
+        let cancel_button = if self.tenders.is_empty() {
+            button("Cancel").on_press(map(TransactionsMessage::CloseModal))
+        } else {
+            button("Void payments").on_press(Message::RequirePermission(
+                Action::VoidTenders,
+                Box::new(map(TransactionsMessage::VoidTenders)),
+            ))
+        };
+
+        column![
+            text(format!(
+                "Total: {}",
+                format_price(self.current_transaction.total_price())
+            ))
+            .size(30),
+            tenders,
+            text(format!(
+                "Remaining: {}",
+                format_price(self.remaining_balance())
+            ))
+            .size(30),
+            text_input(
+                "Amount, leave empty to pay the rest",
+                &self.input_tender_amount,
+                |string| map(TransactionsMessage::TenderAmountChanged(string))
+            ),
+            amount,
+            row![
+                button("Cash")
+                    .style(ButtonStyle::Important)
+                    .on_press(map(TransactionsMessage::CashTender)),
+                button("Card")
+                    .style(ButtonStyle::Important)
+                    .on_press(map(TransactionsMessage::CardTender)),
+                cancel_button,
+            ]
+            .spacing(10)
+        ]
+        .spacing(10)
+        .width(Length::Units(500))
+        .into()
+    }
+
+    /// Renders the contents of the card payment modal, waiting on the terminal until it answers
+    fn render_card_payment(&self) -> Element {
+        let amount = text(format!(
+            "Card payment of {}",
+            format_price(self.pending_card_amount.unwrap_or(0))
+        ))
+        .size(30);
+
+        match &self.card_result {
+            // still waiting, the terminal gives up by itself after the timeout
+            None => column![
+                amount,
+                text("Waiting for the customer to use the card terminal..."),
+            ],
+            Some(result) => column![
+                amount,
+                text(result),
+                row![
+                    button("Try again")
+                        .style(ButtonStyle::Important)
+                        .on_press(map(TransactionsMessage::RetryCardPayment)),
+                    button("Back").on_press(map(TransactionsMessage::CancelCardPayment)),
+                ]
+                .spacing(10)
+            ],
+        }
+        .spacing(10)
+        .width(Length::Units(500))
+        .into()
+    }
+
+    /// Removes the cash payments and the card payments the terminal reversed, logging them
+    /// `results` are the answers for each card payment in order, any not reversed are kept
+    fn void_tenders(
+        &mut self,
+        results: Vec<PaymentResult>,
+        sales_journal: &SalesJournal,
+        security_log: &SecurityLog,
+    ) -> Command<Message> {
+        let mut results = results.into_iter();
+        let mut voided = Vec::new();
+        let mut not_reversed = Vec::new();
+        self.tenders.retain(|tender| {
+            let description = match &tender.auth_code {
+                Some(auth_code) => format!(
+                    "{} {} (auth {})",
+                    tender.method,
+                    format_price(tender.amount as u32),
+                    auth_code
+                ),
+                None => format!("{} {}", tender.method, format_price(tender.amount as u32)),
+            };
+            if tender.method == PaymentMethod::Card {
+                match results.next() {
+                    Some(PaymentResult::Approved { .. }) => {}
+                    Some(result) => {
+                        not_reversed.push(format!("{}: {}", description, result));
+                        return true;
+                    }
+                    None => {
+                        not_reversed.push(format!("{}: no answer", description));
+                        return true;
+                    }
+                }
+            }
+            voided.push(description);
+            false
+        });
+        self.input_tender_amount.clear();
+
+        let mut command = Command::none();
+        if !voided.is_empty() {
+            let result = security_log.record(format!(
+                "{} voided payments: {}",
+                sales_journal.cashier,
+                voided.join(", ")
+            ));
+            if let Err(error) = result {
+                command = show_error(error);
+            }
+        }
+        if not_reversed.is_empty() {
+            toast::warning("Payments voided", "Give back any cash");
+        } else {
+            // they're still paid, so the sale can't be cancelled until they're reversed
+            toast::error("Card payments not reversed", &not_reversed.join("\n"));
+        }
+        command
+    }
+
+    /// Sends the pending card payment to the terminal, which answers with a message once done
+    fn request_card_payment(&mut self, config: &Config) -> Command<Message> {
+        let amount = match self.pending_card_amount {
+            Some(amount) => amount,
+            None => return Command::none(),
+        };
+        self.card_result = None;
+        self.open_modal = Some(ModalType::CardPayment);
+
+        // the terminal blocks until the customer is done, so it gets a thread of its own
+        // instead of holding up the executor the window runs on
+        let provider = config.payment_terminal.provider(config.terminal_timeout);
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = sender.send(provider.request_payment(amount));
+        });
+        Command::perform(
+            async move {
+                receiver.await.unwrap_or_else(|_| {
+                    PaymentResult::Failed("The terminal stopped unexpectedly".to_owned())
+                })
+            },
+            |result| map(TransactionsMessage::TerminalResponded(result)),
+        )
+    }
+
+    /// What is still left to pay of the current transaction
+    fn remaining_balance(&self) -> u32 {
+        let tendered: i32 = self.tenders.iter().map(|tender| tender.amount).sum();
+        (self.current_transaction.total_price() as i32 - tendered).max(0) as u32
+    }
+
+    /// The amount entered in the tender modal, or the remaining balance if nothing was entered
+    fn tender_amount(&self) -> Option<u32> {
+        if self.input_tender_amount.trim().is_empty() {
+            Some(self.remaining_balance())
+        } else {
+            parse_price(&self.input_tender_amount).ok()
+        }
+    }
+
+    /// Takes a payment, finishing the transaction once it's fully paid
+    fn add_tender(
+        &mut self,
+        tender: Tender,
+        item_db: &mut ItemDB,
+        sales_journal: &mut SalesJournal,
+    ) -> Result<(), DataError> {
+        self.tenders.push(tender);
+        self.input_tender_amount.clear();
+
+        if self.remaining_balance() == 0 {
+            return self.finish_transaction(item_db, sales_journal);
+        }
+        self.open_modal = Some(ModalType::Tender);
+        Ok(())
+    }
+
+    /// Renders the contents of the refund modal
+    fn render_refund<'a>(&'a self, sales_journal: &'a SalesJournal) -> Element<'a> {
+        let lines: Element = match self.refund_original(sales_journal) {
+            Some(original) => column(
+                original
+                    .lines
+                    .iter()
+                    .zip(self.refund_quantities.iter())
+                    .enumerate()
+                    .map(|(index, (line, quantity))| {
+                        let refunded = sales_journal.refunded_quantity(original, index);
+                        row![
+                            text(&line.name).width(Length::Fill),
+                            text(format!("{} sold, {} refunded", line.quantity, refunded)),
+                            button("-").on_press(map(TransactionsMessage::ModifyRefundQuantity(
+                                index, -1
+                            ))),
+                            text(quantity),
+                            button("+")
+                                .on_press(map(TransactionsMessage::ModifyRefundQuantity(index, 1))),
+                        ]
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .into()
+                    })
+                    .collect(),
+            )
+            .spacing(10)
+            .into(),
+            None => text("No sale with this receipt number").into(),
+        };
+
+        let reasons = row(RefundReason::ALL
+            .iter()
+            .map(|reason| {
+                render_choice(
+                    reason,
+                    self.refund_reason == Some(*reason),
+                    map(TransactionsMessage::RefundReasonSelected(*reason)),
+                )
+            })
+            .collect())
+        .spacing(10);
+
+        let total: u32 = self
+            .refund_original(sales_journal)
+            .map(|original| {
+                self.refund_quantities
+                    .iter()
+                    .enumerate()
+                    .map(|(index, quantity)| {
+                        sales_journal.refund_amount(original, index, *quantity)
+                    })
+                    .sum()
+            })
+            .unwrap_or(0);
+
+        column![
+            text("Refund"),
+            text_input(
+                "Enter receipt number",
+                &self.input_refund_number,
+                |string| { map(TransactionsMessage::RefundNumberChanged(string)) }
+            ),
+            lines,
+            reasons,
+            row![
+                button(text(format!("Confirm refund ({})", format_price(total))))
+                    .style(ButtonStyle::Important)
+                    .on_press(map(TransactionsMessage::ConfirmRefund)),
+                button("Cancel").on_press(map(TransactionsMessage::CloseModal)),
+            ]
+            .spacing(10)
+        ]
+        .spacing(10)
+        .width(Length::Units(600))
+        .into()
+    }
+
+    /// Renders the contents of the modal asking for the weight of an item
+    fn render_weight(&self) -> Element {
+        let item = match &self.weighing_item {
+            Some(item) => item,
+            None => return "unreachable".into(),
+        };
+
+        let preview = match parse_weight(&self.input_weight) {
+            Ok(weight) => text(format!(
+                "{} @ {}/kg = {}",
+                format_weight(weight),
+                format_price(item.price),
+                format_price((item.price * weight + 500) / 1000)
+            )),
+            Err(_) => text("Invalid weight"),
+        };
+
+        column![
+            text(format!("Weigh {}", item.name)),
+            text_input("Enter weight in kg", &self.input_weight, |string| {
+                map(TransactionsMessage::WeightChanged(string))
+            })
+            .on_submit(map(TransactionsMessage::ConfirmWeight)),
+            preview,
+            row![
+                button("Add").on_press(map(TransactionsMessage::ConfirmWeight)),
+                button("Cancel").on_press(map(TransactionsMessage::CloseModal)),
+            ]
+            .spacing(10)
+        ]
+        .spacing(10)
+        .width(Length::Units(400))
+        .into()
+    }
+
+    /// Renders the contents of the Challenge 25 modal
+    fn render_age_check(&self) -> Element {
+        let item = match &self.age_check_item {
+            Some(item) => item,
+            None => return "unreachable".into(),
+        };
+
+        column![
+            text("Challenge 25").size(40),
+            text(format!("{} is age restricted.", item.name)),
+            text("If the customer looks under 25, ask for ID showing they are 18 or over."),
+            row![
+                button("ID checked, customer is 18+")
+                    .style(ButtonStyle::Important)
+                    .on_press(map(TransactionsMessage::AgeConfirmed)),
+                button("Refuse sale").on_press(map(TransactionsMessage::AgeRefused)),
+            ]
+            .spacing(10)
+        ]
+        .spacing(10)
+        .width(Length::Units(500))
+        .into()
+    }
+
+    /// Renders the contents of the discount modal
+    fn render_discount(&self, config: &Config) -> Element {
+        let targets = row![
+            render_choice(
+                "Selected item",
+                self.discount_target == DiscountTarget::Item,
+                map(TransactionsMessage::DiscountTargetSelected(
+                    DiscountTarget::Item
+                )),
+            ),
+            render_choice(
+                "Whole basket",
+                self.discount_target == DiscountTarget::Basket,
+                map(TransactionsMessage::DiscountTargetSelected(
+                    DiscountTarget::Basket
+                )),
+            ),
+        ]
+        .spacing(10);
+
+        let discount = self.discount_from_input();
+        let price = self.discount_target_price();
+
+        let preview = match &discount {
+            Some(discount) => text(format!("{} off", format_price(discount.amount(price)))),
+            None => text("Invalid discount"),
+        };
+
+        let mut apply_button = button("Apply").style(ButtonStyle::Important);
+        match discount {
+            // a reason always has to be given
+            Some(discount) if !discount.reason.is_empty() => {
+                let message = map(TransactionsMessage::ApplyDiscount);
+                // big discounts need a supervisor
+                apply_button = apply_button.on_press(
+                    if discount.percentage_of(price) > config.discount_password_threshold {
+                        Message::RequirePermission(Action::ApplyDiscount, Box::new(message))
+                    } else {
+                        message
+                    },
+                );
+            }
+            _ => {}
+        }
+
+        column![
+            text("Discount"),
+            targets,
+            text_input(
+                "Discount, e.g. 10% or £1.50",
+                &self.input_discount,
+                |string| { map(TransactionsMessage::DiscountChanged(string)) }
+            ),
+            text_input("Reason", &self.input_discount_reason, |string| {
+                map(TransactionsMessage::DiscountReasonChanged(string))
+            }),
+            preview,
+            row![
+                apply_button,
+                button("Remove discount").on_press(map(TransactionsMessage::RemoveDiscount)),
+                button("Cancel").on_press(map(TransactionsMessage::CloseModal)),
+            ]
+            .spacing(10)
+        ]
+        .spacing(10)
+        .width(Length::Units(500))
+        .into()
+    }
+
+    /// Parses the discount entered in the discount modal
+    fn discount_from_input(&self) -> Option<Discount> {
+        DiscountKind::parse(&self.input_discount)
+            .ok()
+            .map(|kind| Discount {
+                kind,
+                reason: self.input_discount_reason.trim().to_owned(),
+                approved_percentage: 0,
+            })
+    }
+
+    /// Price of what the discount modal applies to, before its discount
+    fn discount_target_price(&self) -> u32 {
+        match self.discount_target {
+            DiscountTarget::Item => self
+                .current_transaction
+                .items
+                .get(self.selected_index)
+                .map_or(0, |item| item.price_after_promotions()),
+            DiscountTarget::Basket => self.current_transaction.subtotal(),
+        }
+    }
+
+    /// Gets the sale the receipt number in the refund modal refers to
+    fn refund_original<'a>(&self, sales_journal: &'a SalesJournal) -> Option<&'a SaleRecord> {
+        self.input_refund_number
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|number| sales_journal.get(number))
+            .filter(|record| record.kind == SaleKind::Sale)
+    }
+
+    /// Record the refund in the journal and put the returned goods back in stock
+    fn finish_refund(
+        &mut self,
+        item_db: &mut ItemDB,
+        sales_journal: &mut SalesJournal,
+    ) -> Result<(), DataError> {
+        let original = match self.refund_original(sales_journal) {
+            Some(original) => original.clone(),
+            None => {
+                toast::warning("Invalid receipt", "No sale with this receipt number");
+                return Ok(());
+            }
+        };
+        let reason = match self.refund_reason {
+            Some(reason) => reason,
+            None => {
+                toast::warning(
+                    "No reason selected",
+                    "Select why the goods are being returned",
+                );
+                return Ok(());
+            }
+        };
+        if self.refund_quantities.iter().all(|quantity| *quantity == 0) {
+            toast::warning("Nothing to refund", "Select the quantities being returned");
+            return Ok(());
+        }
+
+        let record = sales_journal.record_refund(&original, &self.refund_quantities, reason);
+
+        // items deleted since the sale can't go back into stock
+        let mut result = Ok(());
+        for line in record.lines.iter() {
+            if let Err(error) =
+                item_db.move_stock(line.barcode, -line.stock_quantity(), MovementReason::Refund)
+            {
+                result = Err(error);
+            }
+        }
+
+        self.open_modal = None;
+        result.and(write_refund_receipt(record))
+    }
+
+    /// Adds an item to the transaction, asking for the weight first if it's sold by weight
+    fn add_item(&mut self, item: &Item) {
+        if item.sold_by_weight {
+            self.weighing_item = Some(item.clone());
+            self.input_weight.clear();
+            self.open_modal = Some(ModalType::EnterWeight);
+            return;
+        }
+
+        // add item to transaction
+        if self.current_transaction.add_item(item) {
+            // if the item was in it already, select the existing line
+            self.selected_index = self
+                .current_transaction
+                .items
+                .iter()
+                .position(|t_item| &t_item.item == item && !t_item.is_measured())
+                .unwrap();
+        } else {
+            // if the item was not in it already, select the new item
+            self.selected_index = self.current_transaction.items.len() - 1;
+        }
+        self.check_age(item);
+    }
+
+    /// Stops the cashier to check the customer's age if the item just added is restricted
+    fn check_age(&mut self, item: &Item) {
+        if item.age_restricted && !self.current_transaction.age_verified() {
+            self.age_check_item = Some(item.clone());
+            self.open_modal = Some(ModalType::AgeCheck);
+        }
+    }
+
+    /// Adds the item for a scanned or typed in barcode
+    fn add_code(&mut self, code: &str, item_db: &ItemDB) {
+        // check normal barcodes first, in case one happens to look like a variable measure one
+        if let Some(item) = code.parse().ok().and_then(|code| item_db.get_item(code)) {
+            self.add_item(item);
+            return;
+        }
+
+        match parse_variable_measure(code) {
+            Some(barcode) => match item_db.get_item(barcode.plu) {
+                Some(item) => {
+                    match barcode.value {
+                        EmbeddedValue::Price(price) => {
+                            self.current_transaction.add_priced_item(item, price)
+                        }
+                        EmbeddedValue::Weight(weight) => {
+                            self.current_transaction.add_weighed_item(item, weight)
+                        }
+                    }
+                    self.selected_index = self.current_transaction.items.len() - 1;
+                    self.check_age(item);
+                }
+                None => toast::warning("Invalid item", "Item with PLU couldn't be found"),
+            },
+            None if code.parse::<u64>().is_ok() => {
+                toast::warning("Invalid item", "Item with barcode couldn't be found")
+            }
+            None => toast::warning("Invalid barcode", "Barcode contains invalid characters"),
+        }
+    }
+
+    /// Record the sale in the journal, clear the transaction, print receipt
+    /// The sale is finished even if the stock couldn't be updated, the error is only reported
+    fn finish_transaction(
+        &mut self,
+        item_db: &mut ItemDB,
+        sales_journal: &mut SalesJournal,
+    ) -> Result<(), DataError> {
+        let record =
+            sales_journal.record_sale(&self.current_transaction, std::mem::take(&mut self.tenders));
+
+        println!("{}", self.current_transaction.generate_receipt(record));
+        let result = item_db.update_quantities_from_transaction(&self.current_transaction);
         self.current_transaction = Transaction::default();
-        self.open_modal = None
+        self.selected_index = 0;
+        self.input_tender_amount.clear();
+        self.open_modal = None;
+        result
     }
 
-    pub fn update(&mut self, message: TransactionsMessage, item_db: &mut ItemDB) {
+    pub fn update(
+        &mut self,
+        message: TransactionsMessage,
+        item_db: &mut ItemDB,
+        sales_journal: &mut SalesJournal,
+        config: &Config,
+        security_log: &SecurityLog,
+    ) -> Command<Message> {
+        let mut command = Command::none();
+        let mut check_discounts = false;
         match message {
             TransactionsMessage::EventOccured(event) => {
+                // typing into a modal shouldn't be picked up as a barcode
+                if self.open_modal.is_some() {
+                    return command;
+                }
                 if let Event::Keyboard(event) = event {
                     match event {
                         // if number pressed, append to input
@@ -205,28 +941,8 @@ impl TransactionsView {
                             key_code,
                             modifiers: _,
                         } if key_code == KeyCode::Enter && !self.input_code.is_empty() => {
-                            // get number in input
-                            let code = self.input_code.parse();
-                            if let Ok(code) = code {
-                                // get corresponding item
-                                let item = item_db.get_item(code);
-
-                                // if item is found
-                                if let Some(item) = item {
-                                    // add to transaction
-                                    self.current_transaction.add_item(item);
-                                    // set as selected item
-                                    self.selected_index = self.current_transaction.items.len() - 1;
-                                } else {
-                                    // print error message
-                                    notify("Invalid item", "Item with barcode couldn't be found");
-                                }
-                            } else {
-                                notify("Invalid barcode", "Barcode contains invalid characters");
-                            }
-
-                            // clear input
-                            self.input_code.clear();
+                            let code = std::mem::take(&mut self.input_code);
+                            self.add_code(&code, item_db);
                         }
                         _ => {}
                     }
@@ -235,7 +951,22 @@ impl TransactionsView {
 
             // ====================== FINISH TRANSACTION ========================
             TransactionsMessage::FinishTransaction => {
-                self.open_modal = Some(ModalType::CashOrCard);
+                if self.current_transaction.items.is_empty() {
+                    toast::warning("Nothing to finish", "The transaction is empty");
+                } else if self
+                    .current_transaction
+                    .discounts_need_approval(config.discount_password_threshold)
+                {
+                    command = approve_discounts(true);
+                } else if self.current_transaction.total_price() == 0 {
+                    // nothing to pay
+                    if let Err(error) = self.finish_transaction(item_db, sales_journal) {
+                        command = show_error(error);
+                    }
+                } else {
+                    self.input_tender_amount.clear();
+                    self.open_modal = Some(ModalType::Tender);
+                }
             }
             // modify amount
             TransactionsMessage::ModifySelectedItemQuantity(amount) => {
@@ -248,37 +979,318 @@ impl TransactionsView {
                     // modify quantity
                     item.quantity = new_qty;
                 }
+                // a fixed discount is a bigger share of a smaller line or basket
+                check_discounts = true;
             }
 
             // select item
-            TransactionsMessage::SelectItem(item) => {
-                self.selected_index = self
-                    .current_transaction
-                    .items
+            TransactionsMessage::SelectItem(index) => self.selected_index = index,
+            TransactionsMessage::AddItem(item) => self.add_item(&item),
+            TransactionsMessage::WeightChanged(v) => self.input_weight = v,
+            TransactionsMessage::ConfirmWeight => {
+                match (parse_weight(&self.input_weight), self.weighing_item.take()) {
+                    (Ok(weight), Some(item)) if weight > 0 => {
+                        self.current_transaction.add_weighed_item(&item, weight);
+                        self.selected_index = self.current_transaction.items.len() - 1;
+                        self.open_modal = None;
+                        self.check_age(&item);
+                    }
+                    (_, item) => {
+                        self.weighing_item = item;
+                        toast::warning("Invalid weight", "Enter the weight in kg, e.g. 0.35");
+                    }
+                }
+            }
+            TransactionsMessage::TenderAmountChanged(v) => self.input_tender_amount = v,
+            TransactionsMessage::CashTender => match self.tender_amount() {
+                Some(amount) if amount > 0 => {
+                    let tender = Tender {
+                        method: PaymentMethod::Cash,
+                        amount: amount as i32,
+                        auth_code: None,
+                    };
+                    if let Err(error) = self.add_tender(tender, item_db, sales_journal) {
+                        command = show_error(error);
+                    }
+                }
+                _ => toast::warning("Invalid amount", "Enter the cash given"),
+            },
+            TransactionsMessage::CardTender => match self.tender_amount() {
+                // change can only be given from cash
+                Some(amount) if amount > self.remaining_balance() => toast::warning(
+                    "Invalid amount",
+                    "Card payments can't be more than the remaining balance",
+                ),
+                Some(amount) if amount > 0 => {
+                    self.pending_card_amount = Some(amount);
+                    command = self.request_card_payment(config);
+                }
+                _ => toast::warning("Invalid amount", "Enter the amount to pay by card"),
+            },
+            TransactionsMessage::RemoveTender(index) => {
+                if self.tenders[index].method == PaymentMethod::Cash {
+                    self.tenders.remove(index);
+                }
+            }
+            TransactionsMessage::VoidTenders => {
+                let card_tenders = self
+                    .tenders
                     .iter()
-                    .position(|i| i.item == item)
-                    .unwrap();
+                    .filter(|tender| tender.method == PaymentMethod::Card)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if card_tenders.is_empty() {
+                    command = self.void_tenders(Vec::new(), sales_journal, security_log);
+                } else {
+                    // reversals block on the terminal like payments do
+                    self.reversing_tenders = true;
+                    let provider = config.payment_terminal.provider(config.terminal_timeout);
+                    let (sender, receiver) = oneshot::channel();
+                    thread::spawn(move || {
+                        let results = card_tenders
+                            .iter()
+                            .map(|tender| {
+                                provider.reverse_payment(
+                                    tender.amount as u32,
+                                    tender.auth_code.as_deref().unwrap_or_default(),
+                                )
+                            })
+                            .collect();
+                        let _ = sender.send(results);
+                    });
+                    command = Command::perform(
+                        async move { receiver.await.unwrap_or_default() },
+                        |results| map(TransactionsMessage::TendersReversed(results)),
+                    );
+                }
             }
-            TransactionsMessage::AddItem(item) => {
-                // add item to transaction
-                if !self.current_transaction.add_item(&item) {
-                    // if the item was not in it already, select the new item
-                    self.selected_index = self.current_transaction.items.len() - 1;
+            TransactionsMessage::TendersReversed(results) => {
+                self.reversing_tenders = false;
+                command = self.void_tenders(results, sales_journal, security_log);
+            }
+            TransactionsMessage::TerminalResponded(result) => match result {
+                PaymentResult::Approved { auth_code } => {
+                    if let Some(amount) = self.pending_card_amount.take() {
+                        let tender = Tender {
+                            method: PaymentMethod::Card,
+                            amount: amount as i32,
+                            auth_code: Some(auth_code),
+                        };
+                        if let Err(error) = self.add_tender(tender, item_db, sales_journal) {
+                            command = show_error(error);
+                        }
+                    }
+                }
+                // leave it up to the cashier to retry or take another payment
+                result => self.card_result = Some(result),
+            },
+            TransactionsMessage::RetryCardPayment => command = self.request_card_payment(config),
+            TransactionsMessage::CancelCardPayment => {
+                self.pending_card_amount = None;
+                self.card_result = None;
+                self.open_modal = Some(ModalType::Tender);
+            }
+
+            // ====================== AGE CHECKS ========================
+            TransactionsMessage::AgeConfirmed => {
+                if let Some(item) = self.age_check_item.take() {
+                    self.current_transaction.record_age_check(&item, true);
+                }
+                self.open_modal = None;
+            }
+            TransactionsMessage::AgeRefused => {
+                if let Some(item) = self.age_check_item.take() {
+                    self.current_transaction.record_age_check(&item, false);
+
+                    // take back off the one just added, which is the selected line
+                    let t_item = &mut self.current_transaction.items[self.selected_index];
+                    if t_item.quantity > 1 && !t_item.is_measured() {
+                        t_item.quantity -= 1;
+                    } else {
+                        self.current_transaction.items.remove(self.selected_index);
+                        self.selected_index = 0;
+                    }
+                }
+                self.open_modal = None;
+            }
+
+            // ====================== REFUNDS ========================
+            TransactionsMessage::OpenRefund => {
+                self.input_refund_number.clear();
+                self.refund_quantities.clear();
+                self.refund_reason = None;
+                self.open_modal = Some(ModalType::Refund);
+            }
+            TransactionsMessage::RefundNumberChanged(v) => {
+                self.input_refund_number = v;
+                // start with nothing selected for return
+                self.refund_quantities = self
+                    .refund_original(sales_journal)
+                    .map(|original| vec![0; original.lines.len()])
+                    .unwrap_or_default();
+            }
+            TransactionsMessage::ModifyRefundQuantity(index, amount) => {
+                if let Some(original) = self.refund_original(sales_journal) {
+                    let line = &original.lines[index];
+                    // can't return more than what's left from the sale
+                    let refundable = line
+                        .quantity
+                        .unsigned_abs()
+                        .saturating_sub(sales_journal.refunded_quantity(original, index));
+                    let quantity = &mut self.refund_quantities[index];
+                    *quantity = quantity.saturating_add_signed(amount).min(refundable);
+                }
+            }
+            TransactionsMessage::RefundReasonSelected(reason) => self.refund_reason = Some(reason),
+            TransactionsMessage::ConfirmRefund => {
+                if let Err(error) = self.finish_refund(item_db, sales_journal) {
+                    command = show_error(error);
+                }
+            }
+
+            // ====================== PARKING ========================
+            TransactionsMessage::OpenPark => {
+                if self.current_transaction.items.is_empty() {
+                    toast::warning("Nothing to park", "The transaction is empty");
+                } else {
+                    self.input_park_label.clear();
+                    self.open_modal = Some(ModalType::Park);
+                }
+            }
+            TransactionsMessage::ParkLabelChanged(v) => self.input_park_label = v,
+            TransactionsMessage::ConfirmPark => {
+                let label = if self.input_park_label.trim().is_empty() {
+                    format!("Parked at {}", chrono::Local::now().format("%H:%M"))
+                } else {
+                    self.input_park_label.trim().to_owned()
+                };
+                if let Err(error) = self
+                    .parked
+                    .park(label, std::mem::take(&mut self.current_transaction))
+                {
+                    command = show_error(error);
+                }
+                self.tenders.clear();
+                self.selected_index = 0;
+                self.open_modal = None;
+            }
+            TransactionsMessage::OpenRecall => self.open_modal = Some(ModalType::Recall),
+            TransactionsMessage::RecallTransaction(index) => {
+                if self.current_transaction.items.is_empty() {
+                    match self.parked.recall(index) {
+                        Ok(transaction) => {
+                            self.current_transaction = transaction;
+                            self.selected_index = 0;
+                            self.open_modal = None;
+                        }
+                        Err(error) => command = show_error(error),
+                    }
+                } else {
+                    toast::warning(
+                        "Transaction in progress",
+                        "Finish or park the current transaction first",
+                    );
+                }
+            }
+
+            // ====================== DISCOUNTS ========================
+            TransactionsMessage::OpenDiscount => {
+                if self.current_transaction.items.is_empty() {
+                    toast::warning("Nothing to discount", "The transaction is empty");
+                } else {
+                    self.input_discount.clear();
+                    self.input_discount_reason.clear();
+                    self.discount_target = DiscountTarget::Item;
+                    self.open_modal = Some(ModalType::Discount);
+                }
+            }
+            TransactionsMessage::DiscountChanged(v) => self.input_discount = v,
+            TransactionsMessage::DiscountReasonChanged(v) => self.input_discount_reason = v,
+            TransactionsMessage::DiscountTargetSelected(target) => self.discount_target = target,
+            TransactionsMessage::ApplyDiscount => match self.discount_from_input() {
+                Some(mut discount) if !discount.reason.is_empty() => {
+                    // it only gets here once it has been allowed
+                    discount.approved_percentage =
+                        discount.percentage_of(self.discount_target_price());
+                    match self.discount_target {
+                        DiscountTarget::Item => {
+                            self.current_transaction.items[self.selected_index].discount =
+                                Some(discount)
+                        }
+                        DiscountTarget::Basket => {
+                            self.current_transaction.discount = Some(discount)
+                        }
+                    }
+                    self.open_modal = None;
+                }
+                _ => toast::warning("Invalid discount", "Enter an amount and a reason"),
+            },
+            TransactionsMessage::RemoveDiscount => {
+                match self.discount_target {
+                    DiscountTarget::Item => {
+                        self.current_transaction.items[self.selected_index].discount = None
+                    }
+                    DiscountTarget::Basket => self.current_transaction.discount = None,
                 }
+                self.open_modal = None;
             }
-            TransactionsMessage::CashSelected => self.open_modal = Some(ModalType::CashChange),
-            TransactionsMessage::CardSelected => {
-                self.open_modal = Some(ModalType::CardAcceptOrDecline)
+            TransactionsMessage::ApproveDiscounts { finish } => {
+                self.current_transaction.approve_discounts();
+                if finish {
+                    command = self.update(
+                        TransactionsMessage::FinishTransaction,
+                        item_db,
+                        sales_journal,
+                        config,
+                        security_log,
+                    );
+                }
             }
-            TransactionsMessage::PaymentAccepted => {
-                self.finish_transaction(item_db);
+            TransactionsMessage::CloseModal => {
+                self.tenders.clear();
+                self.open_modal = None;
             }
-            TransactionsMessage::PaymentDeclined => self.open_modal = None,
-            TransactionsMessage::CashGivenChanged(v) => self.input_cash_given = v,
         }
+
+        // keep promotions up to date with whatever changed
+        self.promotions.apply(
+            &mut self.current_transaction,
+            chrono::Local::now().naive_local().date(),
+        );
+
+        if check_discounts
+            && self
+                .current_transaction
+                .discounts_need_approval(config.discount_password_threshold)
+        {
+            command = approve_discounts(false);
+        }
+
+        command
     }
 }
 
+/// Writes the refund receipt as a PDF for the printer
+fn write_refund_receipt(record: &SaleRecord) -> Result<(), DataError> {
+    fs::create_dir_all(RECEIPT_DIRECTORY)
+        .map_err(|error| DataError::io(RECEIPT_DIRECTORY, error))?;
+    let path = format!("{}/refund-{}.pdf", RECEIPT_DIRECTORY, record.number);
+    write_text_pdf(&path, &record.generate_refund_receipt())
+        .map_err(|error| DataError::io(&path, error))?;
+    toast::success("Refund receipt ready to print", &path);
+    Ok(())
+}
+
+/// Asks for a supervisor to allow discounts that now take off more than the threshold
+fn approve_discounts(finish: bool) -> Command<Message> {
+    Command::perform(async {}, move |_| {
+        Message::RequirePermission(
+            Action::ApplyDiscount,
+            Box::new(map(TransactionsMessage::ApproveDiscounts { finish })),
+        )
+    })
+}
+
 /// Utility function
 fn render_quick_item_button<'a>(item: Item) -> Element<'a> {
     button(image(get_handle(&item.image_path)))
@@ -295,7 +1307,7 @@ fn render_transaction(transaction: &Transaction, selected_index: usize) -> Eleme
                 .items
                 .iter()
                 .enumerate()
-                .map(|(index, item)| render_item(item, index == selected_index))
+                .map(|(index, item)| render_item(item, index, index == selected_index))
                 .collect(),
         )
         .width(Length::Fill)
@@ -305,21 +1317,80 @@ fn render_transaction(transaction: &Transaction, selected_index: usize) -> Eleme
     .height(Length::Fill)
     .into()
 }
+/// Renders a button for one of several options, highlighted when selected
+fn render_choice<'a>(label: impl ToString, selected: bool, message: Message) -> Element<'a> {
+    button(text(label))
+        .style(if selected {
+            ButtonStyle::ItemSelected
+        } else {
+            ButtonStyle::Item
+        })
+        .on_press(message)
+        .padding(10)
+        .into()
+}
+
+/// Renders the list of parked transactions to pick from
+fn render_parked(parked: &ParkedTransactions) -> Element {
+    if parked.transactions.is_empty() {
+        return text("No parked transactions").into();
+    }
+
+    column(
+        parked
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(index, parked)| {
+                button(row![
+                    text(&parked.label).width(Length::Fill),
+                    text(parked.parked_at.format("%H:%M")).width(Length::Units(80)),
+                    text(format_price(parked.transaction.total_price())).width(Length::Units(80)),
+                ])
+                .style(ButtonStyle::Item)
+                .on_press(map(TransactionsMessage::RecallTransaction(index)))
+                .padding(10)
+                .width(Length::Fill)
+                .into()
+            })
+            .collect(),
+    )
+    .spacing(10)
+    .into()
+}
 /// Render an element for a `TransactionItem`
-fn render_item(item: &TransactionItem, selected: bool) -> Element {
+fn render_item(item: &TransactionItem, index: usize, selected: bool) -> Element {
     let image = image(get_handle(&item.item.image_path)).height(Length::Fill);
 
-    let name = text(&item.item.name)
-        .size(30)
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .horizontal_alignment(Horizontal::Center)
-        .vertical_alignment(Vertical::Center);
+    let name: Element = if item.promotions.is_empty() {
+        text(&item.item.name)
+            .size(30)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .horizontal_alignment(Horizontal::Center)
+            .vertical_alignment(Vertical::Center)
+            .into()
+    } else {
+        // list the promotion savings under the name
+        item.promotions
+            .iter()
+            .fold(column![text(&item.item.name).size(30)], |column, saving| {
+                column.push(
+                    text(format!("{}: -{}", saving.name, format_price(saving.amount))).size(16),
+                )
+            })
+            .width(Length::Fill)
+            .align_items(Alignment::Center)
+            .into()
+    };
 
     let price = text(format!(
         "{} ({})",
-        format_price(item.item.price * item.quantity),
-        item.quantity,
+        format_price(item.total_price()),
+        match item.unit_weight() {
+            Some(weight) if item.quantity == 1 => format_weight(weight),
+            _ => item.quantity.to_string(),
+        },
     ))
     .size(30)
     .height(Length::Fill)
@@ -333,7 +1404,7 @@ fn render_item(item: &TransactionItem, selected: bool) -> Element {
         } else {
             ButtonStyle::Item
         })
-        .on_press(map(TransactionsMessage::SelectItem(item.item.clone())))
+        .on_press(map(TransactionsMessage::SelectItem(index)))
         .height(Length::Units(80))
         .into()
 }
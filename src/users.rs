@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, fs, io::ErrorKind};
+
+use crate::{
+    data_error::DataError,
+    utils::{hash_password, verify_password, write_atomic},
+    ViewIndex,
+};
+
+/// What a user is trusted with, each role can do everything the ones before it can
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Role {
+    #[default]
+    Cashier,
+    Supervisor,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Cashier, Role::Supervisor, Role::Admin];
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Cashier => "Cashier",
+            Role::Supervisor => "Supervisor",
+            Role::Admin => "Admin",
+        })
+    }
+}
+
+/// Things that need a certain role, on top of opening the view they're in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    DeleteItem,
+    /// Only discounts above the threshold in the config
+    ApplyDiscount,
+    Refund,
+    ChangeSettings,
+    /// Running the Z report
+    CloseShift,
+    /// Writing off or correcting stock, booking in goods is open to anyone in the stock view
+    AdjustStock,
+    /// Taking back payments already made towards a sale, card ones included
+    VoidTenders,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::DeleteItem,
+        Action::ApplyDiscount,
+        Action::Refund,
+        Action::ChangeSettings,
+        Action::CloseShift,
+        Action::AdjustStock,
+        Action::VoidTenders,
+    ];
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::DeleteItem => "delete items",
+            Action::ApplyDiscount => "apply large discounts",
+            Action::Refund => "give refunds",
+            Action::ChangeSettings => "change settings",
+            Action::CloseShift => "close the shift",
+            Action::AdjustStock => "adjust stock",
+            Action::VoidTenders => "void payments",
+        })
+    }
+}
+
+/// The lowest role allowed to open each view and do each action
+/// Anything missing from a saved table uses the default, so new views aren't left open
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    pub views: BTreeMap<ViewIndex, Role>,
+    pub actions: BTreeMap<Action, Role>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            views: BTreeMap::from([
+                (ViewIndex::Inventory, Role::Supervisor),
+                (ViewIndex::ItemCreation, Role::Supervisor),
+                (ViewIndex::Suppliers, Role::Admin),
+                (ViewIndex::Audit, Role::Supervisor),
+                (ViewIndex::Settings, Role::Admin),
+            ]),
+            actions: BTreeMap::from([
+                (Action::DeleteItem, Role::Supervisor),
+                (Action::ApplyDiscount, Role::Supervisor),
+                (Action::Refund, Role::Supervisor),
+                (Action::ChangeSettings, Role::Admin),
+                (Action::CloseShift, Role::Supervisor),
+                (Action::AdjustStock, Role::Supervisor),
+                (Action::VoidTenders, Role::Supervisor),
+            ]),
+        }
+    }
+}
+
+impl Permissions {
+    /// The role needed to open a view
+    pub fn view_role(&self, view: ViewIndex) -> Role {
+        match self.views.get(&view) {
+            Some(role) => *role,
+            None => Self::default()
+                .views
+                .get(&view)
+                .copied()
+                .unwrap_or(Role::Cashier),
+        }
+    }
+
+    /// The role needed to do an action
+    pub fn action_role(&self, action: Action) -> Role {
+        match self.actions.get(&action) {
+            Some(role) => *role,
+            None => Self::default()
+                .actions
+                .get(&action)
+                .copied()
+                .unwrap_or(Role::Cashier),
+        }
+    }
+}
+
+/// Someone who can log in to the till
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    /// Argon2 hash of the PIN
+    pub pin: String,
+    pub role: Role,
+}
+
+/// All user accounts and what each role can do
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Users {
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub permissions: Permissions,
+    /// Whether anything changed since the last save
+    #[serde(skip)]
+    dirty: bool,
+    /// Whether the old shared password was imported, it's deleted once the admin is saved
+    #[serde(skip)]
+    imported_legacy: bool,
+}
+
+impl Users {
+    /// Loads users from a YAML file given the path, starting with nobody if it doesn't exist yet
+    pub fn load_yaml(path: &str) -> Result<Self, DataError> {
+        match fs::read_to_string(path) {
+            Ok(string) => {
+                serde_yaml::from_str::<Self>(&string).map_err(|error| DataError::parse(path, error))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(DataError::io(path, error)),
+        }
+    }
+
+    /// The first time, an admin is made with the PIN set to the old shared password if there is one,
+    /// otherwise there are no users until `needs_setup` is dealt with
+    pub fn import_legacy_password(&mut self) {
+        let legacy_password = fs::read_to_string(".password").unwrap_or_default();
+        let legacy_password = legacy_password.trim_end_matches('\n');
+        if self.users.is_empty() && !legacy_password.is_empty() {
+            self.users.push(User {
+                name: "admin".to_owned(),
+                pin: hash_password(&unshift(legacy_password)),
+                role: Role::Admin,
+            });
+            self.dirty = true;
+            self.imported_legacy = true;
+        }
+    }
+
+    /// Whether there's nobody to log in as, so the first admin has to be made
+    pub fn needs_setup(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Finds the user with the name and PIN
+    pub fn authenticate(&self, name: &str, pin: &str) -> Option<User> {
+        match self.position(name) {
+            Some(index) => {
+                verify_password(pin, &self.users[index].pin).then(|| self.users[index].clone())
+            }
+            None => {
+                // take as long as checking a real user, so names can't be guessed from timing
+                hash_password(pin);
+                None
+            }
+        }
+    }
+
+    /// Adds a user, replacing the PIN and role if the name is already taken
+    /// Refuses to demote the last admin so nobody gets locked out
+    pub fn set_user(&mut self, name: &str, pin: &str, role: Role) -> bool {
+        let user = User {
+            name: name.trim().to_owned(),
+            pin: hash_password(pin),
+            role,
+        };
+        match self.position(name) {
+            Some(index) if role != Role::Admin && self.is_last_admin(index) => return false,
+            Some(index) => self.users[index] = user,
+            None => self.users.push(user),
+        }
+        self.dirty = true;
+        true
+    }
+
+    /// Removes a user, refusing to remove the last admin so nobody gets locked out
+    pub fn remove_user(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Some(index) if !self.is_last_admin(index) => {
+                self.users.remove(index);
+                self.dirty = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Where the user with a name is, names are matched the same way as when logging in
+    fn position(&self, name: &str) -> Option<usize> {
+        self.users
+            .iter()
+            .position(|user| user.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Whether the user at an index is the only admin
+    fn is_last_admin(&self, index: usize) -> bool {
+        self.users[index].role == Role::Admin
+            && self
+                .users
+                .iter()
+                .filter(|user| user.role == Role::Admin)
+                .count()
+                == 1
+    }
+
+    /// Whether anything changed since the last save
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Counts the users as changed, so the next save writes them
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Forgets the changes once they've been saved
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+        if self.imported_legacy {
+            // the old file is as good as plain text, so don't leave it lying around
+            let _ = fs::remove_file(".password");
+            self.imported_legacy = false;
+        }
+    }
+
+    /// Saves itself to a YAML file
+    pub fn save_yaml(&self, path: &str) -> Result<(), DataError> {
+        let string = serde_yaml::to_string::<Self>(self)
+            .map_err(|error| DataError::Serialize(error.to_string()))?;
+        write_atomic(path, &string).map_err(|error| DataError::io(path, error))
+    }
+}
+
+/// Whether a PIN is long enough and only digits
+pub fn valid_pin(pin: &str) -> bool {
+    pin.len() >= 4 && pin.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Undoes the old password file's scheme, which shifted every character up by 3
+fn unshift(saved: &str) -> String {
+    saved
+        .chars()
+        .map(|character| (character as u8).wrapping_sub(3) as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_admin_stays_admin() {
+        let mut users = Users::default();
+        assert!(users.set_user("Anna", "1234", Role::Admin));
+        assert!(!users.set_user("anna", "1234", Role::Supervisor));
+        assert!(!users.remove_user("ANNA "));
+        assert_eq!(users.users[0].role, Role::Admin);
+
+        // once there's another admin the first one can be demoted
+        assert!(users.set_user("Ben", "5678", Role::Admin));
+        assert!(users.set_user("anna", "1234", Role::Cashier));
+        assert_eq!(users.users.len(), 2);
+        assert!(!users.remove_user("ben"));
+        assert!(users.remove_user("anna"));
+    }
+}
@@ -1,8 +1,13 @@
 use std::{
-    fs::{read_to_string, write},
+    fs::{self, File},
+    io::{self, Write},
     path::Path,
 };
 
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
 use iced::widget::image::Handle;
 use notify_rust::Notification;
 
@@ -22,15 +27,30 @@ pub fn get_handle(image_path: &Option<String>) -> Handle {
 
 /// Helper function to convert pence to price string
 pub fn format_price(input: u32) -> String {
+    format_large_price(input.into())
+}
+
+/// Formats totals that can go past what a u32 holds, like stock valuations
+pub fn format_large_price(input: u64) -> String {
     let pounds = input / 100;
     let pence = input - (pounds * 100);
 
-    format!("£{0}.{1:0<2}", pounds, pence)
+    format!("£{0}.{1:02}", pounds, pence)
+}
+
+/// Formats a price that can be negative
+pub fn format_signed_price(amount: i32) -> String {
+    if amount < 0 {
+        format!("-{}", format_price(amount.unsigned_abs()))
+    } else {
+        format_price(amount as u32)
+    }
 }
 
 /// Function to parse a string to a price
 /// Parsable formats:
 /// 10
+/// 10.5
 /// 10.00
 /// £10.00
 pub fn parse_price(input: &str) -> Result<u32, ()> {
@@ -46,8 +66,12 @@ pub fn parse_price(input: &str) -> Result<u32, ()> {
     let split = input.split_once('.');
     match split {
         Some((pounds, pence)) => {
+            // one digit of pence is tens of pence, more than two aren't pence at all
+            if pence.is_empty() || pence.len() > 2 || !pence.chars().all(|c| c.is_ascii_digit()) {
+                return Err(());
+            }
             if let Ok(pounds_parsed) = pounds.parse::<u32>() {
-                if let Ok(pence_parsed) = pence.parse::<u32>() {
+                if let Ok(pence_parsed) = format!("{:0<2}", pence).parse::<u32>() {
                     return Ok(pounds_parsed * 100 + pence_parsed);
                 } else {
                     Err(())
@@ -60,35 +84,143 @@ pub fn parse_price(input: &str) -> Result<u32, ()> {
     }
 }
 
+/// Helper function to convert grams to a weight string in kilograms
+pub fn format_weight(grams: u32) -> String {
+    format!("{}.{:03}kg", grams / 1000, grams % 1000)
+}
+
+/// Helper function to format stock, which is in grams for items sold by weight
+pub fn format_stock(quantity: u32, sold_by_weight: bool) -> String {
+    if sold_by_weight {
+        format_weight(quantity)
+    } else {
+        quantity.to_string()
+    }
+}
+
+/// Function to parse a weight in kilograms to grams
+/// Parsable formats:
+/// 1
+/// 0.35
+/// 0.350kg
+pub fn parse_weight(input: &str) -> Result<u32, ()> {
+    let input = input.trim().trim_end_matches("kg").trim();
+    let (kilograms, fraction) = input.split_once('.').unwrap_or((input, ""));
+
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(());
+    }
+
+    let kilograms = if kilograms.is_empty() {
+        0
+    } else {
+        kilograms.parse::<u32>().map_err(|_| ())?
+    };
+    // pad to grams, e.g. .35 is 350g
+    let grams = format!("{:0<3}", fraction).parse::<u32>().map_err(|_| ())?;
+
+    Ok(kilograms * 1000 + grams)
+}
+
+/// Writes a file by writing a temporary file next to it and renaming that into place,
+/// so a crash part way through never leaves a half written file
+pub fn write_atomic(path: &str, contents: &str) -> io::Result<()> {
+    let temp_path = format!("{path}.tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    // make sure it's on disk before it replaces the old one
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    sync_parent(path)
+}
+
+/// Makes sure a rename into a directory is on disk, or a crash could still bring back the old file
+/// Only needed, and only possible, where directories can be opened like files
+#[cfg(unix)]
+fn sync_parent(path: &str) -> io::Result<()> {
+    let parent = match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Keeps the last versions of a file as `path.1` (newest) up to `path.count` (oldest)
+/// Call before overwriting the file
+pub fn rotate_backups(path: &str, count: usize) -> io::Result<()> {
+    if count == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+
+    for index in (1..count).rev() {
+        let from = format!("{path}.{index}");
+        if Path::new(&from).exists() {
+            fs::rename(&from, format!("{path}.{}", index + 1))?;
+        }
+    }
+    fs::copy(path, format!("{path}.1"))?;
+    Ok(())
+}
+
 /// Helper function to notify the user using os native notifications
+/// Only used alongside toasts if enabled in the config
+/// Falls back to printing it when there's no notification server, e.g. no D-Bus session
 pub fn notify(title: &str, description: &str) {
-    Notification::new()
+    if let Err(error) = Notification::new()
         .summary(title)
         .body(description)
         // .icon("idk")
         .show()
-        .unwrap();
+    {
+        eprintln!(
+            "{}: {} (couldn't show notification: {})",
+            title, description, error
+        );
+    }
 }
 
-const ENCRYPTION_OFFSET: i8 = 3;
-
-pub fn encrypt(input: &str) -> String {
-    input
-        .chars()
-        .map(|character| {
-            let mut ascii = character as u8;
-            ascii = ascii.overflowing_add_signed(ENCRYPTION_OFFSET).0;
-            ascii as char
-        })
-        .collect::<String>()
+/// Hashes a password with Argon2 and a random salt, giving a PHC string to store
+pub fn hash_password(input: &str) -> String {
+    Argon2::default()
+        .hash_password(input.as_bytes(), &SaltString::generate(&mut OsRng))
+        .expect("Couldn't hash password")
+        .to_string()
 }
 
-/// Save a new password to the file
-pub fn set_password(input: &str) {
-    write(".password", encrypt(input)).unwrap();
+/// Checks a password against a PHC string from `hash_password`
+/// Argon2 compares in constant time, so a wrong guess takes as long as a right one
+pub fn verify_password(input: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(input.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
 }
 
-/// Get the password, encrypted for safety
-pub fn get_encrypted_password() -> String {
-    read_to_string(".password").unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_price_pads_pence() {
+        assert_eq!(parse_price("5"), Ok(500));
+        assert_eq!(parse_price("5.5"), Ok(550));
+        assert_eq!(parse_price("5.05"), Ok(505));
+        assert_eq!(parse_price("£12.34"), Ok(1234));
+        assert_eq!(parse_price("5.005"), Err(()));
+        assert_eq!(parse_price("5."), Err(()));
+        assert_eq!(parse_price("5.+5"), Err(()));
+    }
+
+    #[test]
+    fn large_price_not_truncated() {
+        assert_eq!(format_price(1234), "£12.34");
+        assert_eq!(format_large_price(5_000_000_005), "£50000000.05");
+    }
 }
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Local};
+use std::{collections::BTreeMap, fs};
+
+use crate::{data_error::DataError, item_db::ItemDB, utils::write_atomic};
+
+/// What the stock in one category is worth
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CategoryValue {
+    pub category: String,
+    /// How many items are in it, including any out of stock
+    pub items: usize,
+    /// In pence
+    pub at_cost: u64,
+    /// In pence, VAT included
+    pub at_retail: u64,
+}
+
+/// What the stock is worth right now, by category
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub time: DateTime<Local>,
+    /// In alphabetical order
+    pub categories: Vec<CategoryValue>,
+    /// Items in stock without a cost price, which count as nothing at cost
+    pub missing_cost: usize,
+}
+
+impl Valuation {
+    pub fn new(item_db: &ItemDB) -> Self {
+        let mut categories: BTreeMap<&str, CategoryValue> = BTreeMap::new();
+        let mut missing_cost = 0;
+        for item in item_db.items() {
+            let category = categories
+                .entry(item.category_name())
+                .or_insert_with(|| CategoryValue {
+                    category: item.category_name().to_owned(),
+                    ..Default::default()
+                });
+            category.items += 1;
+            category.at_cost += item.stock_value(item.cost_price);
+            category.at_retail += item.stock_value(item.price);
+            if item.cost_price == 0 && item.amount_in_stock > 0 {
+                missing_cost += 1;
+            }
+        }
+
+        Self {
+            time: Local::now(),
+            categories: categories.into_values().collect(),
+            missing_cost,
+        }
+    }
+
+    /// The whole stock at cost, in pence
+    pub fn total_at_cost(&self) -> u64 {
+        self.categories.iter().map(|category| category.at_cost).sum()
+    }
+
+    /// The whole stock at retail, in pence
+    pub fn total_at_retail(&self) -> u64 {
+        self.categories
+            .iter()
+            .map(|category| category.at_retail)
+            .sum()
+    }
+
+    /// The categories as CSV with a header row and a total row, values are in pounds
+    pub fn generate_csv(&self) -> String {
+        let mut string = String::from("category,items,value_at_cost,value_at_retail\n");
+        for category in &self.categories {
+            let name = if category.category.contains([',', '"']) {
+                format!("\"{}\"", category.category.replace('"', "\"\""))
+            } else {
+                category.category.clone()
+            };
+            string.push_str(&format!(
+                "{},{},{},{}\n",
+                name,
+                category.items,
+                pounds(category.at_cost),
+                pounds(category.at_retail)
+            ));
+        }
+        string.push_str(&format!(
+            "Total,{},{},{}\n",
+            self.categories
+                .iter()
+                .map(|category| category.items)
+                .sum::<usize>(),
+            pounds(self.total_at_cost()),
+            pounds(self.total_at_retail())
+        ));
+        string
+    }
+
+    /// Writes the CSV into a directory, returning its path
+    pub fn export(&self, directory: &str) -> Result<String, DataError> {
+        fs::create_dir_all(directory).map_err(|error| DataError::io(directory, error))?;
+        let path = format!(
+            "{}/valuation-{}.csv",
+            directory,
+            self.time.format("%Y-%m-%d-%H%M")
+        );
+        write_atomic(&path, &self.generate_csv()).map_err(|error| DataError::io(&path, error))?;
+        Ok(path)
+    }
+}
+
+/// Pence as pounds with two decimals and no symbol, for spreadsheets
+fn pounds(pence: u64) -> String {
+    format!("{}.{:02}", pence / 100, pence % 100)
+}